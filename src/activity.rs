@@ -0,0 +1,129 @@
+use crate::bitmap::Bitmap;
+use crate::board::{Board, QUEEN_DELTAS};
+use crate::eval::{Score, MOBILE_KINDS};
+use crate::piece::{Color, Kind};
+
+impl Board {
+    /// Mobility and king safety from white's point of view. Each piece's attacks are
+    /// generated once, counting both the squares it can go to and the enemy king zone hits.
+    pub fn activity(&self) -> Score {
+        let colors = [Color::White, Color::Black];
+        let pawn_attacks = colors.map(|color| self.pawn_attack_map(color));
+        let occupied = colors.map(|color| self.occupied_by(color));
+        let zones = colors.map(|color| self.king_zone(color));
+        // Attack units and number of attackers on the king zone of each side
+        let mut attacks = [(0, 0); 2];
+        let mut total = Score::default();
+        for (index, piece) in self.pieces() {
+            let Some(slot) = MOBILE_KINDS.iter().position(|&kind| kind == piece.kind) else {
+                continue;
+            };
+            let us = piece.color.index();
+            let them = piece.color.opponent().index();
+            let reach = self.attacks_from(index);
+            let blocked = reach & (occupied[us] | pawn_attacks[them]);
+            let squares = (reach.count() - blocked.count()) as i32;
+            let weight = self.weights.mobility[slot];
+            let mut score = Score::new(weight.mg * squares, weight.eg * squares);
+            if !(reach & zones[them]).is_empty() {
+                attacks[them].0 += self.weights.king.attack[slot];
+                attacks[them].1 += 1;
+            }
+            if piece.color == Color::Black {
+                score = -score;
+            }
+            total += score;
+        }
+        for color in colors {
+            let king = &self.weights.king;
+            let (units, attackers) = attacks[color.index()];
+            let share = king.attackers[(attackers as usize).min(king.attackers.len() - 1)];
+            let safety = self.king_shelter(color) - units * share / 100;
+            match color {
+                Color::White => total.mg += safety,
+                Color::Black => total.mg -= safety,
+            }
+        }
+        total
+    }
+
+    /// Pawn shield, pawn storm and open files around the king of `color`.
+    fn king_shelter(&self, color: Color) -> i32 {
+        let Some(king) = self.king_square(color) else {
+            return 0;
+        };
+        let weights = &self.weights.king;
+        let (king_file, _) = self.index_to_file_rank(king);
+        let king_rank = self.relative_rank(king, color);
+        let mut score = 0;
+        let first = king_file.saturating_sub(1);
+        let last = (king_file + 1).min(self.files - 1);
+        for file in first..=last {
+            let (mut own, mut enemy) = (false, false);
+            for rank in 0..self.ranks {
+                let index = self.file_rank_to_index(file, rank);
+                let Some(pawn) = self
+                    .piece_at(index)
+                    .filter(|piece| piece.kind == Kind::Pawn)
+                else {
+                    continue;
+                };
+                let ahead = self.relative_rank(index, color) as i32 - king_rank as i32;
+                if pawn.color == color {
+                    own = true;
+                    if ahead >= 1 && ahead as usize <= weights.shield.len() {
+                        score += weights.shield[ahead as usize - 1];
+                    }
+                } else {
+                    enemy = true;
+                    if ahead >= 1 && ahead as usize <= weights.storm.len() {
+                        score += weights.storm[ahead as usize - 1];
+                    }
+                }
+            }
+            if !own {
+                score += weights.semi_open;
+                if !enemy {
+                    score += weights.open;
+                }
+            }
+        }
+        score
+    }
+
+    /// Squares around the king of `color` and the three two ranks in front of it.
+    fn king_zone(&self, color: Color) -> Bitmap {
+        let mut zone = Bitmap::new();
+        let Some(king) = self.king_square(color) else {
+            return zone;
+        };
+        zone.set(king);
+        for delta in QUEEN_DELTAS {
+            if let Some(index) = self.offset(king, delta) {
+                zone.set(index);
+            }
+        }
+        let (_, forward) = Board::pawn_forward(color);
+        for file in -1..=1 {
+            if let Some(index) = self.offset(king, (file, 2 * forward)) {
+                zone.set(index);
+            }
+        }
+        zone
+    }
+
+    fn pawn_attack_map(&self, color: Color) -> Bitmap {
+        self.pieces()
+            .filter(|(_, piece)| piece.kind == Kind::Pawn && piece.color == color)
+            .fold(Bitmap::new(), |map, (index, _)| {
+                map | self.attacks_from(index)
+            })
+    }
+
+    fn occupied_by(&self, color: Color) -> Bitmap {
+        self.pieces()
+            .filter(|(_, piece)| piece.color == color)
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
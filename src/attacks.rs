@@ -0,0 +1,132 @@
+use crate::bitmap::Bitmap;
+use crate::board::{Board, BISHOP_DELTAS, KNIGHT_DELTAS, QUEEN_DELTAS, ROOK_DELTAS};
+use crate::piece::{Color, Kind};
+
+/// Pieces of each side attacking or defending a square, given by the squares they stand on.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Control {
+    pub white: Bitmap,
+    pub black: Bitmap,
+}
+
+impl Control {
+    pub fn of(&self, color: Color) -> Bitmap {
+        match color {
+            Color::White => self.white,
+            Color::Black => self.black,
+        }
+    }
+
+    /// Both sides have a piece aiming at the square.
+    pub fn is_contested(&self) -> bool {
+        !self.white.is_empty() && !self.black.is_empty()
+    }
+}
+
+impl Board {
+    /// Squares attacked by the piece on `index`, whoever stands on them.
+    pub fn attacks_from(&self, index: u8) -> Bitmap {
+        let Some(piece) = self.piece_at(index) else {
+            return Bitmap::new();
+        };
+        match piece.kind {
+            Kind::Pawn => self.step_attacks(index, &Board::pawn_attacks(piece.color)),
+            Kind::Knight => self.step_attacks(index, &KNIGHT_DELTAS),
+            Kind::King => self.step_attacks(index, &QUEEN_DELTAS),
+            Kind::Bishop => self.ray_attacks(index, &BISHOP_DELTAS),
+            Kind::Rook => self.ray_attacks(index, &ROOK_DELTAS),
+            Kind::Queen => self.ray_attacks(index, &QUEEN_DELTAS),
+        }
+    }
+
+    /// Squares attacked by all the pieces of a side.
+    pub fn attack_map(&self, color: Color) -> Bitmap {
+        self.pieces()
+            .filter(|(_, piece)| piece.color == color)
+            .fold(Bitmap::new(), |map, (index, _)| {
+                map | self.attacks_from(index)
+            })
+    }
+
+    /// Pieces of a side attacking or defending `index`.
+    pub fn attackers(&self, index: u8, color: Color) -> Bitmap {
+        let mut attackers = Bitmap::new();
+        let mut add = |delta: (i16, i16), kinds: &[Kind], slide: bool| {
+            let mut square = index;
+            while let Some(next) = self.offset(square, delta) {
+                square = next;
+                if let Some(piece) = self.piece_at(square) {
+                    if piece.color == color && kinds.contains(&piece.kind) {
+                        attackers.set(square);
+                    }
+                    break;
+                }
+                if !slide {
+                    break;
+                }
+            }
+        };
+        // Pawns are looked for where they would stand to attack `index`
+        for (df, dr) in Board::pawn_attacks(color) {
+            add((-df, -dr), &[Kind::Pawn], false);
+        }
+        for delta in KNIGHT_DELTAS {
+            add(delta, &[Kind::Knight], false);
+        }
+        for delta in QUEEN_DELTAS {
+            add(delta, &[Kind::King], false);
+        }
+        for delta in BISHOP_DELTAS {
+            add(delta, &[Kind::Bishop, Kind::Queen], true);
+        }
+        for delta in ROOK_DELTAS {
+            add(delta, &[Kind::Rook, Kind::Queen], true);
+        }
+        attackers
+    }
+
+    /// Who attacks and defends a square.
+    pub fn control(&self, index: u8) -> Control {
+        Control {
+            white: self.attackers(index, Color::White),
+            black: self.attackers(index, Color::Black),
+        }
+    }
+
+    pub fn is_attacked(&self, index: u8, by: Color) -> bool {
+        !self.attackers(index, by).is_empty()
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<u8> {
+        self.pieces()
+            .find(|(_, piece)| piece.kind == Kind::King && piece.color == color)
+            .map(|(index, _)| index)
+    }
+
+    pub fn in_check(&self, color: Color) -> bool {
+        self.king_square(color)
+            .is_some_and(|king| self.is_attacked(king, color.opponent()))
+    }
+
+    fn step_attacks(&self, index: u8, deltas: &[(i16, i16)]) -> Bitmap {
+        deltas
+            .iter()
+            .filter_map(|&delta| self.offset(index, delta))
+            .collect()
+    }
+
+    fn ray_attacks(&self, index: u8, deltas: &[(i16, i16)]) -> Bitmap {
+        let mut attacks = Bitmap::new();
+        for &delta in deltas {
+            let mut square = index;
+            while let Some(next) = self.offset(square, delta) {
+                square = next;
+                attacks.set(square);
+                if self.piece_at(square).is_some() {
+                    break;
+                }
+            }
+        }
+        attacks
+    }
+}
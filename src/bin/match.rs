@@ -0,0 +1,688 @@
+//! Plays two engine configurations against each other, headless, until a sequential
+//! probability ratio test tells whether the first is stronger than the second, then reports
+//! the Elo difference. Each opening of the suite is played twice with colors swapped, and
+//! the games are saved as PGN.
+//!
+//! Usage: `match [settings]`, the settings file defaulting to `match.toml`, which documents
+//! the engines, limits, test bounds and adjudication rules.
+
+use chess::board::Board;
+use chess::eval::Weights;
+use chess::moves::Move;
+use chess::nnue::Network;
+use chess::piece::{Color, Kind};
+use chess::search::{Clock, Limits, Search, SearchParams};
+use config::Config;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::process::exit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Start of standard chess, left out of the PGN tags.
+const STANDARD_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+/// Quantile of the normal distribution for the 95% error bars.
+const Z_95: f64 = 1.96;
+
+/// One side of the match, everything but the search limits.
+struct Engine {
+    name: String,
+    weights: Arc<Weights>,
+    network: Option<Arc<Network>>,
+    params: SearchParams,
+    hash: usize,
+    threads: usize,
+}
+
+impl Engine {
+    fn read(config: &Config, section: &str) -> Engine {
+        let get = |key: &str| config.get::<String>(&format!("{section}.{key}"));
+        let name = get("name").unwrap_or_else(|_| section.to_string());
+        let path = get("weights").unwrap_or_else(|_| panic!("Couldn't find {section}.weights."));
+        let weights = Weights::load(&path).unwrap_or_else(|error| panic!("{error}"));
+        let network = get("nnue")
+            .ok()
+            .map(|path| Arc::new(Network::load(&path).unwrap_or_else(|error| panic!("{error}"))));
+        let mut params = SearchParams::default();
+        if let Ok(table) = config.get_table(&format!("{section}.search")) {
+            for (name, value) in table {
+                let value = value
+                    .into_int()
+                    .unwrap_or_else(|_| panic!("Search parameter {name} isn't a number."));
+                params
+                    .set(&name, value)
+                    .unwrap_or_else(|error| panic!("{error}"));
+            }
+        }
+        Engine {
+            name,
+            weights: Arc::new(weights),
+            network,
+            params,
+            hash: config.get(&format!("{section}.hash")).unwrap_or(16),
+            threads: config.get(&format!("{section}.threads")).unwrap_or(1),
+        }
+    }
+
+    fn search(&self) -> Search {
+        let mut search = Search::new(self.hash);
+        search.params = self.params;
+        search.threads = self.threads;
+        search
+    }
+
+    /// `board` set up to be evaluated the way this engine does.
+    fn position(&self, board: &Board) -> Board {
+        let mut board = board.clone();
+        board.set_weights(self.weights.clone());
+        board
+            .set_network(self.network.clone())
+            .unwrap_or_else(|error| panic!("{error}"));
+        board
+    }
+}
+
+/// Starting position of a pair of games: moves in coordinate notation played from a FEN.
+struct Opening {
+    fen: String,
+    moves: Vec<Move>,
+}
+
+/// How games are played and when they are cut short. Scores are in centipawns, counted in
+/// moves of each side.
+struct Rules {
+    files: u8,
+    ranks: u8,
+    limits: Limits,
+    /// Full moves after which the game is a draw, 0 for no limit.
+    max_moves: u32,
+    /// A side loses when both engines agree it is this far behind for `resign_moves`.
+    resign_score: i32,
+    resign_moves: usize,
+    /// The game is a draw when both engines see it this even for `draw_moves`, once past
+    /// move `draw_after`.
+    draw_score: i32,
+    draw_moves: usize,
+    draw_after: u32,
+}
+
+/// Sequential probability ratio test of the first engine being `elo1` stronger than the
+/// second rather than `elo0`, with error rates `alpha` and `beta`.
+struct Sprt {
+    elo0: f64,
+    elo1: f64,
+    alpha: f64,
+    beta: f64,
+}
+
+impl Sprt {
+    /// Log-likelihood ratios at which H0 and H1 are accepted.
+    fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Outcome {
+    White,
+    Black,
+    Draw,
+}
+
+impl Outcome {
+    fn pgn(self) -> &'static str {
+        match self {
+            Outcome::White => "1-0",
+            Outcome::Black => "0-1",
+            Outcome::Draw => "1/2-1/2",
+        }
+    }
+}
+
+struct Game {
+    number: usize,
+    /// The first engine had white.
+    first_white: bool,
+    fen: String,
+    /// Turn and move number of the start, for the move numbers of the PGN.
+    start: (Color, u32),
+    moves: Vec<String>,
+    outcome: Outcome,
+    reason: String,
+    /// PGN termination: normal, adjudication or time forfeit.
+    termination: &'static str,
+}
+
+/// Wins, draws and losses of the first engine.
+#[derive(Default)]
+struct Tally {
+    wins: u32,
+    draws: u32,
+    losses: u32,
+}
+
+impl Tally {
+    fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Mean score and its variance per game. The variance counts half a game more of each
+    /// result, so that it isn't zero after a few games with the same result.
+    fn score(&self) -> (f64, f64) {
+        let (wins, draws, losses) = (self.wins as f64, self.draws as f64, self.losses as f64);
+        let games = self.games().max(1) as f64;
+        let score = (wins + draws / 2.0) / games;
+        let (wins, draws) = (wins + 0.5, draws + 0.5);
+        let total = wins + draws + losses + 0.5;
+        let mean = (wins + draws / 2.0) / total;
+        (score, (wins + draws / 4.0) / total - mean * mean)
+    }
+
+    /// Elo difference and the half width of its 95% interval.
+    fn elo(&self) -> (f64, f64) {
+        let (score, variance) = self.score();
+        let margin = Z_95 * (variance / self.games().max(1) as f64).sqrt();
+        let low = elo(score - margin);
+        let high = elo(score + margin);
+        (elo(score), (high - low) / 2.0)
+    }
+
+    /// Log-likelihood ratio of the test, normal approximation of the trinomial model.
+    fn llr(&self, sprt: &Sprt) -> f64 {
+        let (score, variance) = self.score();
+        let (score0, score1) = (expected_score(sprt.elo0), expected_score(sprt.elo1));
+        self.games() as f64 * (score1 - score0) * (2.0 * score - score0 - score1) / (2.0 * variance)
+    }
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "match.toml".to_string());
+    let config = Config::builder()
+        .add_source(config::File::with_name(&path))
+        .build()
+        .unwrap_or_else(|error| panic!("{error}"));
+    let get = |key: &str| config.get::<f64>(&format!("match.{key}")).ok();
+    let millis = |key: &str| get(key).map(|millis| Duration::from_millis(millis as u64));
+
+    let engines = [
+        Engine::read(&config, "first"),
+        Engine::read(&config, "second"),
+    ];
+    let rules = Rules {
+        files: get("files").map_or(8, |files| files as u8),
+        ranks: get("ranks").map_or(8, |ranks| ranks as u8),
+        limits: Limits {
+            depth: get("depth").map(|depth| depth as u32),
+            nodes: get("nodes").map(|nodes| nodes as u64),
+            movetime: millis("movetime"),
+            clock: millis("clock").map(|remaining| Clock {
+                remaining,
+                increment: millis("increment").unwrap_or_default(),
+            }),
+        },
+        max_moves: get("max_moves").map_or(0, |moves| moves as u32),
+        resign_score: get("resign_score").map_or(0, |score| score as i32),
+        resign_moves: get("resign_moves").map_or(0, |moves| moves as usize),
+        draw_score: get("draw_score").map_or(0, |score| score as i32),
+        draw_moves: get("draw_moves").map_or(0, |moves| moves as usize),
+        draw_after: get("draw_after").map_or(0, |moves| moves as u32),
+    };
+    let sprt = Sprt {
+        elo0: get("elo0").unwrap_or(0.0),
+        elo1: get("elo1").unwrap_or(5.0),
+        alpha: get("alpha").unwrap_or(0.05),
+        beta: get("beta").unwrap_or(0.05),
+    };
+    let games = get("games").map_or(usize::MAX, |games| games as usize);
+    let concurrency = get("concurrency")
+        .map_or(1, |threads| threads as usize)
+        .max(1);
+    let fen = config
+        .get::<String>("match.fen")
+        .unwrap_or_else(|_| STANDARD_FEN.to_string());
+    let suite: String = config
+        .get("match.openings")
+        .expect("Couldn't find match.openings.");
+    let openings = read_openings(&suite, &fen, &rules);
+    if openings.is_empty() {
+        eprintln!("No openings found in {suite}.");
+        exit(1);
+    }
+    let pgn_path: String = config
+        .get("match.pgn")
+        .unwrap_or_else(|_| "match.pgn".to_string());
+    let mut pgn = BufWriter::new(
+        File::create(&pgn_path).unwrap_or_else(|error| panic!("{pgn_path}: {error}")),
+    );
+
+    let (lower, upper) = sprt.bounds();
+    println!(
+        "{} vs {}, {} openings, SPRT elo0 {} elo1 {}, bounds [{lower:.2}, {upper:.2}]",
+        engines[0].name,
+        engines[1].name,
+        openings.len(),
+        sprt.elo0,
+        sprt.elo1
+    );
+
+    let started = Instant::now();
+    let next = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    let mut tally = Tally::default();
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..concurrency {
+            let sender = sender.clone();
+            let (engines, rules, openings) = (&engines, &rules, &openings);
+            let (next, done) = (&next, &done);
+            scope.spawn(move || {
+                let mut searches = [engines[0].search(), engines[1].search()];
+                while !done.load(Ordering::Relaxed) {
+                    let number = next.fetch_add(1, Ordering::Relaxed);
+                    if number >= games {
+                        break;
+                    }
+                    let opening = &openings[number / 2 % openings.len()];
+                    let game = play(number, engines, &mut searches, opening, rules);
+                    if sender.send(game).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        for game in receiver {
+            let first_won = match game.outcome {
+                Outcome::Draw => None,
+                outcome => Some((outcome == Outcome::White) == game.first_white),
+            };
+            match first_won {
+                Some(true) => tally.wins += 1,
+                Some(false) => tally.losses += 1,
+                None => tally.draws += 1,
+            }
+            write_pgn(&mut pgn, &game, &engines).unwrap_or_else(|error| panic!("{error}"));
+            let (elo, margin) = tally.elo();
+            let llr = tally.llr(&sprt);
+            let (white, black) = match game.first_white {
+                true => (&engines[0].name, &engines[1].name),
+                false => (&engines[1].name, &engines[0].name),
+            };
+            println!(
+                "Game {}: {white} - {black} {} ({}). +{} ={} -{}, Elo {elo:+.1} ± {margin:.1}, \
+                 LLR {llr:.2}",
+                game.number + 1,
+                game.outcome.pgn(),
+                game.reason,
+                tally.wins,
+                tally.draws,
+                tally.losses
+            );
+            let verdict = if llr >= upper {
+                Some(format!("H1 accepted: {} is stronger", engines[0].name))
+            } else if llr <= lower {
+                Some(format!("H0 accepted: {} isn't stronger", engines[0].name))
+            } else {
+                None
+            };
+            if let Some(verdict) = verdict {
+                println!("{verdict}");
+                // Games still being played are left out
+                done.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    });
+    pgn.flush().unwrap_or_else(|error| panic!("{error}"));
+
+    let (elo, margin) = tally.elo();
+    println!(
+        "{} games in {:.0} s: +{} ={} -{}, Elo {elo:+.1} ± {margin:.1} (95%), LLR {:.2}, saved to \
+         {pgn_path}",
+        tally.games(),
+        started.elapsed().as_secs_f64(),
+        tally.wins,
+        tally.draws,
+        tally.losses,
+        tally.llr(&sprt)
+    );
+}
+
+/// Openings of the suite, one per line: a FEN, or moves in coordinate notation from `fen`.
+fn read_openings(path: &str, fen: &str, rules: &Rules) -> Vec<Opening> {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|error| panic!("{path}: {error}"));
+    let mut openings = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (fen, names) = match line.contains('/') {
+            true => (line, ""),
+            false => (fen, line),
+        };
+        let mut board = Board::new(false, rules.files, rules.ranks);
+        if let Err(errors) = board.load_fen(fen) {
+            eprintln!("Invalid position on line {}: {errors:?}", number + 1);
+            continue;
+        }
+        let mut moves = vec![];
+        for name in names.split_whitespace() {
+            let Some(mv) = board
+                .legal_moves()
+                .into_iter()
+                .find(|&mv| board.move_name(mv) == name)
+            else {
+                eprintln!("Illegal move {name} on line {}, skipped.", number + 1);
+                moves.clear();
+                break;
+            };
+            board.make_move(mv);
+            moves.push(mv);
+        }
+        if names.is_empty() || !moves.is_empty() {
+            openings.push(Opening {
+                fen: fen.to_string(),
+                moves,
+            });
+        }
+    }
+    openings
+}
+
+/// Plays game `number`, the first engine taking white in even ones.
+fn play(
+    number: usize,
+    engines: &[Engine; 2],
+    searches: &mut [Search; 2],
+    opening: &Opening,
+    rules: &Rules,
+) -> Game {
+    let first_white = number.is_multiple_of(2);
+    let mut board = Board::new(false, rules.files, rules.ranks);
+    board.load_fen(&opening.fen).unwrap();
+    let start = (board.turn, board.fullmove_number);
+    let mut moves = vec![];
+    for &mv in &opening.moves {
+        moves.push(board.san(mv));
+        board.make_move(mv);
+    }
+    for search in searches.iter_mut() {
+        search.clear();
+    }
+    let mut seen: HashMap<u64, u32> = HashMap::new();
+    let mut clocks = [rules.limits.clock; 2];
+    // Scores of the engine moves, from white's point of view
+    let mut scores: Vec<i32> = vec![];
+
+    let (outcome, reason, termination) = loop {
+        *seen.entry(board.hash).or_default() += 1;
+        if let Some(end) = game_over(&mut board, &seen) {
+            break end;
+        }
+        if rules.max_moves > 0 && board.fullmove_number > rules.max_moves {
+            break (Outcome::Draw, "game too long".to_string(), "adjudication");
+        }
+
+        let side = board.turn as usize;
+        let engine = match (board.turn == Color::White) == first_white {
+            true => 0,
+            false => 1,
+        };
+        let mut position = engines[engine].position(&board);
+        let limits = Limits {
+            clock: clocks[side],
+            ..rules.limits
+        };
+        let started = Instant::now();
+        searches[engine].stop.store(false, Ordering::Relaxed);
+        let result = searches[engine].search(&mut position, &limits);
+        if let Some(clock) = &mut clocks[side] {
+            let elapsed = started.elapsed();
+            if elapsed > clock.remaining {
+                let winner = match board.turn {
+                    Color::White => Outcome::Black,
+                    Color::Black => Outcome::White,
+                };
+                break (winner, "time forfeit".to_string(), "time forfeit");
+            }
+            clock.remaining = clock.remaining - elapsed + clock.increment;
+        }
+        let mv = result.best_move.expect("No move in a game going on.");
+        scores.push(match board.turn {
+            Color::White => result.score,
+            Color::Black => -result.score,
+        });
+        moves.push(board.san(mv));
+        board.make_move(mv);
+
+        if let Some(end) = adjudicate(&scores, board.fullmove_number, rules) {
+            break end;
+        }
+    };
+    Game {
+        number,
+        first_white,
+        fen: opening.fen.clone(),
+        start,
+        moves,
+        outcome,
+        reason,
+        termination,
+    }
+}
+
+/// Result of a game the rules end: mate, stalemate, threefold repetition, the fifty-move
+/// rule or too little material to mate.
+fn game_over(
+    board: &mut Board,
+    seen: &HashMap<u64, u32>,
+) -> Option<(Outcome, String, &'static str)> {
+    let draw = |reason: &str| Some((Outcome::Draw, reason.to_string(), "normal"));
+    if board.legal_moves().is_empty() {
+        if !board.in_check(board.turn) {
+            return draw("stalemate");
+        }
+        let winner = match board.turn {
+            Color::White => Outcome::Black,
+            Color::Black => Outcome::White,
+        };
+        return Some((winner, "checkmate".to_string(), "normal"));
+    }
+    if seen.get(&board.hash).is_some_and(|&count| count >= 3) {
+        return draw("threefold repetition");
+    }
+    if board.halfmove_clock >= 100 {
+        return draw("fifty-move rule");
+    }
+    // Bare kings, or a single minor piece left
+    let mut others = board
+        .pieces()
+        .map(|(_, piece)| piece.kind)
+        .filter(|&kind| kind != Kind::King);
+    let insufficient = matches!(
+        (others.next(), others.next()),
+        (None, _) | (Some(Kind::Bishop | Kind::Knight), None)
+    );
+    if insufficient {
+        return draw("insufficient material");
+    }
+    None
+}
+
+/// Ends a game the engines agree is decided, from the scores of their last moves.
+fn adjudicate(
+    scores: &[i32],
+    fullmove_number: u32,
+    rules: &Rules,
+) -> Option<(Outcome, String, &'static str)> {
+    let last = |moves: usize| match moves {
+        0 => None,
+        moves => scores.get(scores.len().checked_sub(2 * moves)?..),
+    };
+    if let Some(last) = last(rules.resign_moves) {
+        if last.iter().all(|&score| score >= rules.resign_score) {
+            return Some((Outcome::White, "black resigns".to_string(), "adjudication"));
+        }
+        if last.iter().all(|&score| score <= -rules.resign_score) {
+            return Some((Outcome::Black, "white resigns".to_string(), "adjudication"));
+        }
+    }
+    if let Some(last) = last(rules.draw_moves).filter(|_| fullmove_number > rules.draw_after) {
+        if last.iter().all(|&score| score.abs() <= rules.draw_score) {
+            return Some((Outcome::Draw, "drawn position".to_string(), "adjudication"));
+        }
+    }
+    None
+}
+
+fn write_pgn(pgn: &mut impl Write, game: &Game, engines: &[Engine; 2]) -> std::io::Result<()> {
+    let (white, black) = match game.first_white {
+        true => (&engines[0].name, &engines[1].name),
+        false => (&engines[1].name, &engines[0].name),
+    };
+    let result = game.outcome.pgn();
+    writeln!(
+        pgn,
+        "[Event \"{} vs {}\"]",
+        engines[0].name, engines[1].name
+    )?;
+    writeln!(pgn, "[Site \"?\"]")?;
+    writeln!(pgn, "[Date \"????.??.??\"]")?;
+    writeln!(pgn, "[Round \"{}\"]", game.number + 1)?;
+    writeln!(pgn, "[White \"{white}\"]")?;
+    writeln!(pgn, "[Black \"{black}\"]")?;
+    writeln!(pgn, "[Result \"{result}\"]")?;
+    if game.fen != STANDARD_FEN {
+        writeln!(pgn, "[SetUp \"1\"]")?;
+        writeln!(pgn, "[FEN \"{}\"]", game.fen)?;
+    }
+    writeln!(pgn, "[Termination \"{}\"]", game.termination)?;
+    writeln!(pgn)?;
+
+    // Movetext wrapped at 80 columns
+    let (mut turn, mut number) = game.start;
+    let mut tokens = vec![];
+    for (ply, san) in game.moves.iter().enumerate() {
+        match turn {
+            Color::White => tokens.push(format!("{number}.")),
+            Color::Black if ply == 0 => tokens.push(format!("{number}...")),
+            Color::Black => {}
+        }
+        tokens.push(san.clone());
+        if turn == Color::Black {
+            number += 1;
+        }
+        turn = turn.opponent();
+    }
+    tokens.push(format!("{{{}}}", game.reason));
+    tokens.push(result.to_string());
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() > 80 {
+            writeln!(pgn, "{line}")?;
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    writeln!(pgn, "{line}")?;
+    writeln!(pgn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(value: f64, expected: f64, tolerance: f64) -> bool {
+        (value - expected).abs() < tolerance
+    }
+
+    #[test]
+    fn bounds() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.1,
+        };
+        let (lower, upper) = sprt.bounds();
+        assert!(close(lower, (0.1f64 / 0.95).ln(), 1e-12), "{lower}");
+        assert!(close(upper, (0.9f64 / 0.05).ln(), 1e-12), "{upper}");
+    }
+
+    #[test]
+    fn elo() {
+        assert!(close(super::elo(0.5), 0.0, 1e-9));
+        assert!(close(super::elo(0.75), 400.0 * 3f64.log10(), 1e-9));
+        assert!(close(super::elo(0.25), -400.0 * 3f64.log10(), 1e-9));
+        let tally = Tally {
+            wins: 300,
+            draws: 400,
+            losses: 300,
+        };
+        let (elo, margin) = tally.elo();
+        assert!(
+            close(elo, 0.0, 1e-9) && close(margin, 16.69, 0.01),
+            "{elo} {margin}"
+        );
+    }
+
+    /// Against the normal approximation without the extra half games, which barely matter
+    /// over thousands of games.
+    #[test]
+    fn llr() {
+        let sprt = Sprt {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+        };
+        let mut tally = Tally {
+            wins: 1100,
+            draws: 1900,
+            losses: 1000,
+        };
+        assert!(close(tally.llr(&sprt), 2.329, 0.01), "{}", tally.llr(&sprt));
+        (tally.wins, tally.losses) = (tally.losses, tally.wins);
+        assert!(
+            close(tally.llr(&sprt), -8.645, 0.01),
+            "{}",
+            tally.llr(&sprt)
+        );
+
+        // An even score is as likely under both hypotheses around it
+        let sprt = Sprt {
+            elo0: -5.0,
+            elo1: 5.0,
+            ..sprt
+        };
+        let tally = Tally {
+            wins: 30,
+            draws: 40,
+            losses: 30,
+        };
+        assert!(close(tally.llr(&sprt), 0.0, 1e-9), "{}", tally.llr(&sprt));
+    }
+}
@@ -0,0 +1,72 @@
+//! Finds forced mates, to check that composed problems are sound: the position has a mate in
+//! the given number of moves, and only one key move starts it.
+//!
+//! Usage: `mate <moves> <fen> [--checks]`, on the board size of `config.toml`. With
+//! `--checks` only checking moves are tried for the attacker, quicker for checkmate puzzles
+//! but blind to quiet keys.
+
+use chess::board::Board;
+use chess::mate::MateFinder;
+use config::Config;
+use std::process::exit;
+use std::time::Instant;
+
+fn main() {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let checks_only = args.iter().any(|arg| arg == "--checks");
+    args.retain(|arg| arg != "--checks");
+    if args.len() < 2 {
+        eprintln!("Usage: mate <moves> <fen> [--checks]");
+        exit(1);
+    }
+    let moves: u32 = args[0]
+        .parse()
+        .unwrap_or_else(|_| panic!("Bad number of moves: {}", args[0]));
+    let fen = args[1..].join(" ");
+
+    let config = Config::builder()
+        .add_source(config::File::with_name("config.toml"))
+        .build()
+        .unwrap();
+    let files: u8 = config
+        .get("board.files")
+        .expect("Couldn't find board.files in config.");
+    let ranks: u8 = config
+        .get("board.ranks")
+        .expect("Couldn't find board.ranks in config.");
+    let mut board = Board::new(false, files, ranks);
+    if let Err(errors) = board.load_fen(&fen) {
+        for error in errors.iter() {
+            eprintln!("Invalid position: {error}");
+        }
+        exit(1);
+    }
+
+    let started = Instant::now();
+    let mut finder = MateFinder::new(checks_only);
+    let solution = finder.solve(&mut board, moves);
+    let elapsed = started.elapsed();
+    let Some(solution) = solution else {
+        println!("No mate in {moves}");
+        println!("{} nodes in {:.1} s", finder.nodes, elapsed.as_secs_f64());
+        exit(2);
+    };
+
+    let names = |moves: &[_]| {
+        moves
+            .iter()
+            .map(|&mv| board.move_name(mv))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    println!("Mate in {}: {}", solution.moves, names(&solution.line));
+    match solution.keys.as_slice() {
+        [key] if solution.moves == moves => println!("Sound, the key is {}", board.move_name(*key)),
+        [_] => println!("Unsound, there is a shorter mate"),
+        keys => println!("Unsound, {} keys: {}", keys.len(), names(keys)),
+    }
+    if checks_only {
+        println!("Only checking keys were tried");
+    }
+    println!("{} nodes in {:.1} s", finder.nodes, elapsed.as_secs_f64());
+}
@@ -0,0 +1,83 @@
+//! Generates distance-to-mate endgame tables by retrograde analysis over the engine's move
+//! generator, for the board size of `config.toml`, into its `engine.endgames` directory.
+//!
+//! Usage: `tablegen <material>...`, each material listing white's pieces then black's, like
+//! `KQK`, `KBNK` or `KRvKB`. The tables captures and promotions lead to are made too, unless
+//! already in the directory.
+
+use chess::endgames::{Dtm, Endgames, Material};
+use config::Config;
+use std::path::Path;
+use std::process::exit;
+use std::time::Instant;
+
+fn main() {
+    let names: Vec<String> = std::env::args().skip(1).collect();
+    if names.is_empty() {
+        eprintln!("Usage: tablegen <material>...");
+        exit(1);
+    }
+    let materials: Vec<Material> = names
+        .iter()
+        .map(|name| Material::parse(name).unwrap_or_else(|error| panic!("{error}")))
+        .collect();
+
+    let config = Config::builder()
+        .add_source(config::File::with_name("config.toml"))
+        .build()
+        .unwrap();
+    let files: u8 = config
+        .get("board.files")
+        .expect("Couldn't find board.files in config.");
+    let ranks: u8 = config
+        .get("board.ranks")
+        .expect("Couldn't find board.ranks in config.");
+    let directory: String = config
+        .get("engine.endgames")
+        .expect("Couldn't find engine.endgames in config.");
+    let directory = Path::new(&directory);
+    std::fs::create_dir_all(directory)
+        .unwrap_or_else(|error| panic!("{}: {error}", directory.display()));
+
+    let mut endgames =
+        Endgames::load(directory, files, ranks).unwrap_or_else(|error| panic!("{error}"));
+    for material in materials {
+        let started = Instant::now();
+        let generated = endgames
+            .generate(&material)
+            .unwrap_or_else(|error| panic!("{error}"));
+        if generated.is_empty() {
+            println!("{} is already in {}", material.name(), directory.display());
+        }
+        for material in generated {
+            let table = endgames.get(&material).unwrap();
+            let bytes = table
+                .save(directory)
+                .unwrap_or_else(|error| panic!("{error}"));
+            let (mut wins, mut draws, mut losses, mut longest) = (0, 0, 0, 0);
+            for result in table.results() {
+                match result {
+                    Dtm::Win(plies) => {
+                        wins += 1;
+                        longest = longest.max(plies);
+                    }
+                    Dtm::Draw => draws += 1,
+                    Dtm::Loss(plies) => {
+                        losses += 1;
+                        longest = longest.max(plies);
+                    }
+                }
+            }
+            println!(
+                "{}: {wins} wins, {draws} draws, {losses} losses, longest mate {longest} plies, \
+                 {bytes} bytes",
+                material.name()
+            );
+        }
+        println!(
+            "{} done in {:.1} s",
+            material.name(),
+            started.elapsed().as_secs_f64()
+        );
+    }
+}
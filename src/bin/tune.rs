@@ -0,0 +1,209 @@
+//! Texel tuning of the evaluation weights: finds the weights whose evaluations of quiet
+//! positions best predict the results of the games they come from, then writes them back
+//! to the weights file of `config.toml`.
+//!
+//! Usage: `tune <positions> [passes]`. Each line of the positions file holds a FEN, or its
+//! first four fields, and the game result for white as `[1.0]`, `[0.5]`, `[0.0]`, `1-0`,
+//! `1/2-1/2` or `0-1`, as in the usual EPD training sets.
+
+use chess::board::Board;
+use chess::eval::Weights;
+use chess::pawns::PawnTable;
+use chess::piece::Color;
+use config::Config;
+use std::collections::HashMap;
+use std::process::exit;
+use std::sync::Arc;
+use std::thread;
+
+/// Passes over all the weights when none is given.
+const DEFAULT_PASSES: usize = 20;
+
+/// Position with the result of its game for white: 1 for a win, 0.5 for a draw, 0 for a loss.
+struct Sample {
+    board: Board,
+    result: f64,
+}
+
+/// One number of the weights file, by entry name and position in its list.
+struct Parameter {
+    name: String,
+    at: usize,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(positions) = args.get(1) else {
+        eprintln!("Usage: tune <positions> [passes]");
+        exit(1);
+    };
+    let passes = args.get(2).map_or(DEFAULT_PASSES, |passes| {
+        passes
+            .parse()
+            .unwrap_or_else(|_| panic!("Bad number of passes: {passes}"))
+    });
+
+    let config = Config::builder()
+        .add_source(config::File::with_name("config.toml"))
+        .build()
+        .unwrap();
+    let files: u8 = config
+        .get("board.files")
+        .expect("Couldn't find board.files in config.");
+    let ranks: u8 = config
+        .get("board.ranks")
+        .expect("Couldn't find board.ranks in config.");
+    let path: String = config
+        .get("engine.weights")
+        .expect("Couldn't find evaluation weights in config.");
+    let weights = Weights::load(&path).unwrap_or_else(|error| panic!("{error}"));
+
+    let mut samples = read_samples(positions, files, ranks);
+    if samples.is_empty() {
+        eprintln!("No positions found in {positions}.");
+        exit(1);
+    }
+    println!("{} positions", samples.len());
+
+    let mut entries: HashMap<String, Vec<i32>> = weights.entries().into_iter().collect();
+    // Phases only weigh the other terms, they are left as they are
+    let parameters: Vec<Parameter> = weights
+        .entries()
+        .into_iter()
+        .filter(|(name, _)| !name.ends_with(".phase"))
+        .flat_map(|(name, numbers)| {
+            (0..numbers.len()).map(move |at| Parameter {
+                name: name.clone(),
+                at,
+            })
+        })
+        .collect();
+
+    let scale = fit_scale(&mut samples, &weights);
+    println!("Scale {scale:.3}");
+    let mut best = error(&mut samples, &weights, scale);
+    println!("Error {best:.6}");
+
+    // Local search: each number moves by one as long as that lowers the error
+    for pass in 1..=passes {
+        let mut improved = false;
+        for parameter in &parameters {
+            for step in [1, -1] {
+                entries.get_mut(&parameter.name).unwrap()[parameter.at] += step;
+                let candidate = Weights::from_entries(entries.clone()).unwrap();
+                let candidate_error = error(&mut samples, &candidate, scale);
+                if candidate_error < best {
+                    best = candidate_error;
+                    improved = true;
+                    break;
+                }
+                entries.get_mut(&parameter.name).unwrap()[parameter.at] -= step;
+            }
+        }
+        let tuned = Weights::from_entries(entries.clone()).unwrap();
+        tuned.save(&path).unwrap_or_else(|error| panic!("{error}"));
+        println!("Pass {pass}: error {best:.6}, saved to {path}");
+        if !improved {
+            break;
+        }
+    }
+}
+
+fn read_samples(path: &str, files: u8, ranks: u8) -> Vec<Sample> {
+    let text = std::fs::read_to_string(path).unwrap_or_else(|error| panic!("{path}: {error}"));
+    let mut samples = vec![];
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(result) = parse_result(line) else {
+            eprintln!("No result on line {}, skipped.", number + 1);
+            continue;
+        };
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let mut fen: Vec<&str> = fields.iter().take(4).copied().collect();
+        // Clocks are optional, as in EPD
+        fen.extend(
+            fields
+                .iter()
+                .skip(4)
+                .take(2)
+                .take_while(|field| field.parse::<u32>().is_ok()),
+        );
+        let mut board = Board::new(false, files, ranks);
+        if let Err(errors) = board.load_fen(&fen.join(" ")) {
+            eprintln!("Invalid position on line {}: {errors:?}", number + 1);
+            continue;
+        }
+        samples.push(Sample { board, result });
+    }
+    samples
+}
+
+fn parse_result(line: &str) -> Option<f64> {
+    if let Some(start) = line.find('[') {
+        let end = start + line[start..].find(']')?;
+        return line[start + 1..end].trim().parse().ok();
+    }
+    [("1/2-1/2", 0.5), ("1-0", 1.0), ("0-1", 0.0)]
+        .into_iter()
+        .find(|(text, _)| line.contains(text))
+        .map(|(_, result)| result)
+}
+
+/// Mean squared difference between the results and the evaluations mapped to an expected
+/// result, `scale` fitting centipawns to winning chances.
+fn error(samples: &mut [Sample], weights: &Weights, scale: f64) -> f64 {
+    let weights = Arc::new(weights.clone());
+    let threads = thread::available_parallelism().map_or(1, |count| count.get());
+    let chunk = samples.len().div_ceil(threads);
+    let total: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = samples
+            .chunks_mut(chunk)
+            .map(|chunk| {
+                let weights = Arc::clone(&weights);
+                scope.spawn(move || {
+                    // Cached structures depend on the weights, a single cleared slot is enough
+                    let mut pawns = PawnTable::new(0);
+                    let mut total = 0.0;
+                    for sample in chunk {
+                        sample.board.set_weights(Arc::clone(&weights));
+                        pawns.clear();
+                        let score = match sample.board.turn {
+                            Color::White => sample.board.evaluate(&mut pawns),
+                            Color::Black => -sample.board.evaluate(&mut pawns),
+                        };
+                        let expected = 1.0 / (1.0 + 10f64.powf(-scale * score as f64 / 400.0));
+                        total += (sample.result - expected).powi(2);
+                    }
+                    total
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    });
+    total / samples.len() as f64
+}
+
+/// Scale giving the current weights the lowest error, narrowed down digit by digit.
+fn fit_scale(samples: &mut [Sample], weights: &Weights) -> f64 {
+    let mut best = 1.0;
+    let mut step = 0.5;
+    let mut best_error = error(samples, weights, best);
+    for _ in 0..4 {
+        let mut scale = (best - 5.0 * step).max(step);
+        while scale <= best + 5.0 * step {
+            let candidate = error(samples, weights, scale);
+            if candidate < best_error {
+                best_error = candidate;
+                best = scale;
+            }
+            scale += step;
+        }
+        step /= 10.0;
+    }
+    best
+}
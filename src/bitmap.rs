@@ -0,0 +1,90 @@
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign};
+
+/// Set of squares, one bit per square index of the board.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Bitmap([u64; 4]);
+
+impl Bitmap {
+    pub fn new() -> Self {
+        Bitmap([0; 4])
+    }
+
+    pub fn set(&mut self, index: u8) {
+        self.0[index as usize / 64] |= 1 << (index % 64);
+    }
+
+    pub fn unset(&mut self, index: u8) {
+        self.0[index as usize / 64] &= !(1 << (index % 64));
+    }
+
+    pub fn get(&self, index: u8) -> bool {
+        self.0[index as usize / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn count(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&word| word == 0)
+    }
+
+    /// Indices of the squares in the set, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        self.0.iter().enumerate().flat_map(|(w, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some((w * 64 + bit) as u8)
+            })
+        })
+    }
+}
+
+impl FromIterator<u8> for Bitmap {
+    fn from_iter<T: IntoIterator<Item = u8>>(iter: T) -> Self {
+        let mut bitmap = Bitmap::new();
+        for index in iter {
+            bitmap.set(index);
+        }
+        bitmap
+    }
+}
+
+impl BitOr for Bitmap {
+    type Output = Bitmap;
+
+    fn bitor(mut self, rhs: Bitmap) -> Bitmap {
+        self |= rhs;
+        self
+    }
+}
+
+impl BitOrAssign for Bitmap {
+    fn bitor_assign(&mut self, rhs: Bitmap) {
+        for (word, other) in self.0.iter_mut().zip(rhs.0) {
+            *word |= other;
+        }
+    }
+}
+
+impl BitAnd for Bitmap {
+    type Output = Bitmap;
+
+    fn bitand(mut self, rhs: Bitmap) -> Bitmap {
+        self &= rhs;
+        self
+    }
+}
+
+impl BitAndAssign for Bitmap {
+    fn bitand_assign(&mut self, rhs: Bitmap) {
+        for (word, other) in self.0.iter_mut().zip(rhs.0) {
+            *word &= other;
+        }
+    }
+}
@@ -1,416 +1,1152 @@
-use crate::moves::MoveType;
+use crate::bitmap::Bitmap;
+use crate::eval::{Score, Weights};
+use crate::moves::{Move, MoveType, NullMove, Undo};
+use crate::nnue::Nnue;
 use crate::piece::{Color, Kind, Piece};
-use opengl_graphics::Texture;
-use piston::Touch::Move;
-use sprite::Scene;
+use crate::pins::Legality;
+use crate::validation::ValidationError;
+use crate::zobrist::Keys;
+use std::sync::Arc;
 
 const BLACK: [f32; 4] = [81.0 / 255.0, 79.0 / 255.0, 174.0 / 255.0, 1.0];
 const WHITE: [f32; 4] = [133.0 / 255.0, 131.0 / 255.0, 198.0 / 255.0, 1.0];
-pub const RANK: [&str; 8] = ["1", "2", "3", "4", "5", "6", "7", "8"];
-pub const FILE: [&str; 8] = ["a", "b", "c", "d", "e", "f", "g", "h"];
+
+/// Pieces a pawn can be promoted to.
+pub const PROMOTIONS: [Kind; 4] = [Kind::Queen, Kind::Rook, Kind::Bishop, Kind::Knight];
+
+/// Largest board side supported, square indices must fit in a `u8`.
+pub const MAX_SIDE: u8 = 16;
+
+pub const KNIGHT_DELTAS: [(i16, i16); 8] = [
+    (1, 2),
+    (2, 1),
+    (1, -2),
+    (2, -1),
+    (-1, 2),
+    (-2, 1),
+    (-1, -2),
+    (-2, -1),
+];
+pub const BISHOP_DELTAS: [(i16, i16); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+pub const ROOK_DELTAS: [(i16, i16); 4] = [(1, 0), (0, -1), (-1, 0), (0, 1)];
+pub const QUEEN_DELTAS: [(i16, i16); 8] = [
+    (1, 1),
+    (1, -1),
+    (-1, -1),
+    (-1, 1),
+    (1, 0),
+    (0, -1),
+    (-1, 0),
+    (0, 1),
+];
 
 #[derive(Copy, Clone, Debug)]
 pub struct Square {
     pub color: [f32; 4],
-    pub index: u8,
-    pub piece_id: Option<u8>,
+    pub file: u8,
+    pub rank: u8,
+    pub piece: Option<Piece>,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Wing {
+    King,
+    Queen,
+}
+
+/// Moves of a piece to generate, captures and promotions being the noisy ones.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Targets {
+    All,
+    Noisy,
+    Quiet,
+}
+
+impl Targets {
+    fn includes(self, noisy: bool) -> bool {
+        match self {
+            Targets::All => true,
+            Targets::Noisy => noisy,
+            Targets::Quiet => !noisy,
+        }
+    }
+}
+
+/// Castling rights of both sides, on both wings.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug)]
+pub struct Castling([[bool; 2]; 2]);
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Variant {
+    Standard,
+    /// Dark chess, each side only sees what its pieces reach and kings can be taken.
+    FogOfWar,
 }
 
+#[derive(Clone)]
 pub struct Board {
     pub squares: Vec<Square>,
     pub flip: bool,
+    pub files: u8,
+    pub ranks: u8,
+    pub turn: Color,
+    pub variant: Variant,
+    pub castling: Castling,
+    pub en_passant: Option<u8>,
+    pub halfmove_clock: u32,
+    pub fullmove_number: u32,
+    pub history: Vec<Undo>,
+    /// Zobrist hash of the position, updated on every move.
+    pub hash: u64,
+    /// Zobrist hash of the pawns only, for the pawn structure cache.
+    pub pawn_hash: u64,
+    pub weights: Arc<Weights>,
+    /// Material and piece-square score from white's point of view, updated on every move.
+    pub psqt: Score,
+    /// Game phase, from 0 in the endgame up to 24 with every piece on the board.
+    pub phase: i32,
+    /// Network evaluating the position instead of the weights, with its accumulators.
+    pub nnue: Option<Nnue>,
 }
 
 impl Square {
-    pub fn new(color: [f32; 4], index: u8) -> Self {
+    pub fn new(color: [f32; 4], file: u8, rank: u8) -> Self {
         Square {
             color,
-            index,
-            piece_id: None,
+            file,
+            rank,
+            piece: None,
         }
     }
 
-    pub fn file_rank(self: &Self) -> (u8, u8) {
-        (self.index % 8, self.index / 8)
+    pub fn file_rank(&self) -> (u8, u8) {
+        (self.file, self.rank)
     }
 
-    pub fn file_rank_to_index(file: u8, rank: u8) -> u8 {
-        return file + 8 * rank;
+    /// Letter of a file, `a` being the leftmost one.
+    pub fn file_name(file: u8) -> String {
+        char::from(b'a' + file).to_string()
     }
 
-    pub fn file_rank_to_xy(file: u8, rank: u8, square_size: f32, height: f32) -> (f32, f32) {
-        (
-            file as f32 * square_size + square_size / 2.0,
-            height - (rank as f32 * square_size + square_size / 2.0),
-        )
+    /// Number of a rank, starting at 1 on white's side.
+    pub fn rank_name(rank: u8) -> String {
+        (rank + 1).to_string()
     }
+}
 
-    pub fn xy_to_file_rank(x: f32, y: f32, square_size: f32, height: f32) -> (u8, u8) {
-        (
-            (x / square_size).floor() as u8,
-            ((height - y) / square_size).floor() as u8,
-        )
+impl Castling {
+    pub fn get(&self, color: Color, wing: Wing) -> bool {
+        self.0[color.index()][wing as usize]
     }
 
-    pub fn xy_to_index(x: f32, y: f32, square_size: f32, height: f32) -> u8 {
-        let (file, rank) = Self::xy_to_file_rank(x, y, square_size, height);
-        Self::file_rank_to_index(file, rank)
+    pub fn set(&mut self, color: Color, wing: Wing, allowed: bool) {
+        self.0[color.index()][wing as usize] = allowed;
     }
+}
 
-    pub fn index_to_file_rank(index: u8) -> (u8, u8) {
-        (index % 8, index / 8)
+impl Variant {
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "standard" => Some(Variant::Standard),
+            "fog" => Some(Variant::FogOfWar),
+            _ => None,
+        }
     }
 }
 
 impl Board {
-    pub fn new(flip: bool) -> Self {
+    pub fn new(flip: bool, files: u8, ranks: u8) -> Self {
+        assert!(
+            (1..=MAX_SIDE).contains(&files) && (1..=MAX_SIDE).contains(&ranks),
+            "Board dimensions {files}x{ranks} not supported."
+        );
         let mut squares: Vec<Square> = Vec::new();
-        for i in 0..64 {
-            if (i + i / 8) % 2 == 0 {
-                squares.push(Square::new(WHITE, i))
-            } else {
-                squares.push(Square::new(BLACK, i))
+        for rank in 0..ranks {
+            for file in 0..files {
+                let color = if (file + rank) % 2 == 0 { BLACK } else { WHITE };
+                squares.push(Square::new(color, file, rank))
             }
         }
-        Board { squares, flip }
+        Board {
+            squares,
+            flip,
+            files,
+            ranks,
+            turn: Color::White,
+            variant: Variant::Standard,
+            castling: Castling::default(),
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: vec![],
+            hash: 0,
+            pawn_hash: 0,
+            weights: Weights::shared_default(),
+            psqt: Score::default(),
+            phase: 0,
+            nnue: None,
+        }
     }
 
-    pub fn get_square_at_file_rank(&mut self, file: u8, rank: u8) -> &Square {
-        &self.squares[(rank + file * 8) as usize]
+    pub fn piece_at(&self, index: u8) -> Option<Piece> {
+        self.squares[index as usize].piece
     }
 
-    /// Forsyth–Edwards Notation parsing
-    pub fn load_fen(&mut self, scene: &mut Scene<Texture>, fen: String) -> Vec<Piece> {
-        let mut index = 0;
-        let mut pieces = vec![];
-        for rank in fen.split("/") {
-            for c in rank.chars() {
-                if c.is_ascii_digit() {
-                    index += c.to_digit(10).unwrap();
-                } else {
-                    let color = match c.is_ascii_lowercase() {
-                        true => Color::White,
-                        false => Color::Black,
-                    };
-                    pieces.push(match c.to_lowercase().last().unwrap() {
-                        'p' => Piece::new(Kind::Pawn, color, scene, index as u8),
-                        'n' => Piece::new(Kind::Knight, color, scene, index as u8),
-                        'b' => Piece::new(Kind::Bishop, color, scene, index as u8),
-                        'r' => Piece::new(Kind::Rook, color, scene, index as u8),
-                        'q' => Piece::new(Kind::Queen, color, scene, index as u8),
-                        'k' => Piece::new(Kind::King, color, scene, index as u8),
-                        _ => {
-                            panic!("Unknown letter found in FEN string: {c}")
-                        }
-                    });
-                    self.squares[index as usize].piece_id = Some(pieces.last().unwrap().id);
-                    index += 1;
-                }
-            }
+    /// Pieces on the board along with their square index.
+    pub fn pieces(&self) -> impl Iterator<Item = (u8, Piece)> + '_ {
+        self.squares
+            .iter()
+            .enumerate()
+            .filter_map(|(index, square)| square.piece.map(|piece| (index as u8, piece)))
+    }
+
+    /// Rank where the pieces of a side start.
+    pub fn back_rank(&self, color: Color) -> u8 {
+        match color {
+            Color::White => 0,
+            Color::Black => self.ranks - 1,
         }
-        pieces
     }
 
-    pub fn generate_legal_moves(
-        &mut self,
-        pieces: &Vec<Piece>,
-        piece: &Piece,
-        file: u8,
-        rank: u8,
-    ) -> Vec<(u8, u8)> {
-        let mut legal_moves: Vec<(i16, i16)> = vec![];
-        match piece.kind {
-            Kind::Pawn => {
-                legal_moves = self.generate_pawn(pieces, piece, file, rank);
+    /// Squares of the king and of the rook of a wing before castling.
+    pub fn castling_squares(&self, color: Color, wing: Wing) -> (u8, u8) {
+        let rank = self.back_rank(color);
+        let rook_file = match wing {
+            Wing::King => self.files - 1,
+            Wing::Queen => 0,
+        };
+        (
+            self.file_rank_to_index(self.files / 2, rank),
+            self.file_rank_to_index(rook_file, rank),
+        )
+    }
+
+    /// Algebraic name of a square, such as `e4`.
+    pub fn square_name(&self, index: u8) -> String {
+        let (file, rank) = self.index_to_file_rank(index);
+        Square::file_name(file) + &Square::rank_name(rank)
+    }
+
+    /// Move in coordinate notation, such as `e2e4` or `e7e8q`.
+    pub fn move_name(&self, mv: Move) -> String {
+        let promotion = match mv.promotion {
+            Some(Kind::Queen) => "q",
+            Some(Kind::Rook) => "r",
+            Some(Kind::Bishop) => "b",
+            Some(Kind::Knight) => "n",
+            _ => "",
+        };
+        self.square_name(mv.from) + &self.square_name(mv.to) + promotion
+    }
+
+    /// Legal move in standard algebraic notation, such as `Nbd2`, `exd5`, `e8=Q+` or `O-O#`.
+    pub fn san(&mut self, mv: Move) -> String {
+        let letter = |kind| match kind {
+            Kind::Pawn => "",
+            Kind::Bishop => "B",
+            Kind::Knight => "N",
+            Kind::Rook => "R",
+            Kind::Queen => "Q",
+            Kind::King => "K",
+        };
+        let piece = self.piece_at(mv.from).expect("No piece to move.");
+        let (from_file, from_rank) = self.index_to_file_rank(mv.from);
+        let (to_file, _) = self.index_to_file_rank(mv.to);
+        let capture = self.captured_by(mv).is_some();
+        let mut san = if self.is_castling(mv) {
+            match to_file > from_file {
+                true => "O-O".to_string(),
+                false => "O-O-O".to_string(),
+            }
+        } else if piece.kind == Kind::Pawn {
+            let mut san = match capture {
+                true => Square::file_name(from_file) + "x",
+                false => String::new(),
+            };
+            san += &self.square_name(mv.to);
+            if let Some(kind) = mv.promotion {
+                san = san + "=" + letter(kind);
             }
-            Kind::Bishop => {
-                let deltas = vec![(1, 1), (1, -1), (-1, -1), (-1, 1)];
-                legal_moves = self.generate_line(deltas, pieces, piece, file, rank);
+            san
+        } else {
+            // Other pieces of the kind reaching the square tell which file or rank is needed
+            let rivals: Vec<u8> = self
+                .legal_moves()
+                .into_iter()
+                .filter(|other| other.to == mv.to && other.from != mv.from)
+                .filter(|other| self.piece_at(other.from).map(|p| p.kind) == Some(piece.kind))
+                .map(|other| other.from)
+                .collect();
+            let mut san = letter(piece.kind).to_string();
+            if !rivals.is_empty() {
+                let same_file = rivals
+                    .iter()
+                    .any(|&rival| self.index_to_file_rank(rival).0 == from_file);
+                let same_rank = rivals
+                    .iter()
+                    .any(|&rival| self.index_to_file_rank(rival).1 == from_rank);
+                san += &match (same_file, same_rank) {
+                    (false, _) => Square::file_name(from_file),
+                    (true, false) => Square::rank_name(from_rank),
+                    (true, true) => self.square_name(mv.from),
+                };
             }
-            Kind::Knight => {
-                let pos: Vec<(i16, i16)> = vec![
-                    (1, 2),
-                    (2, 1),
-                    (1, -2),
-                    (2, -1),
-                    (-1, 2),
-                    (-2, 1),
-                    (-1, -2),
-                    (-2, -1),
-                ];
-                legal_moves = self.generate_custom(pos, pieces, piece, file, rank);
+            if capture {
+                san += "x";
+            }
+            san + &self.square_name(mv.to)
+        };
+        self.make_move(mv);
+        if self.in_check(self.turn) {
+            san += match self.legal_moves().is_empty() {
+                true => "#",
+                false => "+",
+            };
+        }
+        self.unmake_move();
+        san
+    }
+
+    pub fn parse_square(&self, name: &str) -> Option<u8> {
+        let mut chars = name.chars();
+        let file = (chars.next()? as u32).checked_sub('a' as u32)?;
+        let rank = chars.as_str().parse::<u32>().ok()?.checked_sub(1)?;
+        (file < self.files as u32 && rank < self.ranks as u32)
+            .then(|| self.file_rank_to_index(file as u8, rank as u8))
+    }
+
+    pub fn file_rank_to_index(&self, file: u8, rank: u8) -> u8 {
+        file + self.files * rank
+    }
+
+    pub fn index_to_file_rank(&self, index: u8) -> (u8, u8) {
+        (index % self.files, index / self.files)
+    }
+
+    /// Center of a square in window coordinates.
+    pub fn file_rank_to_xy(&self, file: u8, rank: u8, square_size: f32) -> (f32, f32) {
+        let (column, row) = if self.flip {
+            (self.files - 1 - file, self.ranks - 1 - rank)
+        } else {
+            (file, rank)
+        };
+        let height = self.ranks as f32 * square_size;
+        (
+            column as f32 * square_size + square_size / 2.0,
+            height - (row as f32 * square_size + square_size / 2.0),
+        )
+    }
+
+    /// Square under a window position, if any.
+    pub fn xy_to_file_rank(&self, x: f32, y: f32, square_size: f32) -> Option<(u8, u8)> {
+        let height = self.ranks as f32 * square_size;
+        let column = (x / square_size).floor();
+        let row = ((height - y) / square_size).floor();
+        if column < 0.0 || row < 0.0 || column >= self.files as f32 || row >= self.ranks as f32 {
+            return None;
+        }
+        let (column, row) = (column as u8, row as u8);
+        Some(if self.flip {
+            (self.files - 1 - column, self.ranks - 1 - row)
+        } else {
+            (column, row)
+        })
+    }
+
+    pub fn xy_to_index(&self, x: f32, y: f32, square_size: f32) -> Option<u8> {
+        self.xy_to_file_rank(x, y, square_size)
+            .map(|(file, rank)| self.file_rank_to_index(file, rank))
+    }
+
+    /// Forsyth–Edwards Notation parsing, missing trailing fields take their default values.
+    /// Malformed strings and impossible positions are both reported, every problem found.
+    pub fn load_fen(&mut self, fen: &str) -> Result<(), Vec<ValidationError>> {
+        for square in self.squares.iter_mut() {
+            square.piece = None;
+        }
+        self.history.clear();
+        let mut errors = vec![];
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().unwrap_or_default();
+        let rows: Vec<&str> = placement.split('/').collect();
+        if rows.len() != self.ranks as usize {
+            errors.push(ValidationError::RankCount {
+                count: rows.len(),
+                ranks: self.ranks,
+            });
+        }
+        for (row, rank_str) in rows.iter().take(self.ranks as usize).enumerate() {
+            let rank = self.ranks - 1 - row as u8;
+            let mut file: u32 = 0;
+            let mut empty: u32 = 0;
+            for c in rank_str.chars() {
+                if let Some(digit) = c.to_digit(10) {
+                    empty = empty.saturating_mul(10).saturating_add(digit);
+                    continue;
+                }
+                file = file.saturating_add(empty);
+                empty = 0;
+                let color = match c.is_ascii_uppercase() {
+                    true => Color::White,
+                    false => Color::Black,
+                };
+                let kind = match c.to_ascii_lowercase() {
+                    'p' => Kind::Pawn,
+                    'n' => Kind::Knight,
+                    'b' => Kind::Bishop,
+                    'r' => Kind::Rook,
+                    'q' => Kind::Queen,
+                    'k' => Kind::King,
+                    _ => {
+                        errors.push(ValidationError::UnknownPiece { letter: c });
+                        continue;
+                    }
+                };
+                // Overflowing pieces are dropped, the width check below reports the rank
+                if file < self.files as u32 {
+                    let index = self.file_rank_to_index(file as u8, rank);
+                    self.squares[index as usize].piece = Some(Piece::new(kind, color, index));
+                }
+                file += 1;
             }
-            Kind::Rook => {
-                let deltas = vec![(1, 0), (0, -1), (-1, 0), (0, 1)];
-                legal_moves = self.generate_line(deltas, pieces, piece, file, rank);
+            if file.saturating_add(empty) != self.files as u32 {
+                errors.push(ValidationError::RankWidth {
+                    rank: rank_str.to_string(),
+                    files: self.files,
+                });
             }
-            Kind::Queen => {
-                let deltas = vec![
-                    (1, 1),
-                    (1, -1),
-                    (-1, -1),
-                    (-1, 1),
-                    (1, 0),
-                    (0, -1),
-                    (-1, 0),
-                    (0, 1),
-                ];
-                legal_moves = self.generate_line(deltas, pieces, piece, file, rank);
+        }
+        self.turn = match fields.next() {
+            None | Some("w") => Color::White,
+            Some("b") => Color::Black,
+            Some(field) => {
+                errors.push(ValidationError::UnknownTurn {
+                    field: field.to_string(),
+                });
+                Color::White
             }
-            Kind::King => {
-                let pos: Vec<(i16, i16)> = vec![
-                    (0, 1),
-                    (1, 1),
-                    (1, 0),
-                    (0, -1),
-                    (-1, -1),
-                    (-1, 0),
-                    (-1, 1),
-                    (1, -1),
-                ];
-                legal_moves = self.generate_custom(pos, pieces, piece, file, rank);
+        };
+        self.castling = Castling::default();
+        for c in fields.next().unwrap_or("-").chars() {
+            match c {
+                'K' => self.castling.set(Color::White, Wing::King, true),
+                'Q' => self.castling.set(Color::White, Wing::Queen, true),
+                'k' => self.castling.set(Color::Black, Wing::King, true),
+                'q' => self.castling.set(Color::Black, Wing::Queen, true),
+                '-' => {}
+                _ => errors.push(ValidationError::UnknownCastling { letter: c }),
             }
         }
-        legal_moves
-            .iter()
-            .map(|(file, rank)| {
-                (
-                    u8::try_from(file.clone()).unwrap(),
-                    u8::try_from(rank.clone()).unwrap(),
-                )
+        self.en_passant = match fields.next().unwrap_or("-") {
+            "-" => None,
+            name => {
+                let square = self.parse_square(name);
+                if square.is_none() {
+                    errors.push(ValidationError::UnknownEnPassant {
+                        field: name.to_string(),
+                    });
+                }
+                square
+            }
+        };
+        self.halfmove_clock = fields.next().map_or(0, |clock| {
+            clock.parse().unwrap_or_else(|_| {
+                errors.push(ValidationError::BadHalfmoveClock {
+                    field: clock.to_string(),
+                });
+                0
             })
+        });
+        self.fullmove_number = fields.next().map_or(1, |number| {
+            number.parse().unwrap_or_else(|_| {
+                errors.push(ValidationError::BadFullmoveNumber {
+                    field: number.to_string(),
+                });
+                1
+            })
+        });
+        self.hash = self.compute_hash();
+        self.pawn_hash = self.compute_pawn_hash();
+        self.refresh_eval();
+        match errors.is_empty() {
+            true => self.validate(),
+            false => Err(errors),
+        }
+    }
+
+    /// Side that won the game, if it is over.
+    pub fn winner(&mut self) -> Option<Color> {
+        match self.variant {
+            Variant::Standard => {
+                let checkmated = self.in_check(self.turn) && self.legal_moves().is_empty();
+                checkmated.then(|| self.turn.opponent())
+            }
+            Variant::FogOfWar => {
+                let has_king = |color| self.king_square(color).is_some();
+                if !has_king(Color::White) {
+                    Some(Color::Black)
+                } else if !has_king(Color::Black) {
+                    Some(Color::White)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Squares a side can see: those its pieces stand on, can move to or attack.
+    pub fn visibility(&self, color: Color) -> Bitmap {
+        let mut visible = Bitmap::new();
+        for (index, _) in self.pieces().filter(|(_, piece)| piece.color == color) {
+            visible.set(index);
+            visible |= self.attacks_from(index);
+            for mv in self.generate_pseudo_legal_moves(index) {
+                visible.set(mv.to);
+            }
+        }
+        visible
+    }
+
+    /// Square reached from `index` by moving `df` files and `dr` ranks, if inside the board.
+    pub fn offset(&self, index: u8, (df, dr): (i16, i16)) -> Option<u8> {
+        let (file, rank) = self.index_to_file_rank(index);
+        let (file, rank) = (file as i16 + df, rank as i16 + dr);
+        self.is_inside_board(file, rank)
+            .then(|| self.file_rank_to_index(file as u8, rank as u8))
+    }
+
+    /// Legal moves of every piece of the side to move.
+    pub fn legal_moves(&mut self) -> Vec<Move> {
+        let legality = self.legality(self.turn);
+        self.legal_moves_of(Targets::All, &legality)
+    }
+
+    /// Legal moves among `targets` of the side to move, whose pins and check are in
+    /// `legality`.
+    pub fn legal_moves_of(&mut self, targets: Targets, legality: &Legality) -> Vec<Move> {
+        let turn = self.turn;
+        let froms: Vec<u8> = self
+            .pieces()
+            .filter(|(_, piece)| piece.color == turn)
+            .map(|(index, _)| index)
+            .collect();
+        froms
+            .into_iter()
+            .flat_map(|from| self.filter_legal(from, targets, legality))
             .collect()
     }
 
-    pub fn generate_pawn(
-        &mut self,
-        pieces: &Vec<Piece>,
-        piece: &Piece,
-        file: u8,
-        rank: u8,
-    ) -> Vec<(i16, i16)> {
-        let mut legal_moves: Vec<(i16, i16)> = vec![];
+    /// The move can be played by the side to move, e.g. a move remembered from another
+    /// position.
+    pub fn is_legal(&mut self, mv: Move) -> bool {
+        let legality = self.legality(self.turn);
+        self.is_legal_with(mv, &legality)
+    }
 
-        let color = piece.color;
-        let mut moves = vec![];
-        if color == Color::White {
-            moves.append(&mut vec![(-1, 1), (1, 1)])
-        } else {
-            moves.append(&mut vec![(-1, -1), (1, -1)])
+    /// Same as `is_legal`, with the pins and check of the side to move already known.
+    pub fn is_legal_with(&mut self, mv: Move, legality: &Legality) -> bool {
+        let Some(piece) = self.piece_at(mv.from) else {
+            return false;
+        };
+        if piece.color != self.turn {
+            return false;
         }
-        let attack_moves: Vec<(i16, i16)> = moves
-            .iter()
-            .map(|(f, r)| (f + file as i16, r + rank as i16))
-            .filter(|(f, r)| Board::is_inside_board(f.clone(), r.clone()))
-            .filter(|(f, r)| {
-                Board::is_attacking(&pieces, &self.squares, piece, f.clone(), r.clone())
-            })
+        let targets = match self.is_noisy(mv) {
+            true => Targets::Noisy,
+            false => Targets::Quiet,
+        };
+        self.generate_moves(mv.from, targets).contains(&mv)
+            && self.keeps_king_safe(mv, piece, legality)
+    }
+
+    /// The move captures or promotes.
+    pub fn is_noisy(&self, mv: Move) -> bool {
+        mv.promotion.is_some() || self.captured_by(mv).is_some()
+    }
+
+    /// Moves of the piece on `from` that don't leave its own king in check.
+    pub fn generate_legal_moves(&mut self, from: u8) -> Vec<Move> {
+        let color = self.piece_at(from).expect("No piece.").color;
+        let legality = self.legality(color);
+        self.filter_legal(from, Targets::All, &legality)
+    }
+
+    fn filter_legal(&mut self, from: u8, targets: Targets, legality: &Legality) -> Vec<Move> {
+        let piece = self.piece_at(from).expect("No piece.");
+        let mut moves = self.generate_moves(from, targets);
+        moves.retain(|&mv| self.keeps_king_safe(mv, piece, legality));
+        moves
+    }
+
+    /// Only king moves, en passant and moves made in check need to be played to be tested,
+    /// the others are legal unless the piece is pinned and leaves its pin ray.
+    fn keeps_king_safe(&mut self, mv: Move, piece: Piece, legality: &Legality) -> bool {
+        if self.variant == Variant::FogOfWar {
+            // Kings may be left en prise, taking them ends the game
+            return true;
+        }
+        if !legality.in_check && piece.kind != Kind::King && !self.is_en_passant(mv) {
+            let pin = legality.pins.iter().find(|pin| pin.pinned == mv.from);
+            return pin.is_none_or(|pin| pin.ray.get(mv.to));
+        }
+        self.make_move(mv);
+        let legal = !self.in_check(piece.color);
+        self.unmake_move();
+        legal
+    }
+
+    /// Moves of the piece on `from`, without looking at the safety of its king.
+    pub fn generate_pseudo_legal_moves(&self, from: u8) -> Vec<Move> {
+        self.generate_moves(from, Targets::All)
+    }
+
+    /// Moves among `targets` of the piece on `from`, without looking at the safety of its
+    /// king.
+    pub fn generate_moves(&self, from: u8, targets: Targets) -> Vec<Move> {
+        let piece = self.piece_at(from).expect("No piece.");
+        match piece.kind {
+            Kind::Pawn => self.generate_pawn(&piece, from, targets),
+            Kind::Bishop => self.generate_line(&BISHOP_DELTAS, &piece, from, targets),
+            Kind::Knight => self.generate_custom(&KNIGHT_DELTAS, &piece, from, targets),
+            Kind::Rook => self.generate_line(&ROOK_DELTAS, &piece, from, targets),
+            Kind::Queen => self.generate_line(&QUEEN_DELTAS, &piece, from, targets),
+            Kind::King => {
+                let mut moves = self.generate_custom(&QUEEN_DELTAS, &piece, from, targets);
+                if targets != Targets::Noisy {
+                    moves.append(&mut self.generate_castling(&piece, from));
+                }
+                moves
+            }
+        }
+    }
+
+    pub fn generate_pawn(&self, piece: &Piece, from: u8, targets: Targets) -> Vec<Move> {
+        let en_passant = self.en_passant.filter(|_| piece.color == self.turn);
+        // Squares reached, with whether the pawn captures there
+        let mut reached: Vec<(u8, bool)> = Board::pawn_attacks(piece.color)
+            .into_iter()
+            .filter_map(|delta| self.offset(from, delta))
+            .filter(|&to| self.is_attacking(piece, to) || Some(to) == en_passant)
+            .map(|to| (to, true))
             .collect();
 
-        legal_moves.append(&mut attack_moves.clone());
+        let forward = Board::pawn_forward(piece.color);
+        if let Some(to) = self
+            .offset(from, forward)
+            .filter(|&to| self.piece_at(to).is_none())
+        {
+            reached.push((to, false));
+            // Double step from the starting rank
+            let (_, rank) = self.index_to_file_rank(from);
+            if rank == self.pawn_start_rank(piece.color) {
+                if let Some(to) = self
+                    .offset(to, forward)
+                    .filter(|&to| self.piece_at(to).is_none())
+                {
+                    reached.push((to, false));
+                }
+            }
+        }
 
+        let last_rank = self.back_rank(piece.color.opponent());
         let mut moves = vec![];
-        if color == Color::White {
-            moves.append(&mut vec![(0, 1)])
-        } else {
-            moves.append(&mut vec![(0, -1)])
+        for (to, capture) in reached {
+            let (_, rank) = self.index_to_file_rank(to);
+            let promotion = rank == last_rank;
+            if !targets.includes(capture || promotion) {
+                continue;
+            }
+            if promotion {
+                for kind in PROMOTIONS {
+                    moves.push(Move::with_promotion(from, to, kind));
+                }
+            } else {
+                moves.push(Move::new(from, to));
+            }
         }
+        moves
+    }
+    /// Castling moves, written as the king moving to its destination square.
+    pub fn generate_castling(&self, piece: &Piece, from: u8) -> Vec<Move> {
+        let mut moves = vec![];
+        for wing in [Wing::King, Wing::Queen] {
+            let (king, rook) = self.castling_squares(piece.color, wing);
+            if from != king || !self.castling.get(piece.color, wing) {
+                continue;
+            }
+            if self
+                .piece_at(rook)
+                .is_none_or(|rook| rook.kind != Kind::Rook || rook.color != piece.color)
+            {
+                continue;
+            }
+            let (king_to, rook_to) = self.castling_targets(piece.color, wing);
 
-        let mut normal_moves: Vec<(i16, i16)> = moves
-            .iter()
-            .map(|(f, r)| (f + file as i16, r + rank as i16))
-            .filter(|(f, r)| Board::is_inside_board(f.clone(), r.clone()))
-            .filter(|(f, r)| {
-                !Board::is_attacking(&pieces, &self.squares, piece, f.clone(), r.clone())
-            })
-            .filter(|(f, r)| !Board::is_allied(&pieces, &self.squares, piece, f.clone(), r.clone()))
-            .collect();
-        legal_moves.append(&mut normal_moves);
-        legal_moves
+            // Every square the king and the rook cross or land on must be empty
+            let squares = [king, rook, king_to, rook_to];
+            let low = *squares.iter().min().unwrap();
+            let high = *squares.iter().max().unwrap();
+            let empty = (low..=high)
+                .filter(|&index| index != king && index != rook)
+                .all(|index| self.piece_at(index).is_none());
+
+            // And the king can't castle out of, through or into check. There is no check in
+            // fog-of-war, where refused castling would give away hidden attackers.
+            let (king_low, king_high) = (king.min(king_to), king.max(king_to));
+            let safe = self.variant == Variant::FogOfWar
+                || (king_low..=king_high)
+                    .all(|index| !self.is_attacked(index, piece.color.opponent()));
+
+            if empty && safe {
+                moves.push(Move::new(from, king_to));
+            }
+        }
+        moves
     }
 
     pub fn generate_custom(
-        &mut self,
-        custom: Vec<(i16, i16)>,
-        pieces: &Vec<Piece>,
+        &self,
+        deltas: &[(i16, i16)],
         piece: &Piece,
-        file: u8,
-        rank: u8,
-    ) -> Vec<(i16, i16)> {
-        let mut first = true;
-        custom
+        from: u8,
+        targets: Targets,
+    ) -> Vec<Move> {
+        deltas
             .iter()
-            .map(|(f, r)| (f + file as i16, r + rank as i16))
-            .filter(|(f, r)| {
-                Board::is_legal(
-                    &pieces,
-                    &self.squares,
-                    piece,
-                    &mut first,
-                    f.clone(),
-                    r.clone(),
-                    false,
-                )
-            })
+            .filter_map(|&delta| self.offset(from, delta))
+            .filter(|&to| !self.is_allied(piece, to))
+            .filter(|&to| targets.includes(self.is_attacking(piece, to)))
+            .map(|to| Move::new(from, to))
             .collect()
     }
 
     pub fn generate_line(
-        &mut self,
-        deltas: Vec<(i16, i16)>,
-        pieces: &Vec<Piece>,
+        &self,
+        deltas: &[(i16, i16)],
         piece: &Piece,
-        file: u8,
-        rank: u8,
-    ) -> Vec<(i16, i16)> {
-        let mut legal_moves: Vec<(i16, i16)> = vec![];
-        for (df, dr) in deltas {
-            let mut first = true;
-            let mut ite: Vec<(i16, i16)> = vec![(file as i16, rank as i16)]
-                .iter()
-                .cycle()
-                .enumerate()
-                .map(|(index, (f, r))| (f + (1 + index) as i16 * df, r + (1 + index) as i16 * dr))
-                .take_while(|(f, r)| {
-                    Board::is_legal(
-                        &pieces,
-                        &self.squares,
-                        piece,
-                        &mut first,
-                        f.clone(),
-                        r.clone(),
-                        true,
-                    )
-                })
-                .collect();
-            legal_moves.append(&mut ite);
+        from: u8,
+        targets: Targets,
+    ) -> Vec<Move> {
+        let mut moves = vec![];
+        for &delta in deltas {
+            let mut to = from;
+            while let Some(next) = self.offset(to, delta) {
+                to = next;
+                if self.is_allied(piece, to) {
+                    break;
+                }
+                let capture = self.is_attacking(piece, to);
+                if targets.includes(capture) {
+                    moves.push(Move::new(from, to));
+                }
+                if capture {
+                    break;
+                }
+            }
         }
-        legal_moves
+        moves
     }
 
-    fn is_inside_board(file: i16, rank: i16) -> bool {
-        (file >= 0) & (rank >= 0) & (file < 8) & (rank < 8)
+    pub(crate) fn pawn_attacks(color: Color) -> [(i16, i16); 2] {
+        match color {
+            Color::White => [(-1, 1), (1, 1)],
+            Color::Black => [(-1, -1), (1, -1)],
+        }
     }
 
-    fn is_allied(
-        pieces: &Vec<Piece>,
-        squares: &Vec<Square>,
-        piece: &Piece,
-        file: i16,
-        rank: i16,
-    ) -> bool {
-        let mut allied = false;
-        let index = Square::file_rank_to_index(
-            u8::try_from(file.clone()).unwrap(),
-            u8::try_from(rank.clone()).unwrap(),
-        ) as usize;
-
-        if squares[index].piece_id.is_some() {
-            let dest_piece =
-                Piece::get_piece_from_id(pieces, squares[index].piece_id.unwrap()).unwrap();
-            if dest_piece.color == piece.color {
-                allied = true;
-            } else {
-                allied = false;
-            }
+    pub fn pawn_start_rank(&self, color: Color) -> u8 {
+        match color {
+            Color::White => 1,
+            Color::Black => self.ranks - 2,
         }
-        allied
     }
 
-    fn is_attacking(
-        pieces: &Vec<Piece>,
-        squares: &Vec<Square>,
-        piece: &Piece,
-        file: i16,
-        rank: i16,
-    ) -> bool {
-        let index = Square::file_rank_to_index(
-            u8::try_from(file.clone()).unwrap(),
-            u8::try_from(rank.clone()).unwrap(),
-        ) as usize;
+    /// Squares of the king and of the rook of a wing once castled.
+    pub fn castling_targets(&self, color: Color, wing: Wing) -> (u8, u8) {
+        let rank = self.back_rank(color);
+        let (king_file, rook_file) = match wing {
+            Wing::King => (self.files - 2, self.files - 3),
+            Wing::Queen => (2, 3),
+        };
+        (
+            self.file_rank_to_index(king_file, rank),
+            self.file_rank_to_index(rook_file, rank),
+        )
+    }
+
+    /// A king moving more than one file is castling.
+    pub fn is_castling(&self, mv: Move) -> bool {
+        let (from_file, _) = self.index_to_file_rank(mv.from);
+        let (to_file, _) = self.index_to_file_rank(mv.to);
+        self.piece_at(mv.from)
+            .is_some_and(|piece| piece.kind == Kind::King && from_file.abs_diff(to_file) > 1)
+    }
 
-        let mut attacking = false;
+    /// A pawn moving diagonally to the en passant square.
+    pub fn is_en_passant(&self, mv: Move) -> bool {
+        let (from_file, _) = self.index_to_file_rank(mv.from);
+        let (to_file, _) = self.index_to_file_rank(mv.to);
+        Some(mv.to) == self.en_passant
+            && from_file != to_file
+            && self
+                .piece_at(mv.from)
+                .is_some_and(|piece| piece.kind == Kind::Pawn)
+    }
+
+    /// Piece taken by a move, if any.
+    pub fn captured_by(&self, mv: Move) -> Option<Piece> {
+        match self.is_en_passant(mv) {
+            true => self.piece_at(self.en_passant_victim(mv)),
+            false => self.piece_at(mv.to),
+        }
+    }
 
-        if squares[index].piece_id.is_some() {
-            let dest_piece =
-                Piece::get_piece_from_id(pieces, squares[index].piece_id.unwrap()).unwrap();
+    /// Square of the pawn taken en passant, beside the capturing pawn.
+    fn en_passant_victim(&self, mv: Move) -> u8 {
+        let (file, _) = self.index_to_file_rank(mv.to);
+        let (_, rank) = self.index_to_file_rank(mv.from);
+        self.file_rank_to_index(file, rank)
+    }
 
-            attacking = dest_piece.color != piece.color;
+    pub(crate) fn pawn_forward(color: Color) -> (i16, i16) {
+        match color {
+            Color::White => (0, 1),
+            Color::Black => (0, -1),
         }
-        return attacking;
     }
 
-    fn is_legal(
-        pieces: &Vec<Piece>,
-        squares: &Vec<Square>,
-        piece: &Piece,
-        first: &mut bool,
-        file: i16,
-        rank: i16,
-        check_first: bool,
-    ) -> bool {
-        let mut is_legal = true;
-
-        let is_inside = Board::is_inside_board(file, rank);
-        is_legal &= is_inside;
-
-        if is_inside {
-            let index = Square::file_rank_to_index(
-                u8::try_from(file.clone()).unwrap(),
-                u8::try_from(rank.clone()).unwrap(),
-            ) as usize;
-
-            if squares[index].piece_id.is_some() {
-                let dest_piece =
-                    Piece::get_piece_from_id(pieces, squares[index].piece_id.unwrap()).unwrap();
-                if dest_piece.color == piece.color {
-                    is_legal &= false;
-                } else {
-                    if check_first {
-                        if *first {
-                            is_legal &= true;
-                            *first = false;
-                        } else {
-                            is_legal &= false;
-                        }
-                    }
+    fn is_inside_board(&self, file: i16, rank: i16) -> bool {
+        (file >= 0) & (rank >= 0) & (file < self.files as i16) & (rank < self.ranks as i16)
+    }
+
+    fn is_allied(&self, piece: &Piece, index: u8) -> bool {
+        match self.piece_at(index) {
+            Some(dest_piece) => dest_piece.color == piece.color,
+            None => false,
+        }
+    }
+
+    fn is_attacking(&self, piece: &Piece, index: u8) -> bool {
+        match self.piece_at(index) {
+            Some(dest_piece) => dest_piece.color != piece.color,
+            None => false,
+        }
+    }
+
+    /// Plays a move without checking it, it can be taken back with `unmake_move`.
+    pub fn make_move(&mut self, mv: Move) {
+        let castling = self.is_castling(mv);
+        let captured_at = match self.is_en_passant(mv) {
+            true => self.en_passant_victim(mv),
+            false => mv.to,
+        };
+        let piece = self.squares[mv.from as usize]
+            .piece
+            .take()
+            .expect("No piece to move.");
+        let captured = self.squares[captured_at as usize].piece.take();
+        let moved = match mv.promotion {
+            Some(kind) => Piece { kind, ..piece },
+            None => piece,
+        };
+        self.squares[mv.to as usize].piece = Some(moved);
+        self.history.push(Undo {
+            mv,
+            piece,
+            captured,
+            captured_at,
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+            pawn_hash: self.pawn_hash,
+            psqt: self.psqt,
+            phase: self.phase,
+        });
+        if let Some(nnue) = &mut self.nnue {
+            nnue.push();
+        }
+
+        let keys = Keys::get();
+        self.hash ^= keys.piece(piece, mv.from) ^ keys.piece(moved, mv.to);
+        self.remove_eval(piece, mv.from);
+        self.add_eval(moved, mv.to);
+        if piece.kind == Kind::Pawn {
+            self.pawn_hash ^= keys.piece(piece, mv.from);
+        }
+        if moved.kind == Kind::Pawn {
+            self.pawn_hash ^= keys.piece(moved, mv.to);
+        }
+        if let Some(captured) = captured {
+            self.hash ^= keys.piece(captured, captured_at);
+            self.remove_eval(captured, captured_at);
+            if captured.kind == Kind::Pawn {
+                self.pawn_hash ^= keys.piece(captured, captured_at);
+            }
+        }
+        if castling {
+            let (rook_from, rook_to) = self.castling_rook(mv, piece.color);
+            let rook = self.squares[rook_from as usize].piece.take();
+            self.squares[rook_to as usize].piece = rook;
+            if let Some(rook) = rook {
+                self.hash ^= keys.piece(rook, rook_from) ^ keys.piece(rook, rook_to);
+                self.remove_eval(rook, rook_from);
+                self.add_eval(rook, rook_to);
+            }
+        }
+        self.hash ^= self.castling_hash() ^ self.en_passant_hash();
+
+        // Moving a king or a rook, or capturing a rook, loses the matching castling rights
+        for color in [Color::White, Color::Black] {
+            for wing in [Wing::King, Wing::Queen] {
+                let (king, rook) = self.castling_squares(color, wing);
+                if [mv.from, mv.to]
+                    .iter()
+                    .any(|&index| index == king || index == rook)
+                {
+                    self.castling.set(color, wing, false);
                 }
             }
         }
 
-        is_legal
-    }
+        // A double step leaves the crossed square open to en passant
+        self.en_passant = None;
+        if piece.kind == Kind::Pawn {
+            let (_, from_rank) = self.index_to_file_rank(mv.from);
+            let (_, to_rank) = self.index_to_file_rank(mv.to);
+            if from_rank.abs_diff(to_rank) == 2 {
+                self.en_passant = self.offset(mv.from, Board::pawn_forward(piece.color));
+            }
+        }
 
-    pub fn move_piece(
-        self: &mut Self,
-        active_piece_id: Option<u8>,
-        pieces: &mut Vec<Piece>,
-        from_index: usize,
-        to_index: usize,
-    ) -> Result<MoveType, MoveType> {
-        let (file, rank) = Square::index_to_file_rank(from_index as u8);
-        let piece = Piece::get_piece_from_id(pieces, active_piece_id.expect("No active piece id."))
-            .expect("No piece.");
-        let legal_moves_indices: Vec<u8> = self
-            .generate_legal_moves(pieces, &piece, file, rank)
-            .iter()
-            .map(|(file, rank)| Square::file_rank_to_index(file.clone(), rank.clone()))
-            .collect();
+        self.hash ^= self.castling_hash() ^ self.en_passant_hash() ^ keys.black_to_move();
 
-        return if !legal_moves_indices.contains(&(to_index as u8)) {
-            Err(MoveType::Illegal)
-        } else if self.squares[to_index].piece_id.is_none() {
-            self.squares[to_index].piece_id = active_piece_id;
-            self.squares[from_index].piece_id = None;
-            Ok(MoveType::Empty)
+        let irreversible = captured.is_some() || piece.kind == Kind::Pawn;
+        self.halfmove_clock = if irreversible {
+            0
         } else {
-            let target_piece_id = self.squares[to_index]
-                .piece_id
-                .expect("No target piece id.");
+            self.halfmove_clock + 1
+        };
+        if self.turn == Color::Black {
+            self.fullmove_number += 1;
+        }
+        self.turn = self.turn.opponent();
+        if piece.kind == Kind::King && self.nnue.is_some() {
+            self.refresh_accumulator(piece.color);
+        }
+    }
+
+    pub fn unmake_move(&mut self) {
+        let undo = self.history.pop().expect("No move to unmake.");
+        let mv = undo.mv;
+        self.squares[mv.to as usize].piece = None;
+        self.squares[mv.from as usize].piece = Some(undo.piece);
+        self.squares[undo.captured_at as usize].piece = undo.captured;
+        if self.is_castling(mv) {
+            let (rook, rook_to) = self.castling_rook(mv, undo.piece.color);
+            self.squares[rook as usize].piece = self.squares[rook_to as usize].piece.take();
+        }
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove_clock = undo.halfmove_clock;
+        self.hash = undo.hash;
+        self.pawn_hash = undo.pawn_hash;
+        self.psqt = undo.psqt;
+        self.phase = undo.phase;
+        if let Some(nnue) = &mut self.nnue {
+            nnue.pop();
+        }
+        self.turn = self.turn.opponent();
+        if self.turn == Color::Black {
+            self.fullmove_number -= 1;
+        }
+    }
+
+    /// Passes the turn, as the search does to see if the opponent has a threat. Returns what
+    /// `unmake_null_move` needs to restore the position.
+    pub fn make_null_move(&mut self) -> NullMove {
+        let null = NullMove {
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash: self.hash,
+        };
+        self.hash ^= self.en_passant_hash() ^ Keys::get().black_to_move();
+        self.en_passant = None;
+        // Repetitions aren't looked for across a null move
+        self.halfmove_clock = 0;
+        self.turn = self.turn.opponent();
+        null
+    }
+
+    pub fn unmake_null_move(&mut self, null: NullMove) {
+        self.en_passant = null.en_passant;
+        self.halfmove_clock = null.halfmove_clock;
+        self.hash = null.hash;
+        self.turn = self.turn.opponent();
+    }
+
+    /// Squares the rook leaves and reaches when the king castles with `mv`.
+    fn castling_rook(&self, mv: Move, color: Color) -> (u8, u8) {
+        let wing = match mv.to > mv.from {
+            true => Wing::King,
+            false => Wing::Queen,
+        };
+        let (_, rook) = self.castling_squares(color, wing);
+        let (_, rook_to) = self.castling_targets(color, wing);
+        (rook, rook_to)
+    }
+
+    pub fn move_piece(&mut self, from_index: usize, to_index: usize) -> Result<MoveType, MoveType> {
+        let piece = self.squares[from_index].piece.expect("No piece.");
+        let to_piece = self.squares[to_index].piece;
 
-            self.squares[to_index].piece_id = active_piece_id;
-            self.squares[from_index].piece_id = None;
-            Ok(MoveType::Take(target_piece_id))
+        if piece.color != self.turn {
+            return Err(MoveType::OutOfTurn);
+        }
+        if to_piece.is_some_and(|to_piece| to_piece.color == piece.color) {
+            return Err(MoveType::OccupiedBySameColor);
+        }
+        // Pawns reaching the last rank are promoted to queens
+        let Some(mv) = self
+            .generate_legal_moves(from_index as u8)
+            .into_iter()
+            .find(|mv| {
+                mv.to == to_index as u8 && mv.promotion.is_none_or(|kind| kind == Kind::Queen)
+            })
+        else {
+            return Err(MoveType::Illegal);
+        };
+        let move_type = match self.captured_by(mv) {
+            Some(target_piece) => MoveType::Take(target_piece.id),
+            None => MoveType::Empty,
         };
+
+        self.make_move(mv);
+        Ok(move_type)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Reference positions with their leaf counts by depth, from the Chess Programming Wiki.
+    pub(crate) const PERFT: [(&str, &[u64]); 5] = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8_902, 197_281, 4_865_609],
+        ),
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2_039, 97_862, 4_085_603],
+        ),
+        (
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2_812, 43_238, 674_624],
+        ),
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9_467, 422_333],
+        ),
+        (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1_486, 62_379, 2_103_487],
+        ),
+    ];
+
+    fn perft(board: &mut Board, depth: u32) -> u64 {
+        let moves = board.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|mv| {
+                board.make_move(mv);
+                let nodes = perft(board, depth - 1);
+                board.unmake_move();
+                nodes
+            })
+            .sum()
+    }
+
+    fn check_perft(max_depth: usize) {
+        for (fen, counts) in PERFT {
+            let mut board = Board::new(false, 8, 8);
+            board.load_fen(fen).unwrap();
+            for (depth, &count) in counts.iter().enumerate().take(max_depth) {
+                assert_eq!(
+                    perft(&mut board, depth as u32 + 1),
+                    count,
+                    "{fen} depth {}",
+                    depth + 1
+                );
+            }
+        }
+    }
+
+    /// The incremental evaluation and hashes are those computed from scratch.
+    fn check(board: &Board) {
+        let mut fresh = board.clone();
+        fresh.refresh_eval();
+        assert_eq!((board.psqt, board.phase), (fresh.psqt, fresh.phase));
+        assert_eq!(board.hash, board.compute_hash());
+        assert_eq!(board.pawn_hash, board.compute_pawn_hash());
+    }
+
+    fn walk(board: &mut Board, depth: u32) {
+        check(board);
+        if depth == 0 {
+            return;
+        }
+        for mv in board.legal_moves() {
+            board.make_move(mv);
+            walk(board, depth - 1);
+            board.unmake_move();
+            check(board);
+        }
+    }
+
+    #[test]
+    fn incremental() {
+        for (fen, _) in PERFT {
+            let mut board = Board::new(false, 8, 8);
+            board.load_fen(fen).unwrap();
+            walk(&mut board, 3);
+        }
+    }
+
+    /// Noisy and quiet generation split the legal moves, as `is_noisy` tells them apart.
+    #[test]
+    fn targets() {
+        for (fen, _) in PERFT {
+            let mut board = Board::new(false, 8, 8);
+            board.load_fen(fen).unwrap();
+            let legality = board.legality(board.turn);
+            let noisy = board.legal_moves_of(Targets::Noisy, &legality);
+            let quiet = board.legal_moves_of(Targets::Quiet, &legality);
+            assert!(noisy.iter().all(|&mv| board.is_noisy(mv)), "{fen}");
+            assert!(quiet.iter().all(|&mv| !board.is_noisy(mv)), "{fen}");
+            let mut split: Vec<Move> = noisy.into_iter().chain(quiet).collect();
+            let mut all = board.legal_moves();
+            split.sort_by_key(|mv| (mv.from, mv.to, mv.promotion.map(|kind| kind.value())));
+            all.sort_by_key(|mv| (mv.from, mv.to, mv.promotion.map(|kind| kind.value())));
+            assert_eq!(split, all, "{fen}");
+            assert!(
+                all.iter().all(|&mv| board.is_legal_with(mv, &legality)),
+                "{fen}"
+            );
+        }
+    }
+
+    #[test]
+    fn perft_shallow() {
+        check_perft(3);
+    }
+
+    #[test]
+    #[ignore]
+    fn perft_deep() {
+        check_perft(5);
     }
 }
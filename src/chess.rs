@@ -1,24 +1,61 @@
-use crate::board::{Board, Square, FILE, RANK};
 use crate::fonts;
-use crate::moves::MoveType;
-use crate::piece::Piece;
+use crate::sprites;
 use ai_behavior::{Action, Sequence};
+use chess::bitmap::Bitmap;
+use chess::board::{Board, Square, Variant};
+use chess::endgames::{Dtm, Endgames};
+use chess::eval::Weights;
+use chess::moves::Move;
+use chess::nnue::Network;
+use chess::piece::{Color, Kind, Piece};
+use chess::polyglot::Book;
+use chess::search::{Clock, Limits, Line, Search, SearchResult, MATE, MATE_BOUND};
+use chess::skill::{Skill, MAX_ELO, MAX_LEVEL, MIN_ELO};
+use chess::syzygy::{RootProbe, Tablebases, Wdl};
 use config::Config;
 use freetype::Face;
 use graphics::math::Scalar;
-use graphics::{clear, rectangle, Transformed};
+use graphics::{clear, line, rectangle, Transformed};
 use opengl_graphics::{GlGraphics, Texture};
-use piston::input::{RenderArgs, UpdateArgs};
+use piston::input::{Key, RenderArgs, UpdateArgs};
 use sprite::{Ease, EaseFunction, MoveTo, RotateBy, RotateTo, ScaleTo, Scene};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Engine search running on its own thread so the window keeps animating.
+pub struct Thinking {
+    /// The search comes back along with its result to keep its hash table for the next move.
+    result: Receiver<(Search, SearchResult)>,
+    stop: Arc<AtomicBool>,
+    started: Instant,
+    /// Hash of the position analysed, `None` when the engine searches its own move.
+    analysed: Option<u64>,
+    /// Lowered on a ponderhit to turn pondering into the search of the engine's move.
+    pondering: Arc<AtomicBool>,
+    /// Hash of the position after the expected reply while pondering, until it is played.
+    pondered: Option<u64>,
+}
 
 pub struct Chess {
     pub config: Config,
     pub global_scale: f32,
     pub gl: GlGraphics,
     pub board: Board,
-    pub pieces: Vec<Piece>,
+    pub sprites: HashMap<u8, (Kind, Uuid)>,
+    pub engine_side: Option<Color>,
+    pub limits: Limits,
+    pub thinking: Option<Thinking>,
+    /// Idle engine, `None` while it is thinking.
+    pub search: Option<Search>,
     pub scene: Scene<Texture>,
-    pub window_size: u32,
     pub square_size: f32,
     pub x: f32,
     pub y: f32,
@@ -28,38 +65,268 @@ pub struct Chess {
     pub active_piece: Option<u8>,
     pub face: Face,
     pub last_index_clicked: Option<usize>,
+    pub show_control: bool,
+    pub show_pins: bool,
+    pub book: Option<Book>,
+    /// Plies into the game the engine keeps playing from the book.
+    pub book_depth: usize,
+    pub show_book: bool,
+    pub tablebases: Option<Arc<Tablebases>>,
+    pub show_tablebase: bool,
+    pub endgames: Option<Arc<Endgames>>,
+    /// Tablebase verdict of the position with this hash, kept while it is shown.
+    pub verdict: Option<(u64, Option<String>)>,
+    /// The engine analyses the player's positions, showing its best lines.
+    pub show_analysis: bool,
+    /// Lines of the last analysis, of the position with this hash.
+    pub analysis: Option<(u64, SearchResult)>,
+    /// Principal variations searched when analysing.
+    pub multi_pv: usize,
+    pub analysis_time: Duration,
+    /// The engine searches the expected reply on the player's time.
+    pub ponder: bool,
+    /// Width of the panel right of the board.
+    pub panel_size: f32,
+    /// Handicap of the engine when it plays, not when it analyses.
+    pub skill: Skill,
+    /// The strength menu is open, taking the arrow keys.
+    pub show_strength: bool,
 }
 
 impl Chess {
     pub fn init(&mut self) {
-        self.pieces = self.board.load_fen(
-            &mut self.scene,
-            self.config
-                .get("engine.fen")
-                .expect("Couldn't load FEN from config."),
-        );
+        let variant: String = self
+            .config
+            .get("engine.variant")
+            .expect("Couldn't find variant in config.");
+        self.board.variant =
+            Variant::from_name(&variant).expect("Unknown variant found in config.");
+        let weights: String = self
+            .config
+            .get("engine.weights")
+            .expect("Couldn't find evaluation weights in config.");
+        let weights = Weights::load(&weights).unwrap_or_else(|error| panic!("{error}"));
+        self.board.set_weights(Arc::new(weights));
+        // Optional network replacing the hand-crafted evaluation
+        if let Ok(path) = self.config.get::<String>("engine.nnue") {
+            let network = Network::load(&path).unwrap_or_else(|error| panic!("{error}"));
+            self.board
+                .set_network(Some(Arc::new(network)))
+                .unwrap_or_else(|error| panic!("{error}"));
+        }
+        if let Ok(path) = self.config.get::<String>("engine.book") {
+            self.book = Some(Book::load(&path).unwrap_or_else(|error| panic!("{error}")));
+        }
+        self.book_depth = self.config.get("engine.book_depth").unwrap_or(usize::MAX);
+        let engine_side: String = self
+            .config
+            .get("engine.side")
+            .expect("Couldn't find engine side in config.");
+        self.engine_side = match engine_side.as_str() {
+            "white" => Some(Color::White),
+            "black" => Some(Color::Black),
+            "none" => None,
+            _ => panic!("Unknown engine side found in config: {engine_side}"),
+        };
+        let hash: usize = self
+            .config
+            .get("engine.hash")
+            .expect("Couldn't find engine hash size in config.");
+        let mut search = Search::new(hash);
+        search.threads = self.config.get("engine.threads").unwrap_or(1);
+        self.ponder = self.config.get("engine.ponder").unwrap_or(false);
+        self.multi_pv = self.config.get("engine.multipv").unwrap_or(1);
+        let analysis_time: u64 = self.config.get("engine.analysis_time").unwrap_or(3000);
+        self.analysis_time = Duration::from_millis(analysis_time);
+        let default = Skill::default();
+        self.skill = Skill {
+            level: self
+                .config
+                .get("engine.skill_level")
+                .unwrap_or(default.level),
+            limit_strength: self
+                .config
+                .get("engine.limit_strength")
+                .unwrap_or(default.limit_strength),
+            elo: self.config.get("engine.elo").unwrap_or(default.elo),
+        };
+        if let Ok(paths) = self.config.get::<String>("engine.syzygy") {
+            let tablebases = Tablebases::open(&paths).unwrap_or_else(|error| panic!("{error}"));
+            println!("Found tablebases up to {} pieces", tablebases.max_pieces);
+            self.tablebases = Some(Arc::new(tablebases));
+        }
+        search.tablebases = self.tablebases.clone();
+        if let Ok(directory) = self.config.get::<String>("engine.endgames") {
+            let (files, ranks) = (self.board.files, self.board.ranks);
+            let endgames = Endgames::load(Path::new(&directory), files, ranks)
+                .unwrap_or_else(|error| panic!("{error}"));
+            if !endgames.is_empty() {
+                println!("Loaded {} endgame tables", endgames.len());
+                self.endgames = Some(Arc::new(endgames));
+            }
+        }
+        search.endgames = self.endgames.clone();
+        // Optional `[search]` section overriding the selective search defaults
+        if let Ok(table) = self.config.get_table("search") {
+            for (name, value) in table {
+                let value = value
+                    .into_int()
+                    .unwrap_or_else(|_| panic!("Search parameter {name} isn't a number."));
+                search
+                    .params
+                    .set(&name, value)
+                    .unwrap_or_else(|error| panic!("{error}"));
+            }
+        }
+        self.search = Some(search);
+        self.new_game();
+    }
+
+    /// Sets up the configured position and lets the engine forget the previous game.
+    pub fn new_game(&mut self) {
+        if let Some(thinking) = self.thinking.take() {
+            thinking.stop.store(true, Ordering::Relaxed);
+            thinking.pondering.store(false, Ordering::Relaxed);
+            let (search, _) = thinking.result.recv().expect("Engine search thread died.");
+            self.search = Some(search);
+        }
+        if let Some(search) = &mut self.search {
+            search.clear();
+        }
+        self.analysis = None;
+        let fen: String = self
+            .config
+            .get("engine.fen")
+            .expect("Couldn't load FEN from config.");
+        if let Err(errors) = self.board.load_fen(&fen) {
+            for error in errors.iter() {
+                eprintln!("Invalid position: {error}");
+            }
+            panic!("Couldn't load FEN from config, the position is invalid.");
+        }
+        self.limits = self.read_limits();
+        for (_, (_, sprite_id)) in self.sprites.drain() {
+            self.scene.remove_child(sprite_id);
+        }
+        self.active_piece = None;
+        self.sync_sprites();
+    }
+
+    /// Search limits from the `[engine]` section, times are in milliseconds.
+    fn read_limits(&self) -> Limits {
+        let get = |key: &str| self.config.get::<u64>(&format!("engine.{key}")).ok();
+        let clock = get("clock").map(|clock| Clock {
+            remaining: Duration::from_millis(clock),
+            increment: Duration::from_millis(get("increment").unwrap_or(0)),
+        });
+        Limits {
+            depth: get("depth").map(|depth| depth as u32),
+            nodes: get("nodes"),
+            movetime: get("movetime").map(Duration::from_millis),
+            clock,
+        }
+    }
+
+    /// Matches the sprites with the pieces on the board: drops the captured ones, adds the
+    /// missing ones and swaps those of promoted pawns.
+    pub fn sync_sprites(&mut self) {
+        let on_board: HashMap<u8, (u8, Piece)> = self
+            .board
+            .pieces()
+            .map(|(index, piece)| (piece.id, (index, piece)))
+            .collect();
+        self.sprites.retain(|id, (kind, sprite_id)| {
+            let kept = on_board
+                .get(id)
+                .is_some_and(|(_, piece)| piece.kind == *kind);
+            if !kept {
+                self.scene.remove_child(*sprite_id);
+            }
+            kept
+        });
+        for (index, piece) in on_board.into_values() {
+            if self.sprites.contains_key(&piece.id) {
+                continue;
+            }
+            let sprite_id = sprites::new_piece_sprite(piece.kind, piece.color, &mut self.scene);
+            let (file, rank) = self.board.index_to_file_rank(index);
+            let (x, y) = self.board.file_rank_to_xy(file, rank, self.square_size);
+            let child = self.scene.child_mut(sprite_id).unwrap();
+            child.set_position(x.into(), y.into());
+            self.sprites.insert(piece.id, (piece.kind, sprite_id));
+        }
+    }
+
+    /// Squares shown to the player, `None` when the whole board is visible.
+    pub fn visible_squares(&mut self) -> Option<Bitmap> {
+        match (self.board.variant, self.board.winner()) {
+            (Variant::FogOfWar, None) => Some(self.board.visibility(self.viewer())),
+            _ => None,
+        }
+    }
+
+    /// Side the board is shown to: the player facing the engine, or the side to move when
+    /// players take turns in front of the screen.
+    pub fn viewer(&self) -> Color {
+        match self.engine_side {
+            Some(side) => side.opponent(),
+            None => self.board.turn,
+        }
     }
 
     pub fn render(&mut self, args: &RenderArgs) {
         self.render_board(args);
+        if self.show_control {
+            self.render_control(args);
+        }
+        if self.show_pins {
+            self.render_pins(args);
+        }
+        if self.show_book {
+            self.render_book(args);
+        }
+        if self.show_tablebase {
+            self.render_tablebase(args);
+        }
+        self.render_panel(args);
+        if self.show_strength {
+            self.render_strength(args);
+        }
 
         if self.active_piece.is_some() {
-            let (file, rank) = Square::index_to_file_rank(self.last_index_clicked.unwrap() as u8);
-            let piece = Piece::get_piece_from_id(&self.pieces, self.active_piece.unwrap()).unwrap();
+            // Captures losing material in the exchange are told apart, unless hidden by fog
+            let fog = self.board.variant == Variant::FogOfWar;
             let legal_moves = self
                 .board
-                .generate_legal_moves(&self.pieces, &piece, file, rank);
+                .generate_legal_moves(self.last_index_clicked.unwrap() as u8)
+                .iter()
+                .map(|mv| {
+                    let (file, rank) = self.board.index_to_file_rank(mv.to);
+                    let losing = !fog
+                        && self.board.captured_by(*mv).is_some()
+                        && self.board.see(mv.from, mv.to) < 0;
+                    (file, rank, losing)
+                })
+                .collect();
             self.render_legal_moves(legal_moves, args);
         }
         self.render_pieces(args);
+        if self.show_analysis {
+            self.render_arrows(args);
+        }
     }
 
     pub fn render_board(&mut self, args: &RenderArgs) {
-        let mut font_scale = self
+        let mut font_scale: f64 = self
             .config
             .get("render.font_scale")
             .expect("Couldn't find font_scale in config.");
         font_scale = (font_scale as f32 * self.global_scale) as f64;
+        let (left_file, bottom_rank) = match self.board.flip {
+            true => (self.board.files - 1, self.board.ranks - 1),
+            false => (0, 0),
+        };
+        let visible = self.visible_squares();
 
         self.gl.draw(args.viewport(), |c, gl| {
             let mut glyphs;
@@ -70,9 +337,8 @@ impl Chess {
 
             // Draw board squares
             for s in self.board.squares.iter() {
-                let (rank, file) = s.file_rank();
-                let (x, y) =
-                    Square::file_rank_to_xy(file, rank, self.square_size, self.window_size as f32);
+                let (file, rank) = s.file_rank();
+                let (x, y) = self.board.file_rank_to_xy(file, rank, self.square_size);
                 let transform = c.transform.trans(
                     (x - self.square_size / 2.0).into(),
                     (y - self.square_size / 2.0).into(),
@@ -84,8 +350,19 @@ impl Chess {
                     gl,
                 );
 
-                if file == 0 {
-                    glyphs = fonts::glyphs(&mut self.face, format!("{} ", RANK[rank as usize]));
+                // Shade squares hidden by the fog
+                let index = self.board.file_rank_to_index(file, rank);
+                if visible.is_some_and(|visible| !visible.get(index)) {
+                    rectangle(
+                        [0.0, 0.0, 0.0, 0.6],
+                        rectangle::square(0.0, 0.0, self.square_size.into()),
+                        transform,
+                        gl,
+                    );
+                }
+
+                if file == left_file {
+                    glyphs = fonts::glyphs(&mut self.face, format!("{} ", Square::rank_name(rank)));
                     glyph_offset = (-self.square_size * 0.45, -self.square_size * 0.20);
                     fonts::render_text(
                         &glyphs,
@@ -94,8 +371,8 @@ impl Chess {
                         gl,
                     );
                 }
-                if rank == 0 {
-                    glyphs = fonts::glyphs(&mut self.face, format!("{} ", FILE[file as usize]));
+                if rank == bottom_rank {
+                    glyphs = fonts::glyphs(&mut self.face, format!("{} ", Square::file_name(file)));
                     glyph_offset = (self.square_size * 0.30, self.square_size * 0.45);
                     fonts::render_text(
                         &glyphs,
@@ -109,103 +386,434 @@ impl Chess {
     }
 
     pub fn render_pieces(&mut self, args: &RenderArgs) {
-        let mut piece_scale = self
+        let mut piece_scale: f64 = self
             .config
             .get("render.piece_scale")
             .expect("Couldn't find piece_scale in config.");
         piece_scale = (piece_scale as f32 * self.global_scale) as f64;
+        let visible = self.visible_squares();
+        let viewer = self.viewer();
         let mut active_piece_sprite_id = None;
         self.gl.draw(args.viewport(), |c, gl| {
             if let Some(active_piece_id) = self.active_piece {
-                for p in self.pieces.iter() {
-                    if p.id == active_piece_id {
-                        active_piece_sprite_id = Some(p.sprite_id);
-                    }
-                }
+                active_piece_sprite_id = self.sprites.get(&active_piece_id).map(|(_, id)| *id);
                 let child = self
                     .scene
                     .child_mut(active_piece_sprite_id.expect("No sprite id."))
                     .expect("No child for specified uuid.");
                 child.set_position(self.x.into(), self.y.into());
             } else {
-                for square in self.board.squares.iter() {
-                    if let Some(piece_id) = square.piece_id {
-                        let mut active_piece_sprite_id = None;
-                        for p in self.pieces.iter() {
-                            if p.id == piece_id {
-                                active_piece_sprite_id = Some(p.sprite_id);
-                                let child = self
-                                    .scene
-                                    .child_mut(active_piece_sprite_id.expect("No sprite id."))
-                                    .expect("No child for specified uuid.");
-                                let (file, rank) = square.file_rank();
-                                let (x, y) = Square::file_rank_to_xy(
-                                    file,
-                                    rank,
-                                    self.square_size,
-                                    self.window_size as f32,
-                                );
-
-                                let (cx, cy) = child.get_position();
-                                if (cx as f32, cy as f32) != (x, y) {
-                                    if self
-                                        .scene
-                                        .running_for_child(active_piece_sprite_id.unwrap())
-                                        == Some(0)
-                                    {
-                                        let tran = Action(Ease(
-                                            EaseFunction::ExponentialOut,
-                                            Box::new(MoveTo(0.1, x as Scalar, y as Scalar)),
-                                        ));
-
-                                        let rotate = Sequence(vec![Action(Ease(
-                                            EaseFunction::BounceInOut,
-                                            Box::new(RotateTo(0.05, 0.0)),
-                                        ))]);
-
-                                        let scale = Sequence(vec![Action(Ease(
-                                            EaseFunction::ExponentialOut,
-                                            Box::new(ScaleTo(0.2, piece_scale, piece_scale)),
-                                        ))]);
-
-                                        self.scene.run(active_piece_sprite_id.unwrap(), &tran);
-                                        self.scene.run(active_piece_sprite_id.unwrap(), &rotate);
-                                        self.scene.run(active_piece_sprite_id.unwrap(), &scale);
-                                    }
-                                }
-                            }
-                        }
+                for (index, piece) in self.board.pieces() {
+                    let (_, sprite_id) = *self.sprites.get(&piece.id).expect("No sprite id.");
+                    let child = self
+                        .scene
+                        .child_mut(sprite_id)
+                        .expect("No child for specified uuid.");
+
+                    // Enemy pieces are only drawn on squares the viewer can see
+                    child
+                        .set_visible(piece.color == viewer || visible.is_none_or(|v| v.get(index)));
+
+                    let (file, rank) = self.board.index_to_file_rank(index);
+                    let (x, y) = self.board.file_rank_to_xy(file, rank, self.square_size);
+
+                    let (cx, cy) = child.get_position();
+                    if (cx as f32, cy as f32) != (x, y)
+                        && self.scene.running_for_child(sprite_id) == Some(0)
+                    {
+                        let tran = Action(Ease(
+                            EaseFunction::ExponentialOut,
+                            Box::new(MoveTo(0.1, x as Scalar, y as Scalar)),
+                        ));
+
+                        let rotate = Sequence(vec![Action(Ease(
+                            EaseFunction::BounceInOut,
+                            Box::new(RotateTo(0.05, 0.0)),
+                        ))]);
+
+                        let scale = Sequence(vec![Action(Ease(
+                            EaseFunction::ExponentialOut,
+                            Box::new(ScaleTo(0.2, piece_scale, piece_scale)),
+                        ))]);
+
+                        self.scene.run(sprite_id, &tran);
+                        self.scene.run(sprite_id, &rotate);
+                        self.scene.run(sprite_id, &scale);
                     }
                 }
             };
             for child in self.scene.children() {
                 child.draw(c.transform, gl);
             }
-            if active_piece_sprite_id.is_some() {
-                let child = self
-                    .scene
-                    .child_mut(active_piece_sprite_id.unwrap())
-                    .unwrap();
+            if let Some(active_piece_sprite_id) = active_piece_sprite_id {
+                let child = self.scene.child_mut(active_piece_sprite_id).unwrap();
                 child.draw(c.transform, gl)
             }
         });
     }
 
-    pub fn render_legal_moves(&mut self, legal_moves: Vec<(u8, u8)>, args: &RenderArgs) {
+    /// Tints squares by which side attacks them, contested squares stand out.
+    pub fn render_control(&mut self, args: &RenderArgs) {
+        let visible = self.visible_squares();
+        let white = self.board.attack_map(Color::White);
+        let black = self.board.attack_map(Color::Black);
+        self.gl.draw(args.viewport(), |c, gl| {
+            for s in self.board.squares.iter() {
+                let (file, rank) = s.file_rank();
+                let index = self.board.file_rank_to_index(file, rank);
+                if visible.is_some_and(|visible| !visible.get(index)) {
+                    continue;
+                }
+                let color = match (white.get(index), black.get(index)) {
+                    (true, true) => [1.0, 0.6, 0.0, 0.45],
+                    (true, false) => [1.0, 1.0, 1.0, 0.25],
+                    (false, true) => [0.0, 0.0, 0.0, 0.25],
+                    (false, false) => continue,
+                };
+                let (x, y) = self.board.file_rank_to_xy(file, rank, self.square_size);
+                let transform = c.transform.trans(
+                    (x - self.square_size / 2.0).into(),
+                    (y - self.square_size / 2.0).into(),
+                );
+                rectangle(
+                    color,
+                    rectangle::square(0.0, 0.0, self.square_size.into()),
+                    transform,
+                    gl,
+                );
+            }
+        });
+    }
+
+    /// Highlights pinned pieces and the line they are pinned along.
+    pub fn render_pins(&mut self, args: &RenderArgs) {
+        let visible = self.visible_squares();
+        let mut pins = self.board.pins(Color::White);
+        pins.extend(self.board.pins(Color::Black));
+        self.gl.draw(args.viewport(), |c, gl| {
+            for pin in pins.iter() {
+                for index in pin.ray.iter().chain([pin.pinned]) {
+                    if visible.is_some_and(|visible| !visible.get(index)) {
+                        continue;
+                    }
+                    let color = match index == pin.pinned {
+                        true => [1.0, 0.9, 0.0, 0.6],
+                        false => [1.0, 0.9, 0.0, 0.25],
+                    };
+                    let (file, rank) = self.board.index_to_file_rank(index);
+                    let (x, y) = self.board.file_rank_to_xy(file, rank, self.square_size);
+                    let transform = c.transform.trans(
+                        (x - self.square_size / 2.0).into(),
+                        (y - self.square_size / 2.0).into(),
+                    );
+                    rectangle(
+                        color,
+                        rectangle::square(0.0, 0.0, self.square_size.into()),
+                        transform,
+                        gl,
+                    );
+                }
+            }
+        });
+    }
+
+    /// Marks the book moves of the position with their share of the book weights.
+    pub fn render_book(&mut self, args: &RenderArgs) {
+        let Some(book) = &self.book else {
+            return;
+        };
+        let moves = book.moves(&mut self.board);
+        let total: u32 = moves.iter().map(|book_move| book_move.weight as u32).sum();
+        let mut font_scale: f64 = self
+            .config
+            .get("render.font_scale")
+            .expect("Couldn't find font_scale in config.");
+        font_scale = (font_scale as f32 * self.global_scale) as f64;
+        self.gl.draw(args.viewport(), |c, gl| {
+            for book_move in moves.iter() {
+                for (index, alpha) in [(book_move.mv.from, 0.2), (book_move.mv.to, 0.45)] {
+                    let (file, rank) = self.board.index_to_file_rank(index);
+                    let (x, y) = self.board.file_rank_to_xy(file, rank, self.square_size);
+                    let transform = c.transform.trans(
+                        (x - self.square_size / 2.0).into(),
+                        (y - self.square_size / 2.0).into(),
+                    );
+                    rectangle(
+                        [0.0, 0.4, 1.0, alpha],
+                        rectangle::square(0.0, 0.0, self.square_size.into()),
+                        transform,
+                        gl,
+                    );
+                }
+                let share = match total {
+                    0 => 0,
+                    total => book_move.weight as u32 * 100 / total,
+                };
+                let (file, rank) = self.board.index_to_file_rank(book_move.mv.to);
+                let (x, y) = self.board.file_rank_to_xy(file, rank, self.square_size);
+                let glyphs = fonts::glyphs(&mut self.face, format!("{share}%"));
+                fonts::render_text(
+                    &glyphs,
+                    &c.trans(
+                        (x - self.square_size * 0.4).into(),
+                        (y - self.square_size * 0.2).into(),
+                    )
+                    .scale(font_scale, font_scale),
+                    gl,
+                );
+            }
+        });
+    }
+
+    /// Writes what the endgame tables or the tablebases know of the position in the corner
+    /// of the board.
+    pub fn render_tablebase(&mut self, args: &RenderArgs) {
+        if self
+            .verdict
+            .as_ref()
+            .is_none_or(|(hash, _)| *hash != self.board.hash)
+        {
+            let dtm = self
+                .endgames
+                .as_ref()
+                .and_then(|endgames| endgames.probe(&self.board));
+            let probe = self
+                .tablebases
+                .as_ref()
+                .filter(|_| dtm.is_none())
+                .and_then(|tablebases| tablebases.probe_root(&mut self.board));
+            let description = match (dtm, probe) {
+                (Some(dtm), _) => Some(Chess::describe_dtm(dtm)),
+                (None, probe) => probe.map(|probe| Chess::describe_tablebase(&probe)),
+            };
+            let text = description.map(|text| format!("{:?}: {text}", self.board.turn));
+            self.verdict = Some((self.board.hash, text));
+        }
+        let Some((_, Some(text))) = &self.verdict else {
+            return;
+        };
+        let mut font_scale: f64 = self
+            .config
+            .get("render.font_scale")
+            .expect("Couldn't find font_scale in config.");
+        font_scale = (font_scale as f32 * self.global_scale) as f64;
+        let glyphs = fonts::glyphs(&mut self.face, text.clone());
+        let (x, y) = (self.square_size * 0.1, self.square_size * 0.3);
+        self.gl.draw(args.viewport(), |c, gl| {
+            fonts::render_text(
+                &glyphs,
+                &c.trans(x.into(), y.into()).scale(font_scale, font_scale),
+                gl,
+            );
+        });
+    }
+
+    /// Lines of the current analysis, best first.
+    fn analysis_lines(&self) -> &[Line] {
+        match &self.analysis {
+            Some((hash, result)) if *hash == self.board.hash => &result.lines,
+            _ => &[],
+        }
+    }
+
+    /// Lists the analysis lines in the panel, each wrapped to its width.
+    pub fn render_panel(&mut self, args: &RenderArgs) {
+        if self.panel_size <= 0.0 {
+            return;
+        }
+        let mut font_scale: f64 = self
+            .config
+            .get("render.font_scale")
+            .expect("Couldn't find font_scale in config.");
+        font_scale = (font_scale as f32 * self.global_scale) as f64;
+        // The face renders glyphs 100 pixels high, about half as wide
+        let line_height = 100.0 * font_scale * 1.2;
+        let columns = (self.panel_size as f64 / (50.0 * font_scale)) as usize;
+
+        let mut rows = vec![match &self.analysis {
+            _ if !self.show_analysis => "Press A to analyse".to_string(),
+            Some((hash, result)) if *hash == self.board.hash => {
+                format!("Depth {}, {} nodes", result.depth, result.nodes)
+            }
+            _ => "Analysing...".to_string(),
+        }];
+        for (rank, line) in self.analysis_lines().iter().enumerate() {
+            let mut words = vec![format!("{}.", rank + 1), Chess::describe_score(line.score)];
+            words.extend(line.moves.iter().map(|mv| self.board.move_name(*mv)));
+            let mut row = String::new();
+            for word in words {
+                if !row.is_empty() && row.len() + 1 + word.len() > columns {
+                    rows.push(std::mem::take(&mut row));
+                    row.push_str("   ");
+                } else if !row.is_empty() {
+                    row.push(' ');
+                }
+                row.push_str(&word);
+            }
+            rows.push(row);
+        }
+
+        let left = self.board.files as f32 * self.square_size;
+        let height = self.board.ranks as f32 * self.square_size;
+        let glyphs: Vec<_> = rows
+            .into_iter()
+            .map(|row| fonts::glyphs(&mut self.face, row))
+            .collect();
+        self.gl.draw(args.viewport(), |c, gl| {
+            rectangle(
+                [0.15, 0.15, 0.15, 1.0],
+                [left.into(), 0.0, self.panel_size.into(), height.into()],
+                c.transform,
+                gl,
+            );
+            for (row, glyphs) in glyphs.iter().enumerate() {
+                let y = line_height * (row + 1) as f64;
+                fonts::render_text(
+                    glyphs,
+                    &c.trans(left as f64 + line_height * 0.3, y)
+                        .scale(font_scale, font_scale),
+                    gl,
+                );
+            }
+        });
+    }
+
+    /// Draws the first move of each analysis line, fainter for the worse ones.
+    pub fn render_arrows(&mut self, args: &RenderArgs) {
+        let moves: Vec<Move> = self
+            .analysis_lines()
+            .iter()
+            .map(|line| line.moves[0])
+            .collect();
+        let center = |index: u8| {
+            let (file, rank) = self.board.index_to_file_rank(index);
+            let (x, y) = self.board.file_rank_to_xy(file, rank, self.square_size);
+            (x as f64, y as f64)
+        };
+        let radius = self.square_size as f64 * 0.06;
         self.gl.draw(args.viewport(), |c, gl| {
-            for (file, rank) in legal_moves.iter() {
-                let (x, y) = Square::file_rank_to_xy(
-                    file.clone(),
-                    rank.clone(),
-                    self.square_size,
-                    self.window_size as f32,
+            for (rank, mv) in moves.iter().enumerate().rev() {
+                let alpha = 0.8 / (rank + 1) as f32;
+                let (from, to) = (center(mv.from), center(mv.to));
+                line::Line::new([1.0, 0.5, 0.0, alpha], radius).draw_arrow(
+                    [from.0, from.1, to.0, to.1],
+                    radius * 4.0,
+                    &c.draw_state,
+                    c.transform,
+                    gl,
                 );
+            }
+        });
+    }
+
+    /// Menu of the engine strength settings, over the top of the board.
+    pub fn render_strength(&mut self, args: &RenderArgs) {
+        let level = match self.skill.limit_strength {
+            true => format!("{:.1}, from Elo", self.skill.effective_level()),
+            false => self.skill.level.to_string(),
+        };
+        let limit = match self.skill.limit_strength {
+            true => "on",
+            false => "off",
+        };
+        let rows = [
+            "Engine strength (S to close)".to_string(),
+            format!("Skill level: {level}  (Up/Down)"),
+            format!("Limit strength: {limit}  (L)"),
+            format!("Elo: {}  (Left/Right)", self.skill.elo),
+        ];
+        let mut font_scale: f64 = self
+            .config
+            .get("render.font_scale")
+            .expect("Couldn't find font_scale in config.");
+        font_scale = (font_scale as f32 * self.global_scale) as f64;
+        let line_height = 100.0 * font_scale * 1.2;
+        let width = self.board.files as f64 * self.square_size as f64;
+        let glyphs: Vec<_> = rows
+            .into_iter()
+            .map(|row| fonts::glyphs(&mut self.face, row))
+            .collect();
+        self.gl.draw(args.viewport(), |c, gl| {
+            rectangle(
+                [0.0, 0.0, 0.0, 0.8],
+                [0.0, 0.0, width, line_height * (glyphs.len() as f64 + 0.5)],
+                c.transform,
+                gl,
+            );
+            for (row, glyphs) in glyphs.iter().enumerate() {
+                let y = line_height * (row + 1) as f64;
+                fonts::render_text(
+                    glyphs,
+                    &c.trans(line_height * 0.3, y).scale(font_scale, font_scale),
+                    gl,
+                );
+            }
+        });
+    }
+
+    /// Changes the engine strength from the menu, for the engine's next move.
+    fn adjust_strength(&mut self, key: Key) {
+        let skill = &mut self.skill;
+        match key {
+            Key::Up => skill.level = (skill.level + 1).min(MAX_LEVEL),
+            Key::Down => skill.level = skill.level.saturating_sub(1),
+            Key::L => skill.limit_strength = !skill.limit_strength,
+            Key::Right => skill.elo = (skill.elo + 100).min(MAX_ELO),
+            Key::Left => skill.elo = skill.elo.saturating_sub(100).max(MIN_ELO),
+            _ => {}
+        }
+    }
+
+    /// Score in pawns for the side to move, or the moves to a forced mate.
+    fn describe_score(score: i32) -> String {
+        match score {
+            score if score >= MATE_BOUND => format!("#{}", (MATE - score + 1) / 2),
+            score if score <= -MATE_BOUND => format!("#-{}", (MATE + score) / 2),
+            score => format!("{:+.2}", score as f64 / 100.0),
+        }
+    }
+
+    /// Endgame table result for the side to move, mates counted in moves.
+    fn describe_dtm(dtm: Dtm) -> String {
+        match dtm {
+            Dtm::Win(plies) => format!("mate in {}", plies.div_ceil(2)),
+            Dtm::Draw => "endgame table draw".to_string(),
+            Dtm::Loss(plies) => format!("mated in {}", plies / 2),
+        }
+    }
+
+    /// Tablebase result for the side to move, the distance counted in plies to the next
+    /// capture or pawn move.
+    fn describe_tablebase(probe: &RootProbe) -> String {
+        match probe.wdl {
+            Wdl::Win => format!("tablebase win in {}", probe.dtz),
+            Wdl::CursedWin => format!(
+                "tablebase win in {}, too late to beat the fifty-move rule",
+                probe.dtz
+            ),
+            Wdl::Draw => "tablebase draw".to_string(),
+            Wdl::BlessedLoss => format!(
+                "tablebase loss in {}, saved by the fifty-move rule",
+                -probe.dtz
+            ),
+            Wdl::Loss => format!("tablebase loss in {}", -probe.dtz),
+        }
+    }
+
+    pub fn render_legal_moves(&mut self, legal_moves: Vec<(u8, u8, bool)>, args: &RenderArgs) {
+        self.gl.draw(args.viewport(), |c, gl| {
+            for (file, rank, losing) in legal_moves.iter() {
+                let (x, y) = self.board.file_rank_to_xy(*file, *rank, self.square_size);
                 let transform = c.transform.trans(
                     (x - self.square_size / 2.0).into(),
                     (y - self.square_size / 2.0).into(),
                 );
+                let color = match losing {
+                    true => [0.5, 0.0, 0.5, 0.5],
+                    false => [1.0, 0.0, 0.0, 0.5],
+                };
                 rectangle(
-                    [255.0 / 255.0, 0.0 / 255.0, 0.0 / 255.0, 0.5],
+                    color,
                     rectangle::square(0.0, 0.0, self.square_size.into()),
                     transform,
                     gl,
@@ -214,32 +822,49 @@ impl Chess {
         });
     }
 
-    pub fn update(&mut self, args: &UpdateArgs) {
+    pub fn key_pressed(&mut self, key: Key) {
+        match key {
+            Key::C => self.show_control = !self.show_control,
+            Key::P => self.show_pins = !self.show_pins,
+            Key::B => self.show_book = !self.show_book,
+            Key::T => self.show_tablebase = !self.show_tablebase,
+            Key::A => self.show_analysis = !self.show_analysis,
+            Key::S => self.show_strength = !self.show_strength,
+            key if self.show_strength => self.adjust_strength(key),
+            Key::N => self.new_game(),
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, _args: &UpdateArgs) {
         if self.click {
-            let index_clicked =
-                Square::xy_to_index(self.x, self.y, self.square_size, self.window_size as f32)
-                    as usize;
+            let index_clicked = self
+                .board
+                .xy_to_index(self.x, self.y, self.square_size)
+                .map(|index| index as usize);
+            // Only the player to move can pick up its pieces, and not once the game is over
+            let game_over = self.board.winner().is_some();
+            let engine_turn = self.engine_side == Some(self.board.turn);
+            let id_clicked = index_clicked
+                .and_then(|index| self.board.squares[index].piece)
+                .filter(|piece| piece.color == self.board.turn && !engine_turn && !game_over)
+                .map(|piece| piece.id);
 
-            if let Some(id_clicked) = self.board.squares[index_clicked].piece_id {
+            if let Some(id_clicked) = id_clicked {
                 // Where the piece was picked
-                self.last_index_clicked = Some(index_clicked);
+                self.last_index_clicked = index_clicked;
 
                 // Which piece
                 self.active_piece = Some(id_clicked);
 
                 // Graphics, animation
-                let mut active_piece_sprite_id = None;
-                let mut piece_scale = self
+                let active_piece_sprite_id = self.sprites.get(&id_clicked).map(|(_, id)| *id);
+                let mut piece_scale: f64 = self
                     .config
                     .get("render.piece_scale")
                     .expect("Couldn't find piece_scale in config.");
 
                 piece_scale = (piece_scale as f32 * self.global_scale) as f64;
-                for p in self.pieces.iter() {
-                    if p.id == self.active_piece.unwrap() {
-                        active_piece_sprite_id = Some(p.sprite_id);
-                    }
-                }
 
                 let scale = Sequence(vec![Action(Ease(
                     EaseFunction::ExponentialOut,
@@ -257,31 +882,224 @@ impl Chess {
             self.click = false;
         }
         if self.release {
-            if self.active_piece.is_some() {
-                let index_released =
-                    Square::xy_to_index(self.x, self.y, self.square_size, self.window_size as f32)
-                        as usize;
-
+            let index_released = self.board.xy_to_index(self.x, self.y, self.square_size);
+            if let (Some(_), Some(index_released)) = (self.active_piece, index_released) {
                 let last_index = self
                     .last_index_clicked
                     .expect("Couldn't read last index clicked.");
 
-                let move_result = self.board.move_piece(
-                    self.active_piece,
-                    &mut self.pieces,
-                    last_index,
-                    index_released,
-                );
+                let move_result = self.board.move_piece(last_index, index_released as usize);
                 println!("{:?}", move_result);
-                if let Ok(MoveType::Take(piece_id)) = move_result {
-                    let sprite_id = Piece::find_sprite_id_for_id(&self.pieces, piece_id);
-                    self.scene.remove_child(sprite_id.unwrap());
-                    Piece::remove_id_from_pieces(&mut self.pieces, piece_id);
+                self.sync_sprites();
+                if let Some(winner) = self.board.winner() {
+                    println!("{:?} wins", winner);
                 }
             }
 
             self.active_piece = None;
             self.release = false;
         }
+
+        // An analysis is dropped once its position is left or analysis is turned off
+        let engine_turn = self.engine_side == Some(self.board.turn);
+        if let Some(thinking) = &mut self.thinking {
+            let stale = thinking
+                .analysed
+                .is_some_and(|hash| hash != self.board.hash || !self.show_analysis);
+            if stale {
+                thinking.stop.store(true, Ordering::Relaxed);
+            }
+            // Once the player has moved, pondering goes on as the engine's search if the
+            // expected reply was played, and is dropped otherwise
+            if let Some(hash) = thinking.pondered.filter(|_| engine_turn) {
+                if hash == self.board.hash {
+                    println!("Ponderhit");
+                    thinking.pondered = None;
+                    thinking.started = Instant::now();
+                    thinking.pondering.store(false, Ordering::Relaxed);
+                } else {
+                    thinking.stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        // The engine starts thinking once the player has let go of the piece
+        if self.active_piece.is_none() && self.thinking.is_none() {
+            let analysed = self
+                .analysis
+                .as_ref()
+                .is_some_and(|(hash, _)| *hash == self.board.hash);
+            // Fog of war would be lifted by the engine's lines
+            let fog = self.board.variant == Variant::FogOfWar;
+            if engine_turn {
+                self.start_thinking(false);
+            } else if self.show_analysis && !analysed && !fog {
+                self.start_thinking(true);
+            }
+        }
+        self.poll_thinking();
+    }
+
+    /// Starts the engine on its move, or on analysing the position for the player.
+    fn start_thinking(&mut self, analysis: bool) {
+        if self.board.winner().is_some() {
+            return;
+        }
+        if let Some(mv) = self.book_move().filter(|_| !analysis) {
+            println!("Engine plays {} from book", self.board.move_name(mv));
+            self.board.make_move(mv);
+            self.sync_sprites();
+            return;
+        }
+        let thinking = match analysis {
+            true => {
+                let limits = Limits {
+                    movetime: Some(self.analysis_time),
+                    ..Limits::default()
+                };
+                Thinking {
+                    analysed: Some(self.board.hash),
+                    ..self.spawn_search(self.board.clone(), limits, self.multi_pv, Skill::default())
+                }
+            }
+            false => self.spawn_search(self.board.clone(), self.limits, 1, self.skill),
+        };
+        self.thinking = Some(thinking);
+    }
+
+    /// Searches the position after `reply`, the player's expected move, until the player
+    /// moves.
+    fn start_pondering(&mut self, reply: Move) {
+        let mut board = self.board.clone();
+        if self.board.winner().is_some() || !board.legal_moves().contains(&reply) {
+            return;
+        }
+        board.make_move(reply);
+        let hash = board.hash;
+        let search = self.search.as_mut().expect("Engine is already thinking.");
+        search.pondering.store(true, Ordering::Relaxed);
+        let thinking = self.spawn_search(board, self.limits, 1, self.skill);
+        self.thinking = Some(Thinking {
+            pondered: Some(hash),
+            ..thinking
+        });
+    }
+
+    /// Runs the idle engine on `board` on its own thread.
+    fn spawn_search(
+        &mut self,
+        mut board: Board,
+        limits: Limits,
+        multi_pv: usize,
+        skill: Skill,
+    ) -> Thinking {
+        let (sender, receiver) = mpsc::channel();
+        let mut search = self.search.take().expect("Engine is already thinking.");
+        search.multi_pv = multi_pv;
+        search.skill = skill;
+        // Lowered here rather than on the search thread, a stop sent before it runs still counts
+        search.stop.store(false, Ordering::Relaxed);
+        let stop = search.stop.clone();
+        let pondering = search.pondering.clone();
+        thread::spawn(move || {
+            let result = search.search(&mut board, &limits);
+            // The receiver is gone if the game was closed meanwhile
+            let _ = sender.send((search, result));
+        });
+        Thinking {
+            result: receiver,
+            stop,
+            started: Instant::now(),
+            analysed: None,
+            pondering,
+            pondered: None,
+        }
+    }
+
+    /// Move picked from the book while the game is young enough.
+    fn book_move(&mut self) -> Option<Move> {
+        if self.board.history.len() >= self.book_depth {
+            return None;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        self.book.as_ref()?.choose(&mut self.board, random)
+    }
+
+    /// Plays the engine move once its search is over.
+    fn poll_thinking(&mut self) {
+        let Some(thinking) = &self.thinking else {
+            return;
+        };
+        let result = match thinking.result.try_recv() {
+            Ok((search, result)) => {
+                self.search = Some(search);
+                result
+            }
+            Err(TryRecvError::Empty) => return,
+            Err(TryRecvError::Disconnected) => panic!("Engine search thread died."),
+        };
+        let elapsed = thinking.started.elapsed();
+        // Stopped or given up before the player moved
+        if thinking.pondered.is_some() {
+            thinking.pondering.store(false, Ordering::Relaxed);
+            self.thinking = None;
+            return;
+        }
+        if let Some(hash) = thinking.analysed {
+            self.thinking = None;
+            // Dropped when the player moved meanwhile
+            if hash == self.board.hash {
+                self.analysis = Some((hash, result));
+            }
+            return;
+        }
+        self.thinking = None;
+        if let Some(clock) = &mut self.limits.clock {
+            clock.remaining = clock.remaining.saturating_sub(elapsed) + clock.increment;
+        }
+
+        if let Some(mv) = result.best_move {
+            match (result.endgame, result.tablebase) {
+                (Some(dtm), _) => println!(
+                    "Engine plays {} from endgame table ({})",
+                    self.board.move_name(mv),
+                    Chess::describe_dtm(dtm)
+                ),
+                (None, Some(probe)) => println!(
+                    "Engine plays {} from tablebase ({})",
+                    self.board.move_name(mv),
+                    Chess::describe_tablebase(&probe)
+                ),
+                (None, None) => println!(
+                    "Engine plays {} (depth {}, score {}, {} nodes, {} ms)",
+                    self.board.move_name(mv),
+                    result.depth,
+                    result.score,
+                    result.nodes,
+                    elapsed.as_millis()
+                ),
+            }
+            self.board.make_move(mv);
+            self.sync_sprites();
+            if let Some(winner) = self.board.winner() {
+                println!("{:?} wins", winner);
+            }
+            // Analysis has the engine's time instead
+            let reply = result
+                .lines
+                .iter()
+                .find(|line| line.moves[0] == mv)
+                .and_then(|line| line.moves.get(1));
+            if let Some(&reply) = reply.filter(|_| self.ponder && !self.show_analysis) {
+                self.start_pondering(reply);
+            }
+        }
+    }
+
+    /// Asks a running engine search to return early.
+    pub fn stop_thinking(&mut self) {
+        if let Some(thinking) = &self.thinking {
+            thinking.stop.store(true, Ordering::Relaxed);
+        }
     }
 }
@@ -0,0 +1,874 @@
+use crate::board::{Board, Castling, Variant, PROMOTIONS};
+use crate::moves::Move;
+use crate::piece::{Color, Kind, Piece};
+use std::collections::HashMap;
+use std::path::Path;
+use std::thread;
+
+const MAGIC: [u8; 4] = *b"EGTB";
+const VERSION: u32 = 1;
+/// Stored for draws, other values are one more than the plies to mate.
+const DRAW: u8 = 0;
+/// Stored for placements that can't happen, like the side not to move being in check.
+const ILLEGAL: u8 = 255;
+/// Longest mate a table can hold, in plies.
+const MAX_PLIES: usize = 253;
+/// Most positions of a table, indices have to fit in a `u32`.
+const MAX_POSITIONS: u64 = 1 << 32;
+/// Most bytes generating a table may allocate for its values and nodes.
+const MAX_MEMORY: u64 = 1 << 32;
+/// Letters of the pieces in material names, strongest first.
+const NAME_ORDER: [(Kind, char); 6] = [
+    (Kind::King, 'K'),
+    (Kind::Queen, 'Q'),
+    (Kind::Rook, 'R'),
+    (Kind::Bishop, 'B'),
+    (Kind::Knight, 'N'),
+    (Kind::Pawn, 'P'),
+];
+
+/// Result of a position with best play, mates counted in plies.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Dtm {
+    Win(u32),
+    Draw,
+    Loss(u32),
+}
+
+impl Dtm {
+    fn from_value(value: u8) -> Option<Dtm> {
+        match value {
+            ILLEGAL => None,
+            DRAW => Some(Dtm::Draw),
+            value if value % 2 == 0 => Some(Dtm::Win(value as u32 - 1)),
+            value => Some(Dtm::Loss(value as u32 - 1)),
+        }
+    }
+}
+
+/// Pieces of both sides, kings included, each side's strongest first.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Material {
+    sides: [Vec<Kind>; 2],
+}
+
+impl Material {
+    /// Parses white's pieces then black's, like `KRKB` or `KRvKB`.
+    pub fn parse(name: &str) -> Result<Material, String> {
+        let (white, black) = match name.split_once('v') {
+            Some(sides) => sides,
+            None => {
+                let second_king = name
+                    .char_indices()
+                    .skip(1)
+                    .find(|&(_, letter)| letter == 'K')
+                    .ok_or_else(|| format!("{name} needs a king on each side."))?;
+                name.split_at(second_king.0)
+            }
+        };
+        let side = |letters: &str| -> Result<Vec<Kind>, String> {
+            let mut kinds = letters
+                .chars()
+                .map(|letter| {
+                    NAME_ORDER
+                        .iter()
+                        .find(|&&(_, name)| name == letter)
+                        .map(|&(kind, _)| kind)
+                        .ok_or_else(|| format!("Unknown piece {letter} in {name}."))
+                })
+                .collect::<Result<Vec<Kind>, String>>()?;
+            if kinds.iter().filter(|&&kind| kind == Kind::King).count() != 1 {
+                return Err(format!("{name} needs a king on each side."));
+            }
+            kinds.sort_by_key(|&kind| Material::order(kind));
+            Ok(kinds)
+        };
+        Ok(Material {
+            sides: [side(white)?, side(black)?],
+        })
+    }
+
+    /// Material on the board.
+    pub fn of(board: &Board) -> Material {
+        let mut sides = [vec![], vec![]];
+        for (_, piece) in board.pieces() {
+            sides[piece.color.index()].push(piece.kind);
+        }
+        for side in sides.iter_mut() {
+            side.sort_by_key(|&kind| Material::order(kind));
+        }
+        Material { sides }
+    }
+
+    /// Name of the material, like `KRvKB`.
+    pub fn name(&self) -> String {
+        let letters = |side: &Vec<Kind>| -> String {
+            side.iter()
+                .map(|&kind| NAME_ORDER[Material::order(kind)].1)
+                .collect()
+        };
+        format!("{}v{}", letters(&self.sides[0]), letters(&self.sides[1]))
+    }
+
+    fn order(kind: Kind) -> usize {
+        NAME_ORDER
+            .iter()
+            .position(|&(other, _)| other == kind)
+            .unwrap()
+    }
+
+    fn swapped(&self) -> Material {
+        Material {
+            sides: [self.sides[1].clone(), self.sides[0].clone()],
+        }
+    }
+
+    /// Only kings are left: always a draw, no table needed.
+    fn is_bare(&self) -> bool {
+        self.sides.iter().all(|side| side.len() == 1)
+    }
+
+    /// Piece of each square of an index, white's first.
+    fn slots(&self) -> Vec<(Color, Kind)> {
+        let white = self.sides[0].iter().map(|&kind| (Color::White, kind));
+        let black = self.sides[1].iter().map(|&kind| (Color::Black, kind));
+        white.chain(black).collect()
+    }
+
+    /// Materials a capture, a promotion or both lead to.
+    fn successors(&self) -> Vec<Material> {
+        let mut captures = vec![];
+        for color in [Color::White, Color::Black] {
+            for at in 1..self.sides[color.index()].len() {
+                let mut material = self.clone();
+                material.sides[color.index()].remove(at);
+                captures.push(material);
+            }
+        }
+        let mut successors = captures.clone();
+        for material in std::iter::once(self.clone()).chain(captures) {
+            for color in [Color::White, Color::Black] {
+                let side = &material.sides[color.index()];
+                let Some(pawn) = side.iter().position(|&kind| kind == Kind::Pawn) else {
+                    continue;
+                };
+                for kind in PROMOTIONS {
+                    let mut promoted = material.clone();
+                    promoted.sides[color.index()][pawn] = kind;
+                    promoted.sides[color.index()].sort_by_key(|&kind| Material::order(kind));
+                    successors.push(promoted);
+                }
+            }
+        }
+        let mut unique: Vec<Material> = vec![];
+        for material in successors {
+            if !unique.contains(&material) {
+                unique.push(material);
+            }
+        }
+        unique
+    }
+}
+
+/// Distance to mate of every placement of some material on a board of some size, found by
+/// retrograde analysis.
+pub struct EndgameTable {
+    pub material: Material,
+    /// Piece of each square of an index, white's first.
+    slots: Vec<(Color, Kind)>,
+    files: u8,
+    ranks: u8,
+    /// By side to move, then by the square of each piece, the first varying fastest.
+    values: Vec<u8>,
+}
+
+/// What the first pass learns of a position.
+#[derive(Copy, Clone, Default)]
+struct Node {
+    legal: bool,
+    /// Moves staying in the table not yet known to lose.
+    moves: u8,
+    /// A capture or promotion draws, or the side to move is stalemated.
+    exit_draw: bool,
+    /// Plies to be mated after the slowest losing capture or promotion.
+    exit_loss: u8,
+    /// Plies to mate after the quickest winning capture or promotion, 0 if none.
+    exit_win: u8,
+}
+
+impl EndgameTable {
+    /// Builds the table, the tables of the materials it leads to having to be in `endgames`.
+    pub fn generate(material: &Material, endgames: &Endgames) -> Result<EndgameTable, String> {
+        let (files, ranks) = (endgames.files, endgames.ranks);
+        let size = EndgameTable::positions(material, files, ranks)
+            .filter(|&size| size <= MAX_POSITIONS)
+            .ok_or_else(|| format!("{} has too many positions.", material.name()))?;
+        let memory = size * (1 + std::mem::size_of::<Node>() as u64);
+        if memory > MAX_MEMORY {
+            return Err(format!(
+                "{} needs {} MiB, more than the {} MiB a table may use.",
+                material.name(),
+                memory >> 20,
+                MAX_MEMORY >> 20
+            ));
+        }
+        let mut table = EndgameTable {
+            material: material.clone(),
+            slots: material.slots(),
+            files,
+            ranks,
+            values: vec![DRAW; size as usize],
+        };
+        let mut nodes = vec![Node::default(); size as usize];
+
+        // First pass: legal positions, with their moves counted and their exits looked up
+        let threads = thread::available_parallelism().map_or(1, |count| count.get());
+        let chunk = nodes.len().div_ceil(threads);
+        thread::scope(|scope| {
+            for (part, nodes) in nodes.chunks_mut(chunk).enumerate() {
+                let table = &table;
+                scope.spawn(move || {
+                    let mut board = Board::new(false, files, ranks);
+                    let mut placed = vec![];
+                    for (offset, node) in nodes.iter_mut().enumerate() {
+                        let index = part * chunk + offset;
+                        *node = table.examine(&mut board, &mut placed, index, endgames);
+                    }
+                });
+            }
+        });
+
+        // Then positions are settled ply by ply, from the mates back to their predecessors
+        let mut pending: Vec<Vec<u32>> = vec![vec![]; MAX_PLIES + 1];
+        for (index, node) in nodes.iter().enumerate() {
+            if !node.legal {
+                table.values[index] = ILLEGAL;
+            } else if node.exit_win > 0 {
+                pending[node.exit_win as usize].push(index as u32);
+            } else if node.moves == 0 && !node.exit_draw {
+                pending[node.exit_loss as usize].push(index as u32);
+            }
+        }
+        let mut board = Board::new(false, files, ranks);
+        let mut placed = vec![];
+        for ply in 0..=MAX_PLIES {
+            for index in std::mem::take(&mut pending[ply]) {
+                let index = index as usize;
+                if table.values[index] != DRAW {
+                    continue;
+                }
+                table.values[index] = ply as u8 + 1;
+                if ply == MAX_PLIES {
+                    continue;
+                }
+                for previous in table.predecessors(&mut board, &mut placed, index) {
+                    if table.values[previous] != DRAW {
+                        continue;
+                    }
+                    // A move into a loss wins, all moves into wins lose
+                    let node = &mut nodes[previous];
+                    if ply % 2 == 0 {
+                        pending[ply + 1].push(previous as u32);
+                        continue;
+                    }
+                    node.moves -= 1;
+                    if node.moves == 0 && !node.exit_draw && node.exit_win == 0 {
+                        pending[(ply + 1).max(node.exit_loss as usize)].push(previous as u32);
+                    }
+                }
+            }
+        }
+        Ok(table)
+    }
+
+    /// Placements of the material for either side to move, none if they overflow.
+    fn positions(material: &Material, files: u8, ranks: u8) -> Option<u64> {
+        let area = files as u64 * ranks as u64;
+        area.checked_pow(material.slots().len() as u32)?
+            .checked_mul(2)
+    }
+
+    /// Squares of the pieces and side to move of an index.
+    fn decode(&self, index: usize) -> (Vec<u8>, Color) {
+        let area = self.files as usize * self.ranks as usize;
+        let placements = self.values.len() / 2;
+        let turn = if index < placements {
+            Color::White
+        } else {
+            Color::Black
+        };
+        let mut rest = index % placements;
+        let squares = (0..self.slots.len())
+            .map(|_| {
+                let square = rest % area;
+                rest /= area;
+                square as u8
+            })
+            .collect();
+        (squares, turn)
+    }
+
+    fn index(&self, squares: &[u8], turn: Color) -> usize {
+        let area = self.files as usize * self.ranks as usize;
+        let placement = squares
+            .iter()
+            .rev()
+            .fold(0, |index, &square| index * area + square as usize);
+        turn.index() * self.values.len() / 2 + placement
+    }
+
+    /// Puts the pieces of an index on the board, in place of the previous ones.
+    fn place(&self, board: &mut Board, placed: &mut Vec<u8>, squares: &[u8], turn: Color) {
+        for square in placed.drain(..) {
+            board.squares[square as usize].piece = None;
+        }
+        for (id, (&square, &(color, kind))) in squares.iter().zip(&self.slots).enumerate() {
+            board.squares[square as usize].piece = Some(Piece::new(kind, color, id as u8));
+            placed.push(square);
+        }
+        board.turn = turn;
+    }
+
+    fn examine(
+        &self,
+        board: &mut Board,
+        placed: &mut Vec<u8>,
+        index: usize,
+        endgames: &Endgames,
+    ) -> Node {
+        let (squares, turn) = self.decode(index);
+        let mut node = Node::default();
+        for (at, (&square, &(_, kind))) in squares.iter().zip(&self.slots).enumerate() {
+            let (_, rank) = board.index_to_file_rank(square);
+            let pawn_misplaced = kind == Kind::Pawn && (rank == 0 || rank == self.ranks - 1);
+            if squares[..at].contains(&square) || pawn_misplaced {
+                return node;
+            }
+        }
+        self.place(board, placed, &squares, turn);
+        if board.in_check(turn.opponent()) {
+            return node;
+        }
+        node.legal = true;
+        let moves = board.legal_moves();
+        if moves.is_empty() && !board.in_check(turn) {
+            node.exit_draw = true;
+        }
+        for mv in moves {
+            if !board.is_noisy(mv) {
+                node.moves += 1;
+                continue;
+            }
+            board.make_move(mv);
+            let result = endgames.probe(board);
+            board.unmake_move();
+            match result.expect("Tables of captures and promotions are made first.") {
+                Dtm::Draw => node.exit_draw = true,
+                Dtm::Win(plies) => node.exit_loss = node.exit_loss.max(plies as u8 + 1),
+                Dtm::Loss(plies) if node.exit_win == 0 => node.exit_win = plies as u8 + 1,
+                Dtm::Loss(plies) => node.exit_win = node.exit_win.min(plies as u8 + 1),
+            }
+        }
+        node
+    }
+
+    /// Positions with a quiet move leading to this one, taken back with the piece moving
+    /// the way it came: the way it moves, except for pawns.
+    fn predecessors(&self, board: &mut Board, placed: &mut Vec<u8>, index: usize) -> Vec<usize> {
+        let (mut squares, turn) = self.decode(index);
+        self.place(board, placed, &squares, turn);
+        let mover = turn.opponent();
+        let mut previous = vec![];
+        for (slot, &(color, kind)) in self.slots.iter().enumerate() {
+            if color != mover {
+                continue;
+            }
+            let to = squares[slot];
+            let froms: Vec<u8> = match kind {
+                Kind::Pawn => {
+                    let (df, dr) = Board::pawn_forward(color);
+                    let empty = |square: &u8| board.piece_at(*square).is_none();
+                    let mut froms = vec![];
+                    if let Some(one) = board.offset(to, (-df, -dr)).filter(empty) {
+                        froms.push(one);
+                        let two = board.offset(one, (-df, -dr)).filter(empty);
+                        let start = board.pawn_start_rank(color);
+                        if let Some(two) =
+                            two.filter(|&two| board.index_to_file_rank(two).1 == start)
+                        {
+                            froms.push(two);
+                        }
+                    }
+                    froms
+                }
+                _ => board
+                    .generate_pseudo_legal_moves(to)
+                    .into_iter()
+                    .filter(|mv| board.piece_at(mv.to).is_none())
+                    .map(|mv| mv.to)
+                    .collect(),
+            };
+            for from in froms {
+                squares[slot] = from;
+                previous.push(self.index(&squares, mover));
+            }
+            squares[slot] = to;
+        }
+        previous
+    }
+
+    /// Result of the board's position, whose material is this table's or, with `flip`, the
+    /// table's with colors swapped.
+    fn probe(&self, board: &Board, flip: bool) -> Option<Dtm> {
+        let slots = &self.slots;
+        let mut squares = vec![0; slots.len()];
+        let mut used = vec![false; slots.len()];
+        for (square, piece) in board.pieces() {
+            let (color, square) = match flip {
+                true => {
+                    let (file, rank) = board.index_to_file_rank(square);
+                    let mirrored = board.file_rank_to_index(file, board.ranks - 1 - rank);
+                    (piece.color.opponent(), mirrored)
+                }
+                false => (piece.color, square),
+            };
+            let slot =
+                (0..slots.len()).find(|&slot| !used[slot] && slots[slot] == (color, piece.kind))?;
+            used[slot] = true;
+            squares[slot] = square;
+        }
+        let turn = if flip {
+            board.turn.opponent()
+        } else {
+            board.turn
+        };
+        Dtm::from_value(self.values[self.index(&squares, turn)])
+    }
+
+    /// Results of the legal positions, or of all positions for a loaded table.
+    pub fn results(&self) -> impl Iterator<Item = Dtm> + '_ {
+        self.values
+            .iter()
+            .filter_map(|&value| Dtm::from_value(value))
+    }
+
+    /// Writes the table to `<directory>/<material>.egtb`: the header, then the values
+    /// packed in runs. Impossible placements are never probed, they repeat the value before
+    /// them to lengthen the runs.
+    pub fn save(&self, directory: &Path) -> Result<usize, String> {
+        let mut values = self.values.clone();
+        let mut last = DRAW;
+        for value in values.iter_mut() {
+            match *value {
+                ILLEGAL => *value = last,
+                other => last = other,
+            }
+        }
+        let name = self.material.name();
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend([self.files, self.ranks, name.len() as u8]);
+        bytes.extend(name.as_bytes());
+        bytes.extend((self.values.len() as u64).to_le_bytes());
+        bytes.extend(pack(&values));
+        let path = directory.join(format!("{name}.egtb"));
+        std::fs::write(&path, &bytes).map_err(|error| format!("{}: {error}", path.display()))?;
+        Ok(bytes.len())
+    }
+
+    pub fn load(path: &Path) -> Result<EndgameTable, String> {
+        let bytes = std::fs::read(path)
+            .map_err(|error| format!("Couldn't read {}: {error}", path.display()))?;
+        EndgameTable::parse(&bytes).map_err(|error| format!("{}: {error}", path.display()))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<EndgameTable, String> {
+        if bytes.len() < 11 || bytes[..4] != MAGIC {
+            return Err("Not an endgame table.".to_string());
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("Unsupported endgame table version {version}."));
+        }
+        let (files, ranks, name_length) = (bytes[8], bytes[9], bytes[10] as usize);
+        let name_end = 11 + name_length;
+        let header = bytes
+            .get(11..name_end + 8)
+            .ok_or_else(|| "Endgame table is truncated.".to_string())?;
+        let name =
+            std::str::from_utf8(&header[..name_length]).map_err(|error| error.to_string())?;
+        let material = Material::parse(name)?;
+        let size = u64::from_le_bytes(header[name_length..].try_into().unwrap());
+        if EndgameTable::positions(&material, files, ranks) != Some(size) {
+            return Err("Endgame table size doesn't match its material.".to_string());
+        }
+        Ok(EndgameTable {
+            slots: material.slots(),
+            material,
+            files,
+            ranks,
+            values: unpack(&bytes[name_end + 8..], size as usize)?,
+        })
+    }
+}
+
+/// PackBits: a control byte n below 128 is followed by n + 1 literal bytes, above 128 by one
+/// byte repeated 257 - n times.
+fn pack(values: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut at = 0;
+    while at < values.len() {
+        let run = values[at..]
+            .iter()
+            .take(128)
+            .take_while(|&&value| value == values[at])
+            .count();
+        if run >= 2 {
+            bytes.extend([(257 - run) as u8, values[at]]);
+            at += run;
+            continue;
+        }
+        let start = at;
+        while at < values.len() && at - start < 128 {
+            if at + 1 < values.len() && values[at] == values[at + 1] {
+                break;
+            }
+            at += 1;
+        }
+        bytes.push((at - start - 1) as u8);
+        bytes.extend(&values[start..at]);
+    }
+    bytes
+}
+
+fn unpack(bytes: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    let truncated = || "Endgame table is truncated.".to_string();
+    let mut values = Vec::with_capacity(size);
+    let mut at = 0;
+    while at < bytes.len() {
+        let control = bytes[at] as usize;
+        at += 1;
+        match control {
+            0..=127 => {
+                let literals = bytes.get(at..at + control + 1).ok_or_else(truncated)?;
+                values.extend(literals);
+                at += control + 1;
+            }
+            128 => {}
+            _ => {
+                let value = *bytes.get(at).ok_or_else(truncated)?;
+                values.extend(std::iter::repeat_n(value, 257 - control));
+                at += 1;
+            }
+        }
+    }
+    match values.len() == size {
+        true => Ok(values),
+        false => Err("Endgame table doesn't hold the expected number of positions.".to_string()),
+    }
+}
+
+/// Endgame tables for one board size, by material name.
+pub struct Endgames {
+    pub files: u8,
+    pub ranks: u8,
+    tables: HashMap<String, EndgameTable>,
+}
+
+impl Endgames {
+    pub fn new(files: u8, ranks: u8) -> Endgames {
+        Endgames {
+            files,
+            ranks,
+            tables: HashMap::new(),
+        }
+    }
+
+    /// Loads the `.egtb` files of `directory` made for this board size, none if it doesn't
+    /// exist.
+    pub fn load(directory: &Path, files: u8, ranks: u8) -> Result<Endgames, String> {
+        let mut endgames = Endgames::new(files, ranks);
+        if !directory.is_dir() {
+            return Ok(endgames);
+        }
+        let entries = std::fs::read_dir(directory)
+            .map_err(|error| format!("{}: {error}", directory.display()))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "egtb")
+            {
+                let table = EndgameTable::load(&path)?;
+                if (table.files, table.ranks) == (files, ranks) {
+                    endgames.tables.insert(table.material.name(), table);
+                }
+            }
+        }
+        Ok(endgames)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub fn get(&self, material: &Material) -> Option<&EndgameTable> {
+        self.tables.get(&material.name())
+    }
+
+    /// Makes the table of `material` and those it needs, unless known already. Returns the
+    /// materials generated, each after those it leads to.
+    pub fn generate(&mut self, material: &Material) -> Result<Vec<Material>, String> {
+        if material.is_bare() || self.known(material) {
+            return Ok(vec![]);
+        }
+        let mut generated = vec![];
+        for successor in material.successors() {
+            generated.extend(self.generate(&successor)?);
+        }
+        let table = EndgameTable::generate(material, self)?;
+        self.tables.insert(material.name(), table);
+        generated.push(material.clone());
+        Ok(generated)
+    }
+
+    fn known(&self, material: &Material) -> bool {
+        self.tables.contains_key(&material.name())
+            || self.tables.contains_key(&material.swapped().name())
+    }
+
+    /// Result for the side to move, for standard chess without castling or a pawn able to
+    /// take en passant.
+    pub fn probe(&self, board: &Board) -> Option<Dtm> {
+        let en_passant = board.en_passant.is_some_and(|square| {
+            board.attackers(square, board.turn).iter().any(|index| {
+                board
+                    .piece_at(index)
+                    .is_some_and(|piece| piece.kind == Kind::Pawn)
+            })
+        });
+        if (board.files, board.ranks) != (self.files, self.ranks)
+            || board.variant != Variant::Standard
+            || board.castling != Castling::default()
+            || en_passant
+        {
+            return None;
+        }
+        let material = Material::of(board);
+        if material.is_bare() {
+            return Some(Dtm::Draw);
+        }
+        if let Some(table) = self.tables.get(&material.name()) {
+            return table.probe(board, false);
+        }
+        self.tables
+            .get(&material.swapped().name())?
+            .probe(board, true)
+    }
+
+    /// Quickest mate, a drawing move, or the slowest loss, with the result of the position.
+    pub fn best_move(&self, board: &mut Board) -> Option<(Move, Dtm)> {
+        self.probe(board)?;
+        let mut best: Option<(Move, Dtm)> = None;
+        for mv in board.legal_moves() {
+            board.make_move(mv);
+            let child = self.probe(board);
+            board.unmake_move();
+            let result = match child? {
+                Dtm::Win(plies) => Dtm::Loss(plies + 1),
+                Dtm::Draw => Dtm::Draw,
+                Dtm::Loss(plies) => Dtm::Win(plies + 1),
+            };
+            if best.is_none_or(|(_, best)| Endgames::better(result, best)) {
+                best = Some((mv, result));
+            }
+        }
+        best
+    }
+
+    fn better(result: Dtm, than: Dtm) -> bool {
+        match (result, than) {
+            (Dtm::Win(plies), Dtm::Win(other)) => plies < other,
+            (Dtm::Loss(plies), Dtm::Loss(other)) => plies > other,
+            (Dtm::Win(_), _) => true,
+            (Dtm::Draw, Dtm::Loss(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endgames(files: u8, ranks: u8, names: &[&str]) -> Endgames {
+        let mut endgames = Endgames::new(files, ranks);
+        for name in names {
+            endgames.generate(&Material::parse(name).unwrap()).unwrap();
+        }
+        endgames
+    }
+
+    fn table<'a>(endgames: &'a Endgames, name: &str) -> &'a EndgameTable {
+        endgames.get(&Material::parse(name).unwrap()).unwrap()
+    }
+
+    /// Longest win of a table, and its wins and draws with white to move.
+    fn summary(table: &EndgameTable) -> (u32, usize, usize) {
+        let (mut longest, mut wins, mut draws) = (0, 0, 0);
+        let white = &table.values[..table.values.len() / 2];
+        for result in white.iter().filter_map(|&value| Dtm::from_value(value)) {
+            match result {
+                Dtm::Win(plies) => {
+                    longest = longest.max(plies);
+                    wins += 1;
+                }
+                Dtm::Draw => draws += 1,
+                Dtm::Loss(_) => {}
+            }
+        }
+        (longest, wins, draws)
+    }
+
+    fn probe(endgames: &Endgames, fen: &str) -> Option<Dtm> {
+        let mut board = Board::new(false, endgames.files, endgames.ranks);
+        board.load_fen(fen).unwrap();
+        endgames.probe(&board)
+    }
+
+    /// Every so many legal positions, the stored result follows from the children's.
+    fn consistent(endgames: &Endgames, table: &EndgameTable, step: usize) {
+        let mut board = Board::new(false, table.files, table.ranks);
+        let mut placed = vec![];
+        for index in (0..table.values.len()).step_by(step) {
+            let Some(result) = Dtm::from_value(table.values[index]) else {
+                continue;
+            };
+            let (squares, turn) = table.decode(index);
+            table.place(&mut board, &mut placed, &squares, turn);
+            let best = match endgames.best_move(&mut board) {
+                Some((_, best)) => best,
+                None if board.in_check(turn) => Dtm::Loss(0),
+                None => Dtm::Draw,
+            };
+            assert_eq!(best, result, "index {index}");
+        }
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let mut values = vec![];
+        values.extend(0..=255);
+        values.extend([7; 300]);
+        values.extend([1, 2, 2, 3, 3, 3, 4]);
+        values.extend((0..1000).map(|value| (value % 3) as u8));
+        values.push(9);
+        for length in [0, 1, 2, 129, 300, values.len()] {
+            let values = &values[..length];
+            assert_eq!(unpack(&pack(values), values.len()).unwrap(), values);
+        }
+        assert!(unpack(&pack(&[5; 10]), 11).is_err());
+        assert!(unpack(&[3, 1, 2], 4).is_err());
+    }
+
+    #[test]
+    fn save_round_trip() {
+        let endgames = endgames(4, 4, &["KQK"]);
+        let table = table(&endgames, "KQK");
+        let directory = std::env::temp_dir().join(format!("egtb-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let saved = table.save(&directory);
+        let bytes = std::fs::read(directory.join("KQvK.egtb"));
+        std::fs::remove_dir_all(&directory).unwrap();
+        let bytes = bytes.unwrap();
+        assert_eq!(saved.unwrap(), bytes.len());
+
+        let loaded = EndgameTable::parse(&bytes).unwrap();
+        assert_eq!(loaded.material, table.material);
+        assert_eq!((loaded.files, loaded.ranks), (4, 4));
+        assert_eq!(loaded.values.len(), table.values.len());
+        // Impossible placements are saved as the value before them
+        for (&loaded, &value) in loaded.values.iter().zip(&table.values) {
+            if value != ILLEGAL {
+                assert_eq!(loaded, value);
+            }
+        }
+        assert!(EndgameTable::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(EndgameTable::parse(&bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn too_large() {
+        let endgames = Endgames::new(8, 8);
+        let material = Material::parse("KQRvKR").unwrap();
+        assert!(EndgameTable::generate(&material, &endgames).is_err());
+    }
+
+    #[test]
+    fn small_board() {
+        let endgames = endgames(5, 5, &["KPK"]);
+        for name in ["KQK", "KRK", "KBK", "KNK", "KPK"] {
+            consistent(&endgames, table(&endgames, name), 3);
+        }
+    }
+
+    #[test]
+    #[ignore = "takes minutes without optimizations"]
+    fn longest_mates() {
+        let endgames = endgames(8, 8, &["KQK", "KRK"]);
+        assert_eq!(summary(table(&endgames, "KQK")).0, 19);
+        assert_eq!(summary(table(&endgames, "KRK")).0, 31);
+        assert_eq!(
+            probe(&endgames, "k7/8/1K6/8/8/8/8/2Q5 w - - 0 1"),
+            Some(Dtm::Win(1))
+        );
+        assert_eq!(
+            probe(&endgames, "k7/8/1Q6/8/8/8/8/7K b - - 0 1"),
+            Some(Dtm::Draw)
+        );
+        assert_eq!(
+            probe(&endgames, "8/8/8/8/8/8/8/kQ5K b - - 0 1"),
+            Some(Dtm::Draw)
+        );
+    }
+
+    #[test]
+    #[ignore = "takes minutes without optimizations"]
+    fn pawn() {
+        let endgames = endgames(8, 8, &["KPK"]);
+        let table = table(&endgames, "KPK");
+        assert_eq!(summary(table), (55, 124960, 38368));
+        // The defending king holds the rook pawn's corner, but not a king on the sixth rank
+        assert_eq!(
+            probe(&endgames, "k7/8/8/8/8/8/P7/K7 w - - 0 1"),
+            Some(Dtm::Draw)
+        );
+        assert!(matches!(
+            probe(&endgames, "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"),
+            Some(Dtm::Loss(_))
+        ));
+        assert_eq!(
+            probe(&endgames, "4k3/4P3/4K3/8/8/8/8/8 b - - 0 1"),
+            Some(Dtm::Draw)
+        );
+        // Black's pawn reads the table with colors swapped
+        assert!(matches!(
+            probe(&endgames, "8/8/8/8/4p3/4k3/8/4K3 w - - 0 1"),
+            Some(Dtm::Loss(_))
+        ));
+        consistent(&endgames, table, 97);
+    }
+
+    #[test]
+    #[ignore = "takes minutes without optimizations"]
+    fn bishop_and_knight() {
+        let endgames = endgames(8, 8, &["KBNK"]);
+        let table = table(&endgames, "KBNK");
+        assert_eq!(summary(table).0, 65);
+        consistent(&endgames, table, 9973);
+    }
+}
@@ -0,0 +1,468 @@
+use crate::board::Board;
+use crate::pawns::PawnTable;
+use crate::piece::{Color, Kind, Piece};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::sync::{Arc, OnceLock};
+
+/// Weights the engine starts with until others are loaded.
+const DEFAULT_WEIGHTS: &str = include_str!("../assets/eval/weights.txt");
+/// Side of the piece-square tables, other boards are mapped onto them.
+const TABLE_SIDE: usize = 8;
+/// Phase of the starting position, when the middlegame weights apply in full.
+const FULL_PHASE: i32 = 24;
+const KINDS: [Kind; 6] = [
+    Kind::Pawn,
+    Kind::Knight,
+    Kind::Bishop,
+    Kind::Rook,
+    Kind::Queen,
+    Kind::King,
+];
+
+/// Pieces whose mobility is scored, in the order of `Weights::mobility`.
+pub const MOBILE_KINDS: [Kind; 4] = [Kind::Knight, Kind::Bishop, Kind::Rook, Kind::Queen];
+
+/// A middlegame and an endgame score, blended by the game phase.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub fn new(mg: i32, eg: i32) -> Self {
+        Score { mg, eg }
+    }
+
+    /// Single score for a position with `phase` out of `FULL_PHASE` left.
+    pub fn taper(self, phase: i32) -> i32 {
+        let phase = phase.clamp(0, FULL_PHASE);
+        (self.mg * phase + self.eg * (FULL_PHASE - phase)) / FULL_PHASE
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
+/// Weights of one kind of piece.
+#[derive(Clone, Debug)]
+pub struct PieceWeights {
+    pub value: Score,
+    /// Contribution to the game phase, the middlegame fades as these pieces are traded.
+    pub phase: i32,
+    /// Piece-square tables seen from white, rank 8 first.
+    pub mg: [i32; TABLE_SIDE * TABLE_SIDE],
+    pub eg: [i32; TABLE_SIDE * TABLE_SIDE],
+}
+
+/// Pawn structure terms, for each pawn concerned.
+#[derive(Clone, Debug)]
+pub struct PawnWeights {
+    /// Another pawn of the same side stands in front on the file.
+    pub doubled: Score,
+    /// No pawn of the same side on the neighbouring files.
+    pub isolated: Score,
+    /// Can't be defended by a pawn and can't safely step forward.
+    pub backward: Score,
+    /// Side by side with or defended by another pawn.
+    pub connected: Score,
+    /// Each group of neighbouring files with pawns past the first.
+    pub island: Score,
+    /// No enemy pawn can stop it, by rank counted from its side.
+    pub passed_mg: [i32; TABLE_SIDE],
+    pub passed_eg: [i32; TABLE_SIDE],
+    /// Percentage of the passed pawn bonus kept when every square of its path to promotion
+    /// is occupied or attacked by the enemy, a share of the loss for each such square.
+    pub passed_blocked: i32,
+    /// Endgame bonus per square of distance from the square in front of a passed pawn to
+    /// its own king and to the enemy king, the latter counting for it, scaled by rank.
+    pub passed_king: [i32; 2],
+}
+
+/// King safety terms, middlegame only since they fade with the attacking material.
+#[derive(Clone, Debug)]
+pub struct KingWeights {
+    /// Each pawn of the king's side one and two ranks in front of it, on its file or next.
+    pub shield: [i32; 2],
+    /// Each enemy pawn one, two and three ranks in front of the king, on its file or next.
+    pub storm: [i32; 3],
+    /// Each file on or next to the king without a pawn of its side.
+    pub semi_open: i32,
+    /// Added when the file has no enemy pawn either.
+    pub open: i32,
+    /// Attack units of a knight, bishop, rook and queen hitting the king zone.
+    pub attack: [i32; 4],
+    /// Share in percent of the attack units counted, by number of attackers.
+    pub attackers: [i32; 8],
+}
+
+/// Everything the evaluation is tuned by, read from a data file.
+#[derive(Clone, Debug)]
+pub struct Weights {
+    pieces: Vec<PieceWeights>,
+    pub pawns: PawnWeights,
+    /// Per square a knight, bishop, rook and queen can go to.
+    pub mobility: [Score; 4],
+    pub king: KingWeights,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights::parse(DEFAULT_WEIGHTS).expect("Default evaluation weights are invalid.")
+    }
+}
+
+impl Weights {
+    /// The default weights, parsed once and shared by every board.
+    pub fn shared_default() -> Arc<Weights> {
+        static DEFAULT: OnceLock<Arc<Weights>> = OnceLock::new();
+        DEFAULT.get_or_init(|| Arc::new(Weights::default())).clone()
+    }
+
+    pub fn load(path: &str) -> Result<Weights, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| format!("{path}: {error}"))?;
+        Weights::parse(&text).map_err(|error| format!("{path}: {error}"))
+    }
+
+    /// Writes the weights to `path`, keeping the layout and comments of a file already there.
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(existing) => self
+                .rewrite(&existing)
+                .map_err(|error| format!("{path}: {error}"))?,
+            Err(_) => self.to_text(),
+        };
+        std::fs::write(path, text).map_err(|error| format!("{path}: {error}"))
+    }
+
+    /// Weights file `text` with its numbers replaced by these weights, each keeping the
+    /// column it ended on.
+    pub fn rewrite(&self, text: &str) -> Result<String, String> {
+        let entries: HashMap<String, Vec<i32>> = self.entries().into_iter().collect();
+        let mut written: HashMap<&str, usize> = HashMap::new();
+        let mut current: Option<&str> = None;
+        let mut output = String::new();
+        for line in text.split_inclusive('\n') {
+            let (code, comment) = line.split_at(line.find('#').unwrap_or(line.len()));
+            let mut rest = code;
+            while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+                let end = rest[start..]
+                    .find(char::is_whitespace)
+                    .map_or(rest.len(), |end| start + end);
+                let (space, token) = (&rest[..start], &rest[start..end]);
+                rest = &rest[end..];
+                if token.parse::<i32>().is_err() {
+                    let name = entries
+                        .get_key_value(token)
+                        .ok_or(format!("unknown entry {token}"))?
+                        .0;
+                    current = Some(name);
+                    output.push_str(space);
+                    output.push_str(token);
+                    continue;
+                }
+                let name = current.ok_or(format!("number {token} before any name"))?;
+                let count = written.entry(name).or_default();
+                let value = entries[name]
+                    .get(*count)
+                    .ok_or(format!("{name} has too many numbers"))?;
+                *count += 1;
+                let width = space.len() + token.len();
+                let value = value.to_string();
+                match value.len() < width || space.is_empty() {
+                    true => output.push_str(&format!("{value:>width$}")),
+                    false => output.push_str(&format!(" {value}")),
+                }
+            }
+            output.push_str(rest);
+            output.push_str(comment);
+        }
+        for (name, numbers) in &entries {
+            if written.get(name.as_str()) != Some(&numbers.len()) {
+                return Err(format!("{name} doesn't have {} numbers", numbers.len()));
+            }
+        }
+        Ok(output)
+    }
+
+    /// Reads `name numbers...` entries, `#` starts a comment.
+    pub fn parse(text: &str) -> Result<Weights, String> {
+        let mut entries: HashMap<String, Vec<i32>> = HashMap::new();
+        let mut current: Option<String> = None;
+        for token in text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(str::split_whitespace)
+        {
+            if let Ok(number) = token.parse::<i32>() {
+                let Some(name) = &current else {
+                    return Err(format!("number {number} before any name"));
+                };
+                entries.get_mut(name).unwrap().push(number);
+            } else {
+                entries.insert(token.to_string(), vec![]);
+                current = Some(token.to_string());
+            }
+        }
+        Weights::from_entries(entries)
+    }
+
+    /// Builds the weights from named lists of numbers, as `entries` gives them.
+    pub fn from_entries(mut entries: HashMap<String, Vec<i32>>) -> Result<Weights, String> {
+        let mut take = |name: &str, len: usize| -> Result<Vec<i32>, String> {
+            let numbers = entries.remove(name).ok_or(format!("{name} is missing"))?;
+            match numbers.len() == len {
+                true => Ok(numbers),
+                false => Err(format!(
+                    "{name} has {} numbers instead of {len}",
+                    numbers.len()
+                )),
+            }
+        };
+        let table = TABLE_SIDE * TABLE_SIDE;
+        let mut pieces = vec![];
+        for kind in KINDS {
+            let name = Weights::kind_name(kind);
+            let value = take(&format!("{name}.value"), 2)?;
+            pieces.push(PieceWeights {
+                value: Score::new(value[0], value[1]),
+                phase: take(&format!("{name}.phase"), 1)?[0],
+                mg: take(&format!("{name}.mg"), table)?.try_into().unwrap(),
+                eg: take(&format!("{name}.eg"), table)?.try_into().unwrap(),
+            });
+        }
+        let mut score = |name: &str| take(name, 2).map(|pair| Score::new(pair[0], pair[1]));
+        let pawns = PawnWeights {
+            doubled: score("doubled")?,
+            isolated: score("isolated")?,
+            backward: score("backward")?,
+            connected: score("connected")?,
+            island: score("island")?,
+            passed_mg: take("passed.mg", TABLE_SIDE)?.try_into().unwrap(),
+            passed_eg: take("passed.eg", TABLE_SIDE)?.try_into().unwrap(),
+            passed_blocked: take("passed.blocked", 1)?[0],
+            passed_king: take("passed.king", 2)?.try_into().unwrap(),
+        };
+        let mut mobility = [Score::default(); 4];
+        for (score, kind) in mobility.iter_mut().zip(MOBILE_KINDS) {
+            let pair = take(&format!("mobility.{}", Weights::kind_name(kind)), 2)?;
+            *score = Score::new(pair[0], pair[1]);
+        }
+        let king = KingWeights {
+            shield: take("king.shield", 2)?.try_into().unwrap(),
+            storm: take("king.storm", 3)?.try_into().unwrap(),
+            semi_open: take("king.semi_open", 1)?[0],
+            open: take("king.open", 1)?[0],
+            attack: take("king.attack", 4)?.try_into().unwrap(),
+            attackers: take("king.attackers", 8)?.try_into().unwrap(),
+        };
+        if let Some(name) = entries.keys().next() {
+            return Err(format!("unknown weight {name}"));
+        }
+        Ok(Weights {
+            pieces,
+            pawns,
+            mobility,
+            king,
+        })
+    }
+
+    /// Every weight by name, in the order of the data file.
+    pub fn entries(&self) -> Vec<(String, Vec<i32>)> {
+        let mut entries = vec![];
+        for kind in KINDS {
+            let name = Weights::kind_name(kind);
+            let piece = self.piece(kind);
+            entries.push((
+                format!("{name}.value"),
+                vec![piece.value.mg, piece.value.eg],
+            ));
+            entries.push((format!("{name}.phase"), vec![piece.phase]));
+            entries.push((format!("{name}.mg"), piece.mg.to_vec()));
+            entries.push((format!("{name}.eg"), piece.eg.to_vec()));
+        }
+        let pawns = &self.pawns;
+        for (name, score) in [
+            ("doubled", pawns.doubled),
+            ("isolated", pawns.isolated),
+            ("backward", pawns.backward),
+            ("connected", pawns.connected),
+            ("island", pawns.island),
+        ] {
+            entries.push((name.to_string(), vec![score.mg, score.eg]));
+        }
+        entries.push(("passed.mg".to_string(), pawns.passed_mg.to_vec()));
+        entries.push(("passed.eg".to_string(), pawns.passed_eg.to_vec()));
+        entries.push(("passed.blocked".to_string(), vec![pawns.passed_blocked]));
+        entries.push(("passed.king".to_string(), pawns.passed_king.to_vec()));
+        for (score, kind) in self.mobility.iter().zip(MOBILE_KINDS) {
+            let name = format!("mobility.{}", Weights::kind_name(kind));
+            entries.push((name, vec![score.mg, score.eg]));
+        }
+        let king = &self.king;
+        entries.push(("king.shield".to_string(), king.shield.to_vec()));
+        entries.push(("king.storm".to_string(), king.storm.to_vec()));
+        entries.push(("king.semi_open".to_string(), vec![king.semi_open]));
+        entries.push(("king.open".to_string(), vec![king.open]));
+        entries.push(("king.attack".to_string(), king.attack.to_vec()));
+        entries.push(("king.attackers".to_string(), king.attackers.to_vec()));
+        entries
+    }
+
+    /// Same format as `parse` reads, tables laid out as boards.
+    pub fn to_text(&self) -> String {
+        let mut text =
+            String::from("# Evaluation weights in centipawns, read by the engine at startup.\n");
+        let mut group = String::new();
+        for (name, numbers) in self.entries() {
+            // A blank line between pieces and terms
+            let prefix = name.split('.').next().unwrap_or_default();
+            if prefix != group {
+                text.push('\n');
+                group = prefix.to_string();
+            }
+            if numbers.len() == TABLE_SIDE * TABLE_SIDE {
+                let _ = writeln!(text, "{name}");
+                for row in numbers.chunks(TABLE_SIDE) {
+                    for value in row {
+                        let _ = write!(text, "{value:>5}");
+                    }
+                    text.push('\n');
+                }
+            } else {
+                let numbers: Vec<String> = numbers.iter().map(i32::to_string).collect();
+                let _ = writeln!(text, "{name} {}", numbers.join(" "));
+            }
+        }
+        text
+    }
+
+    pub fn piece(&self, kind: Kind) -> &PieceWeights {
+        &self.pieces[Weights::kind_index(kind)]
+    }
+
+    pub fn piece_mut(&mut self, kind: Kind) -> &mut PieceWeights {
+        &mut self.pieces[Weights::kind_index(kind)]
+    }
+
+    fn kind_index(kind: Kind) -> usize {
+        KINDS.iter().position(|&k| k == kind).unwrap()
+    }
+
+    fn kind_name(kind: Kind) -> &'static str {
+        match kind {
+            Kind::Pawn => "pawn",
+            Kind::Knight => "knight",
+            Kind::Bishop => "bishop",
+            Kind::Rook => "rook",
+            Kind::Queen => "queen",
+            Kind::King => "king",
+        }
+    }
+}
+
+impl Board {
+    /// Evaluation in centipawns from the point of view of the side to move, by the network
+    /// if one is set, otherwise from the weights with pawn structures cached in `pawns`.
+    pub fn evaluate(&self, pawns: &mut PawnTable) -> i32 {
+        if let Some(nnue) = &self.nnue {
+            return nnue.evaluate(self.turn);
+        }
+        let structure = self.pawn_structure(pawns);
+        let score =
+            self.psqt + structure.score + self.passed_pawns(structure.passed) + self.activity();
+        let score = score.taper(self.phase);
+        match self.turn {
+            Color::White => score,
+            Color::Black => -score,
+        }
+    }
+
+    /// Switches to other weights, the incremental scores are computed again.
+    pub fn set_weights(&mut self, weights: Arc<Weights>) {
+        self.weights = weights;
+        self.refresh_eval();
+    }
+
+    /// Computes from scratch the scores that moves keep up to date.
+    pub(crate) fn refresh_eval(&mut self) {
+        self.psqt = Score::default();
+        self.phase = 0;
+        for (index, piece) in self.pieces().collect::<Vec<_>>() {
+            let (score, phase) = self.piece_eval(piece, index);
+            self.psqt += score;
+            self.phase += phase;
+        }
+        self.refresh_nnue();
+    }
+
+    /// Accounts for a piece arriving on `index`.
+    pub(crate) fn add_eval(&mut self, piece: Piece, index: u8) {
+        let (score, phase) = self.piece_eval(piece, index);
+        self.psqt += score;
+        self.phase += phase;
+        if let Some(nnue) = &mut self.nnue {
+            nnue.update(piece, index, true);
+        }
+    }
+
+    /// Accounts for a piece leaving `index`.
+    pub(crate) fn remove_eval(&mut self, piece: Piece, index: u8) {
+        let (score, phase) = self.piece_eval(piece, index);
+        self.psqt -= score;
+        self.phase -= phase;
+        if let Some(nnue) = &mut self.nnue {
+            nnue.update(piece, index, false);
+        }
+    }
+
+    /// Material and table score of a piece, from white's point of view, and its phase.
+    fn piece_eval(&self, piece: Piece, index: u8) -> (Score, i32) {
+        let weights = self.weights.piece(piece.kind);
+        let (file, rank) = self.index_to_file_rank(index);
+        let file = file as usize * TABLE_SIDE / self.files as usize;
+        let rank = rank as usize * TABLE_SIDE / self.ranks as usize;
+        let row = match piece.color {
+            Color::White => TABLE_SIDE - 1 - rank,
+            Color::Black => rank,
+        };
+        let square = row * TABLE_SIDE + file;
+        let score = weights.value + Score::new(weights.mg[square], weights.eg[square]);
+        match piece.color {
+            Color::White => (score, weights.phase),
+            Color::Black => (-score, weights.phase),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+pub mod activity;
+pub mod attacks;
+pub mod bitmap;
+pub mod board;
+pub mod endgames;
+pub mod eval;
+pub mod mate;
+pub mod moves;
+pub mod nnue;
+pub mod pawns;
+pub mod picker;
+pub mod piece;
+pub mod pins;
+pub mod polyglot;
+pub mod search;
+pub mod see;
+pub mod skill;
+pub mod syzygy;
+pub mod tt;
+pub mod validation;
+pub mod zobrist;
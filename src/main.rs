@@ -1,8 +1,6 @@
-mod board;
 mod chess;
 mod fonts;
-mod moves;
-mod piece;
+mod sprites;
 
 extern crate find_folder;
 extern crate freetype as ft;
@@ -12,16 +10,20 @@ extern crate opengl_graphics;
 extern crate piston;
 extern crate sprite;
 
-use crate::board::Board;
-use chess::Chess;
+use crate::chess::Chess;
+use ::chess::board::Board;
+use ::chess::search::Limits;
+use ::chess::skill::Skill;
 use config::Config;
 use glutin_window::GlutinWindow as Window;
 use opengl_graphics::{GlGraphics, OpenGL};
 use piston::event_loop::{EventSettings, Events};
 use piston::input::{RenderEvent, UpdateEvent};
 use piston::window::WindowSettings;
-use piston::{Button, CursorEvent, EventLoop, MouseCursorEvent, PressEvent, ReleaseEvent};
+use piston::{Button, MouseCursorEvent, PressEvent, ReleaseEvent};
 use sprite::Scene;
+use std::collections::HashMap;
+use std::time::Duration;
 
 fn main() {
     // Load configuration
@@ -44,7 +46,24 @@ fn main() {
 
     window_size = (window_size as f32 * global_scale) as u32;
 
-    let mut window_settings = WindowSettings::new("Chess Engine", [window_size, window_size]);
+    // Board geometry, the longest side spans the configured window size
+    let files: u8 = config
+        .get("board.files")
+        .expect("Couldn't find board.files in config.");
+    let ranks: u8 = config
+        .get("board.ranks")
+        .expect("Couldn't find board.ranks in config.");
+    let square_size = window_size as f32 / files.max(ranks) as f32;
+    // Analysis panel right of the board
+    let panel_size: f32 = config.get("render.panel_size").unwrap_or(0.0) * global_scale;
+
+    let mut window_settings = WindowSettings::new(
+        "Chess Engine",
+        [
+            (square_size * files as f32 + panel_size) as u32,
+            (square_size * ranks as f32) as u32,
+        ],
+    );
     window_settings.set_samples(0);
     window_settings.set_resizable(false);
     let mut window: Window = window_settings
@@ -65,11 +84,14 @@ fn main() {
         config,
         global_scale,
         gl: GlGraphics::new(opengl),
-        board: Board::new(false),
-        pieces: vec![],
+        board: Board::new(false, files, ranks),
+        sprites: HashMap::new(),
+        engine_side: None,
+        limits: Limits::default(),
+        thinking: None,
+        search: None,
         scene: Scene::new(),
-        window_size,
-        square_size: window_size as f32 / 8.0,
+        square_size,
         x: 0.0,
         y: 0.0,
         pressed: false,
@@ -78,6 +100,23 @@ fn main() {
         active_piece: None,
         face,
         last_index_clicked: None,
+        show_control: false,
+        show_pins: false,
+        book: None,
+        book_depth: usize::MAX,
+        show_book: false,
+        tablebases: None,
+        show_tablebase: false,
+        endgames: None,
+        verdict: None,
+        show_analysis: false,
+        analysis: None,
+        multi_pv: 1,
+        analysis_time: Duration::ZERO,
+        ponder: false,
+        panel_size,
+        skill: Skill::default(),
+        show_strength: false,
     };
 
     game.init();
@@ -86,11 +125,14 @@ fn main() {
     while let Some(e) = events.next(&mut window) {
         game.scene.event(&e);
 
-        if let Some(Button::Mouse(button)) = e.press_args() {
+        if let Some(Button::Mouse(_)) = e.press_args() {
             game.pressed = true;
             game.click = true;
         }
-        if let Some(Button::Mouse(button)) = e.release_args() {
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            game.key_pressed(key);
+        }
+        if let Some(Button::Mouse(_)) = e.release_args() {
             game.pressed = false;
             game.release = true;
         };
@@ -106,4 +148,5 @@ fn main() {
             game.y = pos[1] as f32;
         });
     }
+    game.stop_thinking();
 }
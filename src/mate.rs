@@ -0,0 +1,217 @@
+use crate::board::Board;
+use crate::moves::Move;
+use std::collections::HashMap;
+
+/// What is known of a position with the attacker to move.
+#[derive(Copy, Clone, Default)]
+struct Known {
+    /// Mates in at most this many moves, if proven.
+    mate_in: Option<u32>,
+    /// Can't mate in this many moves or fewer.
+    no_mate_in: u32,
+}
+
+/// Forced mate found by `MateFinder::solve`.
+#[derive(Clone, Debug)]
+pub struct Solution {
+    /// Every first move mating within the asked number of moves, the key of a sound problem
+    /// being the only one.
+    pub keys: Vec<Move>,
+    /// Moves of the quickest mate, counted for the attacker.
+    pub moves: u32,
+    /// The quickest mate against the longest defence, attacker and defender moves alternating.
+    pub line: Vec<Move>,
+}
+
+/// Proves or disproves forced mates with a full-width AND/OR search: the attacker needs one
+/// move after which every defence is mated in time. Results are kept by position, so deeper
+/// tries reuse the shallower ones.
+#[derive(Default)]
+pub struct MateFinder {
+    /// Only checking moves are tried for the attacker, much faster but blind to quiet keys.
+    pub checks_only: bool,
+    pub nodes: u64,
+    known: HashMap<u64, Known>,
+}
+
+impl MateFinder {
+    pub fn new(checks_only: bool) -> Self {
+        MateFinder {
+            checks_only,
+            ..MateFinder::default()
+        }
+    }
+
+    /// Forced mate of the side to move in at most `moves` moves, `None` when there is none.
+    pub fn solve(&mut self, board: &mut Board, moves: u32) -> Option<Solution> {
+        let shortest = self.distance(board, moves)?;
+        let mut keys = Vec::new();
+        for mv in self.attacks(board, moves) {
+            board.make_move(mv);
+            if self.defence_fails(board, moves) {
+                keys.push(mv);
+            }
+            board.unmake_move();
+        }
+        let line = self.line(board, shortest);
+        Some(Solution {
+            keys,
+            moves: shortest,
+            line,
+        })
+    }
+
+    /// Fewest moves, up to `moves`, the side to move mates in.
+    pub fn distance(&mut self, board: &mut Board, moves: u32) -> Option<u32> {
+        (1..=moves).find(|&n| self.mates(board, n))
+    }
+
+    /// The side to move mates in at most `n` moves.
+    fn mates(&mut self, board: &mut Board, n: u32) -> bool {
+        if n == 0 {
+            return false;
+        }
+        let known = self.known.get(&board.hash).copied().unwrap_or_default();
+        if known.mate_in.is_some_and(|mate_in| mate_in <= n) {
+            return true;
+        }
+        if known.no_mate_in >= n {
+            return false;
+        }
+        self.nodes += 1;
+        let mut mates = false;
+        for mv in self.attacks(board, n) {
+            board.make_move(mv);
+            mates = self.defence_fails(board, n);
+            board.unmake_move();
+            if mates {
+                break;
+            }
+        }
+        let known = self.known.entry(board.hash).or_default();
+        match mates {
+            true => known.mate_in = Some(known.mate_in.map_or(n, |mate_in| mate_in.min(n))),
+            false => known.no_mate_in = known.no_mate_in.max(n),
+        }
+        mates
+    }
+
+    /// After an attacker move: every defence is mated within the `n` moves the move began.
+    fn defence_fails(&mut self, board: &mut Board, n: u32) -> bool {
+        self.nodes += 1;
+        let defences = board.legal_moves();
+        if defences.is_empty() {
+            return board.in_check(board.turn);
+        }
+        if n == 1 {
+            return false;
+        }
+        defences.into_iter().all(|defence| {
+            board.make_move(defence);
+            let mated = self.mates(board, n - 1);
+            board.unmake_move();
+            mated
+        })
+    }
+
+    /// Attacker moves worth trying with `n` moves left, checks first. A mate in one has to
+    /// give check.
+    fn attacks(&self, board: &mut Board, n: u32) -> Vec<Move> {
+        let mut checks = Vec::new();
+        let mut others = Vec::new();
+        for mv in board.legal_moves() {
+            board.make_move(mv);
+            let check = board.in_check(board.turn);
+            board.unmake_move();
+            match check {
+                true => checks.push(mv),
+                false if n > 1 && !self.checks_only => others.push(mv),
+                false => {}
+            }
+        }
+        // Captures are likelier to break the defence than quiet moves
+        others.sort_by_key(|&mv| board.captured_by(mv).is_none());
+        checks.extend(others);
+        checks
+    }
+
+    /// Quickest mate in at most `n` moves, each defence putting it off as long as possible.
+    fn line(&mut self, board: &mut Board, n: u32) -> Vec<Move> {
+        let mut line = Vec::new();
+        let mut left = n;
+        'attack: while left > 0 {
+            for mv in self.attacks(board, left) {
+                board.make_move(mv);
+                if self.defence_fails(board, left) {
+                    line.push(mv);
+                    // The defence leaving the most moves, they all lose in time
+                    let defences = board.legal_moves();
+                    let longest = defences
+                        .into_iter()
+                        .filter_map(|defence| {
+                            board.make_move(defence);
+                            let distance = self.distance(board, left - 1);
+                            board.unmake_move();
+                            distance.map(|distance| (distance, defence))
+                        })
+                        .max_by_key(|&(distance, _)| distance);
+                    let Some((distance, defence)) = longest else {
+                        break 'attack;
+                    };
+                    board.make_move(defence);
+                    line.push(defence);
+                    left = distance;
+                    continue 'attack;
+                }
+                board.unmake_move();
+            }
+            break;
+        }
+        for _ in &line {
+            board.unmake_move();
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keys in SAN and length of the quickest mate, if any within `moves`.
+    fn solve(fen: &str, moves: u32, checks_only: bool) -> Option<(Vec<String>, u32)> {
+        let mut board = Board::new(false, 8, 8);
+        board.load_fen(fen).unwrap();
+        let solution = MateFinder::new(checks_only).solve(&mut board, moves)?;
+        let keys = solution.keys.iter().map(|&mv| board.san(mv)).collect();
+        Some((keys, solution.moves))
+    }
+
+    #[test]
+    fn sound() {
+        // The discovered check is the only key, Ka8 and Kb8 Qb7#, Ka6 Qa3#
+        let fen = "8/k2KQ3/8/8/8/8/8/8 w - - 0 1";
+        let expected = Some((vec!["Kc6+".to_string()], 2));
+        assert_eq!(solve(fen, 2, false), expected);
+        assert_eq!(solve(fen, 2, true), expected);
+        assert_eq!(solve(fen, 1, false), None);
+    }
+
+    #[test]
+    fn cooked() {
+        let (mut keys, moves) = solve("8/k7/2KR4/8/8/8/8/8 w - - 0 1", 2, false).unwrap();
+        keys.sort();
+        assert_eq!(
+            (keys, moves),
+            (vec!["Kc7".to_string(), "Rd8".to_string()], 2)
+        );
+    }
+
+    #[test]
+    fn quiet_key() {
+        // Rc6 leaves Black only Ka8, then Ra6#
+        let fen = "2K5/k7/8/8/8/8/8/2R5 w - - 0 1";
+        assert_eq!(solve(fen, 2, false), Some((vec!["Rc6".to_string()], 2)));
+        assert_eq!(solve(fen, 2, true), None);
+    }
+}
@@ -1,7 +1,65 @@
+use crate::board::Castling;
+use crate::eval::Score;
+use crate::piece::{Kind, Piece};
+
 #[derive(Debug)]
 pub enum MoveType {
     Empty,
     Take(u8),
     OccupiedBySameColor,
+    OutOfTurn,
     Illegal,
 }
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Move {
+    pub from: u8,
+    pub to: u8,
+    pub promotion: Option<Kind>,
+}
+
+/// What `Board::make_move` needs to remember to take a move back.
+#[derive(Copy, Clone, Debug)]
+pub struct Undo {
+    pub mv: Move,
+    /// Moving piece, as it was before a promotion.
+    pub piece: Piece,
+    pub captured: Option<Piece>,
+    /// Square of the captured piece, differs from the destination for en passant.
+    pub captured_at: u8,
+    pub castling: Castling,
+    pub en_passant: Option<u8>,
+    pub halfmove_clock: u32,
+    /// Position hash before the move.
+    pub hash: u64,
+    pub pawn_hash: u64,
+    /// Incremental evaluation before the move.
+    pub psqt: Score,
+    pub phase: i32,
+}
+
+/// What `Board::make_null_move` changes besides the turn.
+#[derive(Copy, Clone, Debug)]
+pub struct NullMove {
+    pub en_passant: Option<u8>,
+    pub halfmove_clock: u32,
+    pub hash: u64,
+}
+
+impl Move {
+    pub fn new(from: u8, to: u8) -> Self {
+        Move {
+            from,
+            to,
+            promotion: None,
+        }
+    }
+
+    pub fn with_promotion(from: u8, to: u8, kind: Kind) -> Self {
+        Move {
+            from,
+            to,
+            promotion: Some(kind),
+        }
+    }
+}
@@ -0,0 +1,485 @@
+use crate::board::Board;
+use crate::piece::{Color, Kind, Piece};
+use std::sync::Arc;
+
+const MAGIC: &[u8; 4] = b"NNUE";
+const VERSION: u32 = 1;
+/// Kinds standing as features, the kings only select which weights are used.
+const FEATURE_KINDS: [Kind; 5] = [
+    Kind::Pawn,
+    Kind::Knight,
+    Kind::Bishop,
+    Kind::Rook,
+    Kind::Queen,
+];
+/// Hidden units are clipped to this, the quantization of the first layer.
+const QA: i32 = 255;
+/// Quantization of the output weights.
+const QB: i32 = 64;
+/// Centipawns of an output of 1.0.
+const OUTPUT_SCALE: i32 = 400;
+/// Hidden units handled by one SIMD step, the layer size must be a multiple of it.
+const LANES: usize = 16;
+
+/// Network with HalfKP-like inputs: for each side, every non-king piece on every square
+/// relative to that side's king, seen with the board flipped for black. The two resulting
+/// accumulators, side to move first, go through a clipped ReLU into a single output.
+///
+/// File layout, little endian: `NNUE`, version (u32), files (u8), ranks (u8), hidden size
+/// (u16), feature weights (i16, one row of hidden size per feature), feature biases (i16),
+/// output weights (i16, twice the hidden size) and the output bias (i32).
+#[derive(Debug)]
+pub struct Network {
+    files: u8,
+    ranks: u8,
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i32,
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Network, String> {
+        let bytes =
+            std::fs::read(path).map_err(|error| format!("Couldn't read {path}: {error}"))?;
+        Network::parse(&bytes).map_err(|error| format!("{path}: {error}"))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Network, String> {
+        let mut reader = Reader { bytes, at: 0 };
+        if reader.take(4)? != MAGIC {
+            return Err("Not a network file.".to_string());
+        }
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(format!("Unsupported network version {version}."));
+        }
+        let files = reader.take(1)?[0];
+        let ranks = reader.take(1)?[0];
+        let hidden = u16::from_le_bytes(reader.take(2)?.try_into().unwrap()) as usize;
+        if hidden == 0 || !hidden.is_multiple_of(LANES) {
+            return Err(format!("Hidden size {hidden} isn't a multiple of {LANES}."));
+        }
+        let features = Network::feature_count(files, ranks);
+        let network = Network {
+            files,
+            ranks,
+            hidden,
+            feature_weights: reader.i16s(features * hidden)?,
+            feature_bias: reader.i16s(hidden)?,
+            output_weights: reader.i16s(2 * hidden)?,
+            output_bias: reader.u32()? as i32,
+        };
+        if reader.at != bytes.len() {
+            return Err("Trailing data after the network.".to_string());
+        }
+        Ok(network)
+    }
+
+    fn feature_count(files: u8, ranks: u8) -> usize {
+        let squares = files as usize * ranks as usize;
+        squares * 2 * FEATURE_KINDS.len() * squares
+    }
+
+    /// Input of `piece` on `index` for the accumulator of `perspective`, whose king is on `king`.
+    fn feature(&self, perspective: Color, king: u8, piece: Piece, index: u8) -> Option<usize> {
+        let kind = FEATURE_KINDS.iter().position(|&kind| kind == piece.kind)?;
+        let squares = self.files as usize * self.ranks as usize;
+        let relative = |index: u8| match perspective {
+            Color::White => index as usize,
+            Color::Black => {
+                let (file, rank) = (index % self.files, index / self.files);
+                file as usize + self.files as usize * (self.ranks - 1 - rank) as usize
+            }
+        };
+        let piece = 2 * kind + (piece.color != perspective) as usize;
+        Some((relative(king) * 2 * FEATURE_KINDS.len() + piece) * squares + relative(index))
+    }
+
+    fn row(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+}
+
+/// Hidden layer sums of both sides for one position, with the kings they were computed for.
+#[derive(Clone, Debug)]
+struct Accumulator {
+    values: [Vec<i16>; 2],
+    kings: [u8; 2],
+}
+
+/// Network in use by a board and its accumulators, one per position along the moves played
+/// so that unmaking a move only drops the top one.
+#[derive(Clone, Debug)]
+pub struct Nnue {
+    network: Arc<Network>,
+    stack: Vec<Accumulator>,
+    /// Accumulators in use, the ones above are kept to reuse their memory.
+    len: usize,
+}
+
+impl Nnue {
+    fn top(&mut self) -> &mut Accumulator {
+        &mut self.stack[self.len - 1]
+    }
+
+    /// Starts the accumulator of the next position as a copy of the current one.
+    pub(crate) fn push(&mut self) {
+        if self.stack.len() == self.len {
+            let copy = self.stack[self.len - 1].clone();
+            self.stack.push(copy);
+        } else {
+            let (below, above) = self.stack.split_at_mut(self.len);
+            let (from, to) = (&below[self.len - 1], &mut above[0]);
+            for side in 0..2 {
+                to.values[side].copy_from_slice(&from.values[side]);
+            }
+            to.kings = from.kings;
+        }
+        self.len += 1;
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.len -= 1;
+    }
+
+    /// Adds or removes the inputs of a piece, kings being left to `Board::refresh_accumulator`.
+    pub(crate) fn update(&mut self, piece: Piece, index: u8, add: bool) {
+        let network = Arc::clone(&self.network);
+        let accumulator = self.top();
+        for perspective in [Color::White, Color::Black] {
+            let king = accumulator.kings[perspective.index()];
+            let Some(feature) = network.feature(perspective, king, piece, index) else {
+                continue;
+            };
+            let values = &mut accumulator.values[perspective.index()];
+            match add {
+                true => simd::add(values, network.row(feature)),
+                false => simd::sub(values, network.row(feature)),
+            }
+        }
+    }
+
+    /// Output for the side to move, in centipawns.
+    pub fn evaluate(&self, turn: Color) -> i32 {
+        let network = &self.network;
+        let accumulator = &self.stack[self.len - 1];
+        let us = &accumulator.values[turn.index()];
+        let them = &accumulator.values[turn.opponent().index()];
+        let (own_weights, their_weights) = network.output_weights.split_at(network.hidden);
+        let sum = network.output_bias
+            + simd::clipped_dot(us, own_weights)
+            + simd::clipped_dot(them, their_weights);
+        (sum as i64 * OUTPUT_SCALE as i64 / (QA * QB) as i64) as i32
+    }
+}
+
+impl Board {
+    /// Evaluates with `network` from now on, or with the hand-crafted evaluation for `None`.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) -> Result<(), String> {
+        let Some(network) = network else {
+            self.nnue = None;
+            return Ok(());
+        };
+        if (network.files, network.ranks) != (self.files, self.ranks) {
+            return Err(format!(
+                "Network made for a {}x{} board, not {}x{}.",
+                network.files, network.ranks, self.files, self.ranks
+            ));
+        }
+        let hidden = network.hidden;
+        self.nnue = Some(Nnue {
+            network,
+            stack: vec![Accumulator {
+                values: [vec![0; hidden], vec![0; hidden]],
+                kings: [0; 2],
+            }],
+            len: 1,
+        });
+        self.refresh_nnue();
+        Ok(())
+    }
+
+    /// Computes both accumulators of the current position from scratch, forgetting the others.
+    pub(crate) fn refresh_nnue(&mut self) {
+        if let Some(nnue) = &mut self.nnue {
+            nnue.len = 1;
+        }
+        for color in [Color::White, Color::Black] {
+            self.refresh_accumulator(color);
+        }
+    }
+
+    /// Recomputes the accumulator of `perspective`, needed when its king has moved.
+    pub(crate) fn refresh_accumulator(&mut self, perspective: Color) {
+        let king = self.king_square(perspective).unwrap_or(0);
+        let pieces: Vec<_> = self.pieces().collect();
+        let Some(nnue) = &mut self.nnue else {
+            return;
+        };
+        let network = Arc::clone(&nnue.network);
+        let accumulator = nnue.top();
+        accumulator.kings[perspective.index()] = king;
+        let values = &mut accumulator.values[perspective.index()];
+        values.copy_from_slice(&network.feature_bias);
+        for (index, piece) in pieces {
+            if let Some(feature) = network.feature(perspective, king, piece, index) {
+                simd::add(values, network.row(feature));
+            }
+        }
+    }
+}
+
+/// Little endian reader of the network file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.at + count;
+        let bytes = self
+            .bytes
+            .get(self.at..end)
+            .ok_or("Network file is truncated.")?;
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i16s(&mut self, count: usize) -> Result<Vec<i16>, String> {
+        Ok(self
+            .take(2 * count)?
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect())
+    }
+}
+
+/// Vector loops of the network, with AVX2 when the CPU has it and plain code otherwise.
+mod simd {
+    pub fn add(values: &mut [i16], row: &[i16]) {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2, checked just above
+            return unsafe { avx2::add(values, row) };
+        }
+        scalar::add(values, row)
+    }
+
+    pub fn sub(values: &mut [i16], row: &[i16]) {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2, checked just above
+            return unsafe { avx2::sub(values, row) };
+        }
+        scalar::sub(values, row)
+    }
+
+    /// Sum of the values clipped to 0..=QA times the weights.
+    pub fn clipped_dot(values: &[i16], weights: &[i16]) -> i32 {
+        #[cfg(target_arch = "x86_64")]
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2, checked just above
+            return unsafe { avx2::clipped_dot(values, weights) };
+        }
+        scalar::clipped_dot(values, weights)
+    }
+
+    pub(super) mod scalar {
+        use super::super::QA;
+
+        pub fn add(values: &mut [i16], row: &[i16]) {
+            for (value, weight) in values.iter_mut().zip(row) {
+                *value = value.wrapping_add(*weight);
+            }
+        }
+
+        pub fn sub(values: &mut [i16], row: &[i16]) {
+            for (value, weight) in values.iter_mut().zip(row) {
+                *value = value.wrapping_sub(*weight);
+            }
+        }
+
+        pub fn clipped_dot(values: &[i16], weights: &[i16]) -> i32 {
+            values
+                .iter()
+                .zip(weights)
+                .map(|(&value, &weight)| (value as i32).clamp(0, QA) * weight as i32)
+                .sum()
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub(super) mod avx2 {
+        use super::super::{LANES, QA};
+        use std::arch::x86_64::*;
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn add(values: &mut [i16], row: &[i16]) {
+            for (values, row) in values.chunks_exact_mut(LANES).zip(row.chunks_exact(LANES)) {
+                let sum = _mm256_add_epi16(
+                    _mm256_loadu_si256(values.as_ptr().cast()),
+                    _mm256_loadu_si256(row.as_ptr().cast()),
+                );
+                _mm256_storeu_si256(values.as_mut_ptr().cast(), sum);
+            }
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn sub(values: &mut [i16], row: &[i16]) {
+            for (values, row) in values.chunks_exact_mut(LANES).zip(row.chunks_exact(LANES)) {
+                let difference = _mm256_sub_epi16(
+                    _mm256_loadu_si256(values.as_ptr().cast()),
+                    _mm256_loadu_si256(row.as_ptr().cast()),
+                );
+                _mm256_storeu_si256(values.as_mut_ptr().cast(), difference);
+            }
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn clipped_dot(values: &[i16], weights: &[i16]) -> i32 {
+            let zero = _mm256_setzero_si256();
+            let max = _mm256_set1_epi16(QA as i16);
+            let mut sums = _mm256_setzero_si256();
+            for (values, weights) in values.chunks_exact(LANES).zip(weights.chunks_exact(LANES)) {
+                let clipped = _mm256_min_epi16(
+                    _mm256_max_epi16(_mm256_loadu_si256(values.as_ptr().cast()), zero),
+                    max,
+                );
+                let products =
+                    _mm256_madd_epi16(clipped, _mm256_loadu_si256(weights.as_ptr().cast()));
+                sums = _mm256_add_epi32(sums, products);
+            }
+            let mut lanes = [0i32; 8];
+            _mm256_storeu_si256(lanes.as_mut_ptr().cast(), sums);
+            lanes.iter().sum()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::tests::PERFT;
+
+    /// Pseudo-random numbers in `-range..=range`.
+    fn noise(count: usize, range: i32) -> Vec<i16> {
+        let mut seed: u32 = 1;
+        (0..count)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                ((seed >> 8) as i32 % (2 * range + 1) - range) as i16
+            })
+            .collect()
+    }
+
+    /// An 8x8 network file with small weights.
+    fn file(hidden: u16) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend([8, 8]);
+        bytes.extend(hidden.to_le_bytes());
+        let weights = (Network::feature_count(8, 8) + 3) * hidden as usize;
+        for weight in noise(weights, 64) {
+            bytes.extend(weight.to_le_bytes());
+        }
+        bytes.extend(100i32.to_le_bytes());
+        bytes
+    }
+
+    /// The incremental accumulators match ones computed from scratch.
+    fn check(board: &Board) {
+        let mut fresh = board.clone();
+        fresh.refresh_nnue();
+        let top = |board: &Board| {
+            let nnue = board.nnue.as_ref().unwrap();
+            nnue.stack[nnue.len - 1].clone()
+        };
+        let (incremental, fresh) = (top(board), top(&fresh));
+        assert_eq!(incremental.kings, fresh.kings);
+        assert_eq!(incremental.values, fresh.values);
+    }
+
+    fn walk(board: &mut Board, depth: u32) {
+        check(board);
+        if depth == 0 {
+            return;
+        }
+        for mv in board.legal_moves() {
+            board.make_move(mv);
+            walk(board, depth - 1);
+            board.unmake_move();
+            check(board);
+        }
+    }
+
+    #[test]
+    fn incremental() {
+        let network = Arc::new(Network::parse(&file(32)).unwrap());
+        for (fen, _) in PERFT {
+            let mut board = Board::new(false, 8, 8);
+            board.load_fen(fen).unwrap();
+            board.set_network(Some(Arc::clone(&network))).unwrap();
+            walk(&mut board, 2);
+        }
+    }
+
+    #[test]
+    fn wrong_board() {
+        let network = Arc::new(Network::parse(&file(16)).unwrap());
+        assert!(Board::new(false, 10, 8).set_network(Some(network)).is_err());
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn avx2() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+        // Full range values, to cover wrapping and clipping on both sides
+        let row = noise(4 * LANES, i16::MAX as i32);
+        let values: Vec<i16> = row.iter().rev().copied().collect();
+        let (mut scalar, mut vector) = (values.clone(), values.clone());
+        simd::scalar::add(&mut scalar, &row);
+        // SAFETY: the CPU supports AVX2, checked above
+        unsafe { simd::avx2::add(&mut vector, &row) };
+        assert_eq!(scalar, vector);
+        simd::scalar::sub(&mut scalar, &row);
+        simd::scalar::sub(&mut scalar, &row);
+        // SAFETY: as above
+        unsafe {
+            simd::avx2::sub(&mut vector, &row);
+            simd::avx2::sub(&mut vector, &row);
+        }
+        assert_eq!(scalar, vector);
+        // SAFETY: as above
+        let dot = unsafe { simd::avx2::clipped_dot(&values, &row) };
+        assert_eq!(simd::scalar::clipped_dot(&values, &row), dot);
+    }
+
+    #[test]
+    fn rejected() {
+        let bytes = file(16);
+        assert!(Network::parse(&bytes).is_ok());
+        let error = Network::parse(&bytes[..bytes.len() - 1]).unwrap_err();
+        assert!(error.contains("truncated"), "{error}");
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(Network::parse(&trailing).is_err());
+        let mut magic = bytes.clone();
+        magic[0] = b'X';
+        assert!(Network::parse(&magic).is_err());
+        for hidden in [0u16, 24] {
+            let mut bad = bytes.clone();
+            bad[10..12].copy_from_slice(&hidden.to_le_bytes());
+            let error = Network::parse(&bad).unwrap_err();
+            assert!(error.contains("Hidden size"), "{error}");
+        }
+    }
+}
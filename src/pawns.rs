@@ -0,0 +1,200 @@
+use crate::bitmap::Bitmap;
+use crate::board::Board;
+use crate::eval::Score;
+use crate::piece::{Color, Kind};
+
+/// Pawn structure of a position, the same for every position sharing its pawns.
+#[derive(Copy, Clone, Debug)]
+pub struct PawnEntry {
+    key: u64,
+    /// Doubled, isolated, backward, connected pawns and islands, from white's point of view.
+    pub score: Score,
+    /// Passed pawns of both sides, scored with the pieces around them at each evaluation.
+    pub passed: Bitmap,
+}
+
+/// Cache of pawn structures indexed by the pawn hash. Pawns move rarely, so most lookups
+/// hit. Entries depend on the weights, so the table has to be cleared when they change.
+pub struct PawnTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+
+impl PawnTable {
+    /// Table taking about `megabytes` of memory.
+    pub fn new(megabytes: usize) -> Self {
+        let count = (megabytes * 1024 * 1024 / std::mem::size_of::<Option<PawnEntry>>()).max(1);
+        PawnTable {
+            entries: vec![None; count],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        ((key as u128 * self.entries.len() as u128) >> 64) as usize
+    }
+}
+
+impl Board {
+    /// Pawn structure, looked up in `table` before being computed.
+    pub fn pawn_structure(&self, table: &mut PawnTable) -> PawnEntry {
+        let slot = table.slot(self.pawn_hash);
+        if let Some(entry) = table.entries[slot].filter(|entry| entry.key == self.pawn_hash) {
+            return entry;
+        }
+        let entry = self.compute_pawn_structure();
+        table.entries[slot] = Some(entry);
+        entry
+    }
+
+    pub fn compute_pawn_structure(&self) -> PawnEntry {
+        let mut entry = PawnEntry {
+            key: self.pawn_hash,
+            score: Score::default(),
+            passed: Bitmap::new(),
+        };
+        for color in [Color::White, Color::Black] {
+            let score = self.side_pawn_structure(color, &mut entry.passed);
+            match color {
+                Color::White => entry.score += score,
+                Color::Black => entry.score -= score,
+            }
+        }
+        entry
+    }
+
+    /// Bonus of the passed pawns from white's point of view: by rank, less for each square
+    /// of the path to promotion that is occupied or attacked by the enemy, and in the
+    /// endgame by how close the kings are to the pawn's path.
+    pub fn passed_pawns(&self, passed: Bitmap) -> Score {
+        let weights = &self.weights.pawns;
+        let mut total = Score::default();
+        for index in passed.iter() {
+            let Some(pawn) = self.piece_at(index) else {
+                continue;
+            };
+            let rank = self.relative_rank(index, pawn.color);
+            let row = rank as usize * weights.passed_mg.len() / self.ranks as usize;
+            let mut score = Score::new(weights.passed_mg[row], weights.passed_eg[row]);
+            let front = self.offset(index, Board::pawn_forward(pawn.color));
+            if let Some(front) = front {
+                let (obstructed, length) = self.passed_path(front, pawn.color);
+                let kept = 100 - (100 - weights.passed_blocked) * obstructed / length;
+                score.mg = score.mg * kept / 100;
+                score.eg = score.eg * kept / 100;
+                // Kings matter more the further the pawn has gone
+                let scale = (row as i32 - 2).max(0);
+                let distance = |color: Color| {
+                    self.king_square(color)
+                        .map_or(0, |king| self.distance(king, front) as i32)
+                };
+                let [own, enemy] = weights.passed_king;
+                score.eg +=
+                    scale * (own * distance(pawn.color) + enemy * distance(pawn.color.opponent()));
+            }
+            match pawn.color {
+                Color::White => total += score,
+                Color::Black => total -= score,
+            }
+        }
+        total
+    }
+
+    /// Squares of the path from `front` to promotion of a `color` pawn that are occupied
+    /// or attacked by the enemy, and the length of the path.
+    fn passed_path(&self, front: u8, color: Color) -> (i32, i32) {
+        let (mut obstructed, mut length) = (0, 0);
+        let mut square = Some(front);
+        while let Some(index) = square {
+            length += 1;
+            if self.piece_at(index).is_some() || self.is_attacked(index, color.opponent()) {
+                obstructed += 1;
+            }
+            square = self.offset(index, Board::pawn_forward(color));
+        }
+        (obstructed, length)
+    }
+
+    /// Number of king moves between two squares.
+    pub fn distance(&self, a: u8, b: u8) -> u8 {
+        let (file_a, rank_a) = self.index_to_file_rank(a);
+        let (file_b, rank_b) = self.index_to_file_rank(b);
+        file_a.abs_diff(file_b).max(rank_a.abs_diff(rank_b))
+    }
+
+    /// Rank counted from the side of `color`, 0 being its back rank.
+    pub fn relative_rank(&self, index: u8, color: Color) -> u8 {
+        let (_, rank) = self.index_to_file_rank(index);
+        match color {
+            Color::White => rank,
+            Color::Black => self.ranks - 1 - rank,
+        }
+    }
+
+    /// Structure terms of one side's pawns, marking its passed pawns.
+    fn side_pawn_structure(&self, color: Color, passed: &mut Bitmap) -> Score {
+        let weights = &self.weights.pawns;
+        let files = self.files as usize;
+        // Relative ranks of the pawns standing on each file
+        let mut own: Vec<Vec<u8>> = vec![vec![]; files];
+        let mut enemy: Vec<Vec<u8>> = vec![vec![]; files];
+        for (index, piece) in self.pieces().filter(|(_, piece)| piece.kind == Kind::Pawn) {
+            let (file, _) = self.index_to_file_rank(index);
+            let rank = self.relative_rank(index, color);
+            match piece.color == color {
+                true => own[file as usize].push(rank),
+                false => enemy[file as usize].push(rank),
+            }
+        }
+        let neighbours = |file: usize| file.saturating_sub(1)..(file + 2).min(files);
+
+        let mut score = Score::default();
+        for (index, _) in self
+            .pieces()
+            .filter(|(_, piece)| piece.kind == Kind::Pawn && piece.color == color)
+        {
+            let (file, _) = self.index_to_file_rank(index);
+            let file = file as usize;
+            let rank = self.relative_rank(index, color);
+            let adjacent = || neighbours(file).filter(move |&other| other != file);
+
+            if own[file].iter().any(|&other| other > rank) {
+                score += weights.doubled;
+            }
+            let isolated = adjacent().all(|other| own[other].is_empty());
+            if isolated {
+                score += weights.isolated;
+            }
+            let phalanx_or_defended =
+                adjacent().any(|other| own[other].iter().any(|&r| r == rank || r + 1 == rank));
+            if phalanx_or_defended {
+                score += weights.connected;
+            }
+            // Every neighbour has gone ahead and an enemy pawn guards the square in front
+            let left_behind = adjacent().all(|other| own[other].iter().all(|&r| r > rank));
+            let stop_guarded = adjacent().any(|other| enemy[other].contains(&(rank + 2)));
+            if !isolated && left_behind && stop_guarded {
+                score += weights.backward;
+            }
+            if neighbours(file).all(|other| enemy[other].iter().all(|&r| r <= rank)) {
+                passed.set(index);
+            }
+        }
+
+        let mut islands = 0;
+        let mut on_island = false;
+        for pawns in own.iter() {
+            if !pawns.is_empty() && !on_island {
+                islands += 1;
+            }
+            on_island = !pawns.is_empty();
+        }
+        if islands > 1 {
+            score.mg += weights.island.mg * (islands - 1);
+            score.eg += weights.island.eg * (islands - 1);
+        }
+        score
+    }
+}
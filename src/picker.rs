@@ -0,0 +1,291 @@
+use crate::board::{Board, Targets, MAX_SIDE};
+use crate::moves::Move;
+use crate::piece::{Color, Kind};
+use crate::pins::Legality;
+
+const SQUARES: usize = MAX_SIDE as usize * MAX_SIDE as usize;
+/// History scores are kept within this bound, older results fading as new ones come in.
+const HISTORY_MAX: i32 = 16_384;
+
+/// What the search learned about quiet moves, shared by every node.
+pub struct Heuristics {
+    /// Two quiet moves per ply that recently caused a cutoff.
+    killers: Vec<[Option<Move>; 2]>,
+    /// Quiet move that refuted each move of the opponent, indexed by its from and to.
+    counter_moves: Vec<Option<Move>>,
+    /// Cutoffs made by each quiet move, by side to move, from and to.
+    history: Vec<i32>,
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Heuristics {
+            killers: vec![],
+            counter_moves: vec![None; SQUARES * SQUARES],
+            history: vec![0; 2 * SQUARES * SQUARES],
+        }
+    }
+}
+
+impl Heuristics {
+    /// Drops what only held for the previous search, history is scaled down.
+    pub fn new_search(&mut self) {
+        self.killers.clear();
+        for score in self.history.iter_mut() {
+            *score /= 2;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Heuristics::default();
+    }
+
+    pub fn killers(&self, ply: i32) -> [Option<Move>; 2] {
+        self.killers.get(ply as usize).copied().unwrap_or_default()
+    }
+
+    pub fn counter_move(&self, previous: Option<Move>) -> Option<Move> {
+        previous.and_then(|previous| self.counter_moves[Heuristics::move_index(previous)])
+    }
+
+    pub fn history(&self, color: Color, mv: Move) -> i32 {
+        self.history[color.index() * SQUARES * SQUARES + Heuristics::move_index(mv)]
+    }
+
+    /// Rewards the quiet move that caused a cutoff and punishes those tried before it.
+    /// `previous` is the opponent's move it answered, `None` after a null move.
+    pub fn cutoff(
+        &mut self,
+        board: &Board,
+        mv: Move,
+        previous: Option<Move>,
+        tried: &[Move],
+        ply: i32,
+        depth: u32,
+    ) {
+        let ply = ply as usize;
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(mv) {
+            killers[1] = killers[0];
+            killers[0] = Some(mv);
+        }
+        if let Some(previous) = previous {
+            self.counter_moves[Heuristics::move_index(previous)] = Some(mv);
+        }
+        let bonus = (depth * depth).min(HISTORY_MAX as u32) as i32;
+        self.update_history(board.turn, mv, bonus);
+        for &other in tried {
+            self.update_history(board.turn, other, -bonus);
+        }
+    }
+
+    fn update_history(&mut self, color: Color, mv: Move, bonus: i32) {
+        let score =
+            &mut self.history[color.index() * SQUARES * SQUARES + Heuristics::move_index(mv)];
+        // Pulled towards the bound so that a score can't grow without limit
+        *score += bonus - *score * bonus.abs() / HISTORY_MAX;
+    }
+
+    fn move_index(mv: Move) -> usize {
+        mv.from as usize * SQUARES + mv.to as usize
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Stage {
+    HashMove,
+    GenerateNoisy,
+    GoodCaptures,
+    Promotions,
+    Killers,
+    CounterMove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+/// Hands out the moves of a node best first, generating them stage by stage so that an
+/// early cutoff spares the work of the later stages: hash move, winning captures by
+/// MVV-LVA, promotions, killers, counter-move, quiet moves by history, losing captures.
+pub struct MovePicker {
+    stage: Stage,
+    hash_move: Option<Move>,
+    killers: [Option<Move>; 2],
+    counter_move: Option<Move>,
+    /// Quiet moves are skipped, as in the quiescence search.
+    noisy_only: bool,
+    /// Moves of the current stage with their score, the best is taken out first.
+    scored: Vec<(Move, i32)>,
+    bad_captures: Vec<(Move, i32)>,
+    promotions: Vec<(Move, i32)>,
+    /// Moves already handed out ahead of their stage.
+    played_early: Vec<Move>,
+    /// Pins and check of the side to move, found when the first move is generated.
+    legality: Option<Legality>,
+}
+
+impl MovePicker {
+    /// Picker of every move, `previous` being the opponent's last move, `None` after a null
+    /// move.
+    pub fn new(
+        hash_move: Option<Move>,
+        previous: Option<Move>,
+        heuristics: &Heuristics,
+        ply: i32,
+    ) -> Self {
+        MovePicker {
+            stage: Stage::HashMove,
+            hash_move,
+            killers: heuristics.killers(ply),
+            counter_move: heuristics.counter_move(previous),
+            noisy_only: false,
+            scored: vec![],
+            bad_captures: vec![],
+            promotions: vec![],
+            played_early: vec![],
+            legality: None,
+        }
+    }
+
+    /// Picker of captures and queen promotions, the losing captures left out.
+    pub fn noisy(hash_move: Option<Move>) -> Self {
+        MovePicker {
+            stage: Stage::HashMove,
+            hash_move,
+            killers: [None; 2],
+            counter_move: None,
+            noisy_only: true,
+            scored: vec![],
+            bad_captures: vec![],
+            promotions: vec![],
+            played_early: vec![],
+            legality: None,
+        }
+    }
+
+    pub fn next(&mut self, board: &mut Board, heuristics: &Heuristics) -> Option<Move> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateNoisy;
+                    if let Some(mv) = self.hash_move {
+                        let allowed = !self.noisy_only || board.is_noisy(mv);
+                        if allowed && board.is_legal_with(mv, self.legality(board)) {
+                            self.played_early.push(mv);
+                            return Some(mv);
+                        }
+                    }
+                }
+                Stage::GenerateNoisy => {
+                    let legality = self.legality(board);
+                    for mv in board.legal_moves_of(Targets::Noisy, legality) {
+                        if self.played_early.contains(&mv) {
+                            continue;
+                        }
+                        // Under-promotions, capturing or not, are left to the main search
+                        let under_promotion = mv.promotion.is_some_and(|kind| kind != Kind::Queen);
+                        if self.noisy_only && under_promotion {
+                            continue;
+                        }
+                        let Some(victim) = board.captured_by(mv) else {
+                            let score = mv.promotion.map_or(0, |kind| kind.value());
+                            self.promotions.push((mv, score));
+                            continue;
+                        };
+                        // Most valuable victim first, then least valuable attacker
+                        let attacker = board.piece_at(mv.from).expect("No piece.");
+                        let promotion = mv.promotion.map_or(0, |kind| kind.value());
+                        let score = 10 * (victim.kind.value() + promotion) - attacker.kind.value();
+                        match board.see(mv.from, mv.to) >= 0 {
+                            true => self.scored.push((mv, score)),
+                            false => self.bad_captures.push((mv, score)),
+                        }
+                    }
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => match MovePicker::take_best(&mut self.scored) {
+                    Some(mv) => return Some(mv),
+                    None => {
+                        self.scored = std::mem::take(&mut self.promotions);
+                        self.stage = Stage::Promotions;
+                    }
+                },
+                Stage::Promotions => match MovePicker::take_best(&mut self.scored) {
+                    Some(mv) => return Some(mv),
+                    None => {
+                        self.stage = match self.noisy_only {
+                            true => Stage::Done,
+                            false => Stage::Killers,
+                        }
+                    }
+                },
+                Stage::Killers => {
+                    let killer = self.killers.iter_mut().find_map(|killer| killer.take());
+                    match killer {
+                        Some(mv) => {
+                            if self.try_early(board, mv) {
+                                return Some(mv);
+                            }
+                        }
+                        None => self.stage = Stage::CounterMove,
+                    }
+                }
+                Stage::CounterMove => {
+                    self.stage = Stage::GenerateQuiets;
+                    if let Some(mv) = self.counter_move {
+                        if self.try_early(board, mv) {
+                            return Some(mv);
+                        }
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    let turn = board.turn;
+                    let legality = self.legality(board);
+                    self.scored = board
+                        .legal_moves_of(Targets::Quiet, legality)
+                        .into_iter()
+                        .filter(|mv| !self.played_early.contains(mv))
+                        .map(|mv| (mv, heuristics.history(turn, mv)))
+                        .collect();
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => match MovePicker::take_best(&mut self.scored) {
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stage::BadCaptures,
+                },
+                Stage::BadCaptures => match MovePicker::take_best(&mut self.bad_captures) {
+                    Some(mv) => return Some(mv),
+                    None => self.stage = Stage::Done,
+                },
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Quiet move remembered from elsewhere in the tree, played now if legal here.
+    fn try_early(&mut self, board: &mut Board, mv: Move) -> bool {
+        if self.played_early.contains(&mv) || board.is_noisy(mv) {
+            return false;
+        }
+        if !board.is_legal_with(mv, self.legality(board)) {
+            return false;
+        }
+        self.played_early.push(mv);
+        true
+    }
+
+    fn legality(&mut self, board: &Board) -> &Legality {
+        self.legality
+            .get_or_insert_with(|| board.legality(board.turn))
+    }
+
+    /// Selection of the best scored move, cheaper than sorting when a cutoff comes early.
+    fn take_best(moves: &mut Vec<(Move, i32)>) -> Option<Move> {
+        let best = (0..moves.len()).max_by_key(|&i| moves[i].1)?;
+        Some(moves.swap_remove(best).0)
+    }
+}
@@ -1,10 +1,3 @@
-use crate::piece::Color::{Black, White};
-use opengl_graphics::{Texture, TextureSettings};
-use sprite::*;
-use std::collections::HashMap;
-use std::rc::Rc;
-use uuid::Uuid;
-
 #[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
 pub enum Color {
     White,
@@ -20,94 +13,43 @@ pub enum Kind {
     King,
 }
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Piece {
     pub kind: Kind,
-    pub sprite_id: Uuid,
     pub id: u8,
     pub color: Color,
 }
 
-fn get_set() -> HashMap<(Kind, Color), String> {
-    let mut set = HashMap::new();
-    set.insert((Kind::Pawn, White), String::from("wP.png"));
-    set.insert((Kind::Bishop, White), String::from("wB.png"));
-    set.insert((Kind::Knight, White), String::from("wN.png"));
-    set.insert((Kind::King, White), String::from("wK.png"));
-    set.insert((Kind::Queen, White), String::from("wQ.png"));
-    set.insert((Kind::Rook, White), String::from("wR.png"));
-    set.insert((Kind::Pawn, Black), String::from("bP.png"));
-    set.insert((Kind::Bishop, Black), String::from("bB.png"));
-    set.insert((Kind::Knight, Black), String::from("bN.png"));
-    set.insert((Kind::King, Black), String::from("bK.png"));
-    set.insert((Kind::Queen, Black), String::from("bQ.png"));
-    set.insert((Kind::Rook, Black), String::from("bR.png"));
-    set
-}
-
-impl Piece {
-    pub fn new(kind: Kind, color: Color, scene: &mut Scene<Texture>, id: u8) -> Piece {
-        let set: HashMap<(Kind, Color), String> = get_set();
-        let assets = find_folder::Search::ParentsThenKids(1, 1)
-            .for_folder("assets")
-            .unwrap();
-        let s = set.get(&(kind, color)).unwrap();
-        let tex = Rc::new(
-            Texture::from_path(
-                assets.join(format!("set/merida/{s}")),
-                &TextureSettings::new(),
-            )
-            .unwrap(),
-        );
-        let mut sprite = Sprite::from_texture(tex);
-        sprite.set_scale(0.0, 0.0);
-        let sprite_id: Uuid = scene.add_child(sprite);
-
-        Piece {
-            kind,
-            color,
-            sprite_id,
-            id,
+impl Color {
+    pub fn opponent(self) -> Color {
+        match self {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
         }
     }
 
-    pub fn find_color_for_id(pieces: &Vec<Piece>, id: u8) -> Option<Color> {
-        let mut color: Option<Color> = None;
-        for piece in pieces.iter() {
-            if piece.id == id {
-                color = Some(piece.color)
-            }
-        }
-        color
-    }
-
-    pub fn find_sprite_id_for_id(pieces: &Vec<Piece>, id: u8) -> Option<Uuid> {
-        let mut sprite_id = None;
-        for piece in pieces.iter() {
-            if piece.id == id {
-                sprite_id = Some(piece.sprite_id)
-            }
-        }
-        sprite_id
+    /// Position of the color in tables holding one entry per side.
+    pub fn index(self) -> usize {
+        self as usize
     }
+}
 
-    pub fn remove_id_from_pieces(pieces: &mut Vec<Piece>, id: u8) {
-        for (idx, piece) in pieces.iter().enumerate() {
-            if piece.id == id {
-                pieces.remove(idx);
-                break;
-            }
+impl Kind {
+    /// Material value in centipawns, kings can't be traded.
+    pub fn value(self) -> i32 {
+        match self {
+            Kind::Pawn => 100,
+            Kind::Knight => 300,
+            Kind::Bishop => 300,
+            Kind::Rook => 500,
+            Kind::Queen => 900,
+            Kind::King => 0,
         }
     }
+}
 
-    pub fn get_piece_from_id(pieces: &Vec<Piece>, id: u8) -> Option<&Piece> {
-        let mut return_piece = None;
-        for piece in pieces.iter() {
-            if piece.id == id {
-                return_piece = Some(piece);
-                break;
-            }
-        }
-        return_piece
+impl Piece {
+    pub fn new(kind: Kind, color: Color, id: u8) -> Piece {
+        Piece { kind, color, id }
     }
 }
@@ -0,0 +1,140 @@
+use crate::bitmap::Bitmap;
+use crate::board::{Board, BISHOP_DELTAS, QUEEN_DELTAS, ROOK_DELTAS};
+use crate::piece::{Color, Kind};
+
+/// A piece alone on the line between a king and an enemy slider.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Pin {
+    pub pinned: u8,
+    pub pinner: u8,
+    /// Squares the pinned piece may still move to, up to and including the pinner.
+    pub ray: Bitmap,
+}
+
+/// A piece that unveils an attack on the enemy king from a friendly slider when it moves.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Discovery {
+    pub piece: u8,
+    pub attacker: u8,
+    /// Squares between the attacker and the king, the attacker included.
+    pub ray: Bitmap,
+}
+
+/// Pins and check of a side, what telling its legal moves apart needs. Found once for all
+/// the moves tried in a position.
+pub struct Legality {
+    pub pins: Vec<Pin>,
+    pub in_check: bool,
+}
+
+/// A lone piece standing between a slider and a target square.
+struct Alignment {
+    blocker: u8,
+    slider: u8,
+    ray: Bitmap,
+}
+
+impl Board {
+    pub fn legality(&self, color: Color) -> Legality {
+        Legality {
+            pins: self.pins(color),
+            in_check: self.in_check(color),
+        }
+    }
+
+    /// Absolutely pinned pieces of a side.
+    pub fn pins(&self, color: Color) -> Vec<Pin> {
+        let Some(king) = self.king_square(color) else {
+            return vec![];
+        };
+        self.alignments(king, color, color.opponent())
+            .into_iter()
+            .map(|a| Pin {
+                pinned: a.blocker,
+                pinner: a.slider,
+                ray: a.ray,
+            })
+            .collect()
+    }
+
+    /// Pieces of a side whose move would give a discovered check.
+    pub fn discovered_attacks(&self, color: Color) -> Vec<Discovery> {
+        let Some(king) = self.king_square(color.opponent()) else {
+            return vec![];
+        };
+        self.alignments(king, color, color)
+            .into_iter()
+            .map(|a| Discovery {
+                piece: a.blocker,
+                attacker: a.slider,
+                ray: a.ray,
+            })
+            .collect()
+    }
+
+    /// Sliders of a side attacking `index` through exactly one piece of either color.
+    pub fn xray_attackers(&self, index: u8, color: Color) -> Bitmap {
+        let mut attackers = Bitmap::new();
+        for delta in QUEEN_DELTAS {
+            let mut square = index;
+            let mut blockers = 0;
+            while let Some(next) = self.offset(square, delta) {
+                square = next;
+                let Some(piece) = self.piece_at(square) else {
+                    continue;
+                };
+                if blockers == 1 {
+                    if piece.color == color && Board::slides_along(piece.kind, delta) {
+                        attackers.set(square);
+                    }
+                    break;
+                }
+                blockers += 1;
+            }
+        }
+        attackers
+    }
+
+    /// Walks every line from `target` looking for a piece of `blocker_color` followed by a
+    /// slider of `slider_color` moving along that line.
+    fn alignments(&self, target: u8, blocker_color: Color, slider_color: Color) -> Vec<Alignment> {
+        let mut alignments = vec![];
+        for delta in QUEEN_DELTAS {
+            let mut square = target;
+            let mut ray = Bitmap::new();
+            let mut blocker = None;
+            while let Some(next) = self.offset(square, delta) {
+                square = next;
+                ray.set(square);
+                let Some(piece) = self.piece_at(square) else {
+                    continue;
+                };
+                match blocker {
+                    None if piece.color == blocker_color => blocker = Some(square),
+                    Some(blocker)
+                        if piece.color == slider_color
+                            && Board::slides_along(piece.kind, delta) =>
+                    {
+                        alignments.push(Alignment {
+                            blocker,
+                            slider: square,
+                            ray,
+                        });
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        alignments
+    }
+
+    fn slides_along(kind: Kind, delta: (i16, i16)) -> bool {
+        match kind {
+            Kind::Queen => true,
+            Kind::Rook => ROOK_DELTAS.contains(&delta),
+            Kind::Bishop => BISHOP_DELTAS.contains(&delta),
+            _ => false,
+        }
+    }
+}
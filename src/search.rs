@@ -0,0 +1,948 @@
+use crate::board::Board;
+use crate::endgames::{Dtm, Endgames};
+use crate::moves::Move;
+use crate::pawns::PawnTable;
+use crate::picker::{Heuristics, MovePicker};
+use crate::piece::Kind;
+use crate::skill::Skill;
+use crate::syzygy::{RootProbe, Tablebases, Wdl};
+use crate::tt::{Bound, TranspositionTable};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Score of being mated on the spot, mates further away are worth a ply less each.
+pub const MATE: i32 = 30_000;
+const INFINITY: i32 = 32_000;
+/// Scores beyond this are mates.
+pub const MATE_BOUND: i32 = MATE - 1000;
+/// Plies from the root past which the search returns the static evaluation.
+const MAX_PLY: i32 = 128;
+/// Score of a tablebase win, a ply less for each ply from the root, below all mates.
+pub const TB_WIN: i32 = MATE_BOUND - 1 - MAX_PLY;
+/// Scores beyond this are tablebase wins or mates, counted from the root.
+pub const TB_BOUND: i32 = TB_WIN - MAX_PLY;
+/// Extra depth tablebase results are stored with, no search is more accurate.
+const TB_DEPTH_BONUS: u32 = 6;
+/// First iteration searched with an aspiration window.
+const ASPIRATION_MIN_DEPTH: u32 = 4;
+/// Deepest iteration tried when no other limit stops the search.
+pub const MAX_DEPTH: u32 = 64;
+/// Nodes searched between two looks at the clock and the stop flag.
+const CHECK_INTERVAL: u64 = 1024;
+/// Margin over the captured piece under which a capture can't bring the score back to alpha.
+const DELTA_MARGIN: i32 = 200;
+/// Size of the pawn structure cache.
+const PAWN_TABLE_MEGABYTES: usize = 2;
+/// Kept off the remaining clock for the time it takes to play the move.
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+/// Helper threads skip iterations in these patterns so that they don't all search the same
+/// depth: a helper skips `depth` when `(depth + phase) / size` is odd.
+const SKIP_SIZE: [u32; 20] = [1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4];
+const SKIP_PHASE: [u32; 20] = [0, 1, 0, 1, 2, 3, 0, 1, 2, 3, 4, 5, 0, 1, 2, 3, 4, 5, 6, 7];
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    /// Centipawns from the point of view of the side to move.
+    pub score: i32,
+    /// Depth of the last completed iteration.
+    pub depth: u32,
+    pub nodes: u64,
+    /// Best lines found, one per root move searched in MultiPV, the best move's first.
+    pub lines: Vec<Line>,
+    /// The root was found in the tablebases, which picked the move.
+    pub tablebase: Option<RootProbe>,
+    /// The root was found in the generated endgame tables, which picked the move.
+    pub endgame: Option<Dtm>,
+}
+
+/// A principal variation and its score for the side to move at the root.
+#[derive(Clone, Debug)]
+pub struct Line {
+    pub moves: Vec<Move>,
+    pub score: i32,
+}
+
+/// Time left on a side's clock and what it gains after each move.
+#[derive(Copy, Clone, Debug)]
+pub struct Clock {
+    pub remaining: Duration,
+    pub increment: Duration,
+}
+
+/// When to stop deepening, the first limit reached ends the search.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<Duration>,
+    pub clock: Option<Clock>,
+}
+
+impl Limits {
+    pub fn depth(depth: u32) -> Self {
+        Limits {
+            depth: Some(depth),
+            ..Limits::default()
+        }
+    }
+
+    /// Time the search may take: the fixed move time, or a share of the clock.
+    pub fn budget(&self) -> Option<Duration> {
+        let from_clock = self.clock.map(|clock| {
+            let usable = clock.remaining.saturating_sub(MOVE_OVERHEAD);
+            let share = usable / 30 + clock.increment * 3 / 4;
+            share.min(usable)
+        });
+        match (self.movetime, from_clock) {
+            (Some(movetime), Some(from_clock)) => Some(movetime.min(from_clock)),
+            (movetime, from_clock) => movetime.or(from_clock),
+        }
+    }
+}
+
+/// Switches and margins of the selective search, so that each technique can be measured in
+/// self-play. Margins are in centipawns per ply of depth left.
+#[derive(Copy, Clone, Debug)]
+pub struct SearchParams {
+    /// Principal variation search: moves after the first are tried with a null window.
+    pub pvs: bool,
+    /// Half width of the window around the previous iteration's score, 0 to disable.
+    pub aspiration_window: i32,
+    pub null_move: bool,
+    /// Plies saved by the null move search, on top of one more every 6 plies of depth.
+    pub null_move_reduction: u32,
+    pub null_move_min_depth: u32,
+    /// Depth from which null move cutoffs are verified, 0 to never verify.
+    pub null_move_verify_depth: u32,
+    /// Late move reductions.
+    pub lmr: bool,
+    /// At least 3, a reduced move keeps one ply to search.
+    pub lmr_min_depth: u32,
+    /// Moves searched at full depth before reducing.
+    pub lmr_min_moves: u32,
+    pub futility: bool,
+    pub futility_margin: i32,
+    pub futility_max_depth: u32,
+    pub reverse_futility: bool,
+    pub reverse_futility_margin: i32,
+    pub reverse_futility_max_depth: u32,
+    /// Searching one ply deeper when in check.
+    pub check_extension: bool,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        SearchParams {
+            pvs: true,
+            aspiration_window: 25,
+            null_move: true,
+            null_move_reduction: 2,
+            null_move_min_depth: 3,
+            null_move_verify_depth: 8,
+            lmr: true,
+            lmr_min_depth: 3,
+            lmr_min_moves: 3,
+            futility: true,
+            futility_margin: 150,
+            futility_max_depth: 3,
+            reverse_futility: true,
+            reverse_futility_margin: 120,
+            reverse_futility_max_depth: 6,
+            check_extension: true,
+        }
+    }
+}
+
+impl SearchParams {
+    /// Sets a parameter by its field name, switches take 0 or 1.
+    pub fn set(&mut self, name: &str, value: i64) -> Result<(), String> {
+        let flag = value != 0;
+        let count = u32::try_from(value).map_err(|_| format!("{name} can't be {value}"))?;
+        let margin = value as i32;
+        match name {
+            "pvs" => self.pvs = flag,
+            "aspiration_window" => self.aspiration_window = margin,
+            "null_move" => self.null_move = flag,
+            "null_move_reduction" => self.null_move_reduction = count,
+            "null_move_min_depth" => self.null_move_min_depth = count,
+            "null_move_verify_depth" => self.null_move_verify_depth = count,
+            "lmr" => self.lmr = flag,
+            "lmr_min_depth" => self.lmr_min_depth = count.max(3),
+            "lmr_min_moves" => self.lmr_min_moves = count,
+            "futility" => self.futility = flag,
+            "futility_margin" => self.futility_margin = margin,
+            "futility_max_depth" => self.futility_max_depth = count,
+            "reverse_futility" => self.reverse_futility = flag,
+            "reverse_futility_margin" => self.reverse_futility_margin = margin,
+            "reverse_futility_max_depth" => self.reverse_futility_max_depth = count,
+            "check_extension" => self.check_extension = flag,
+            _ => return Err(format!("Unknown search parameter {name}")),
+        }
+        Ok(())
+    }
+}
+
+/// Iterative deepening negamax alpha-beta search over the legal move generator.
+pub struct Search {
+    pub nodes: u64,
+    /// Kept from one search to the next, cleared between games. Shared with the helpers.
+    pub tt: Arc<TranspositionTable>,
+    pub pawns: PawnTable,
+    pub heuristics: Heuristics,
+    pub params: SearchParams,
+    /// Raised from another thread to end the search, the last completed iteration is kept.
+    /// Left raised when a search ends, the caller lowers it before starting the next one so
+    /// that a stop sent meanwhile isn't lost.
+    pub stop: Arc<AtomicBool>,
+    /// Raised while searching on the opponent's time: no limit but the depth applies, and the
+    /// result waits, until it is lowered on a ponderhit. The clock starts then.
+    pub pondering: Arc<AtomicBool>,
+    /// Syzygy tables probed at the root and after captures and pawn moves.
+    pub tablebases: Option<Arc<Tablebases>>,
+    /// Distance-to-mate tables probed like the Syzygy ones, first.
+    pub endgames: Option<Arc<Endgames>>,
+    /// Threads searching the root together, this one included (Lazy SMP).
+    pub threads: usize,
+    /// Principal variations searched, each excluding the root moves of the ones before.
+    pub multi_pv: usize,
+    /// Handicap for weaker play, full strength by default.
+    pub skill: Skill,
+    helpers: Vec<Search>,
+    /// Size and phase of the iterations skipped, for helpers.
+    skip: Option<(u32, u32)>,
+    budget: Option<Duration>,
+    deadline: Option<Instant>,
+    node_limit: Option<u64>,
+    iteration: u32,
+    aborted: bool,
+    /// Move played at each ply of the current line, `None` for a null move.
+    path: Vec<Option<Move>>,
+}
+
+impl Search {
+    /// Search with a transposition table of `hash_megabytes`.
+    pub fn new(hash_megabytes: usize) -> Self {
+        Search::sharing(Arc::new(TranspositionTable::new(hash_megabytes)))
+    }
+
+    fn sharing(tt: Arc<TranspositionTable>) -> Self {
+        Search {
+            nodes: 0,
+            tt,
+            pawns: PawnTable::new(PAWN_TABLE_MEGABYTES),
+            heuristics: Heuristics::default(),
+            params: SearchParams::default(),
+            stop: Arc::default(),
+            pondering: Arc::default(),
+            tablebases: None,
+            endgames: None,
+            threads: 1,
+            multi_pv: 1,
+            skill: Skill::default(),
+            helpers: Vec::new(),
+            skip: None,
+            budget: None,
+            deadline: None,
+            node_limit: None,
+            iteration: 0,
+            aborted: false,
+            path: Vec::new(),
+        }
+    }
+
+    /// Forgets what was learned from the previous game.
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.heuristics.clear();
+        self.pawns.clear();
+        for helper in &mut self.helpers {
+            helper.clear();
+        }
+    }
+
+    /// Best move for the side to move, deepening one ply at a time until a limit is reached.
+    /// Helper threads search the same root meanwhile, filling the shared transposition table,
+    /// and the deepest completed iteration of all threads is kept.
+    pub fn search(&mut self, board: &mut Board, limits: &Limits) -> SearchResult {
+        self.tt.new_search();
+
+        let mut result = SearchResult {
+            best_move: None,
+            score: Search::terminal_score(board, 0),
+            depth: 0,
+            nodes: 0,
+            lines: Vec::new(),
+            tablebase: None,
+            endgame: None,
+        };
+        if board.legal_moves().is_empty() {
+            return result;
+        }
+        let endgames = self.endgames.clone().filter(|_| !self.skill.enabled());
+        if let Some((mv, dtm)) = endgames.and_then(|endgames| endgames.best_move(board)) {
+            result.best_move = Some(mv);
+            result.score = Search::dtm_score(dtm, 0);
+            result.lines.push(Line {
+                moves: vec![mv],
+                score: result.score,
+            });
+            result.endgame = Some(dtm);
+            return result;
+        }
+        // A weakened engine doesn't get perfect endgame play
+        let tablebases = self.tablebases.clone().filter(|_| !self.skill.enabled());
+        if let Some(probe) = tablebases.and_then(|tablebases| tablebases.probe_root(board)) {
+            result.best_move = Some(probe.best_move);
+            result.score = Search::tablebase_score(probe.wdl, 0);
+            result.lines.push(Line {
+                moves: vec![probe.best_move],
+                score: result.score,
+            });
+            result.tablebase = Some(probe);
+            return result;
+        }
+
+        let limits = &self.skill.limits(limits);
+        self.spawn_helpers();
+        let mut helpers = std::mem::take(&mut self.helpers);
+        // Helpers run until the main thread is done, only the depth limit applies to them
+        let helper_limits = Limits {
+            depth: limits.depth,
+            ..Limits::default()
+        };
+        let stop = self.stop.clone();
+        let (main, others) = thread::scope(|scope| {
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .map(|helper| {
+                    let mut board = board.clone();
+                    let result = result.clone();
+                    scope.spawn(move || helper.deepen(&mut board, &helper_limits, result))
+                })
+                .collect();
+            let main = self.deepen(board, limits, result);
+            // Done early while pondering, the helpers keep going until the opponent moves
+            while self.pondering.load(Ordering::Relaxed) && !stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            stop.store(true, Ordering::Relaxed);
+            let others: Vec<_> = handles
+                .into_iter()
+                .map(|handle| handle.join().expect("Search thread panicked."))
+                .collect();
+            (main, others)
+        });
+        self.helpers = helpers;
+
+        let nodes = main.nodes + others.iter().map(|other| other.nodes).sum::<u64>();
+        let mut best = main;
+        for other in others {
+            if other.depth > best.depth {
+                best = other;
+            }
+        }
+        best.nodes = nodes;
+        if self.skill.enabled() {
+            let random = RandomState::new().build_hasher().finish();
+            if let Some(mv) = self.skill.pick(&best.lines, random) {
+                let line = best.lines.iter().find(|line| line.moves[0] == mv).unwrap();
+                best.best_move = Some(mv);
+                best.score = line.score;
+            }
+        }
+        best
+    }
+
+    /// Creates or drops helpers to match the thread count and hands them the settings.
+    fn spawn_helpers(&mut self) {
+        // A weakened engine searches alone: helpers would ignore its node limit, and even
+        // their table entries would let the main thread search deeper within it
+        let count = match self.skill.enabled() {
+            true => 0,
+            false => self.threads.max(1) - 1,
+        };
+        self.helpers.truncate(count);
+        while self.helpers.len() < count {
+            let pattern = self.helpers.len() % SKIP_SIZE.len();
+            let mut helper = Search::sharing(self.tt.clone());
+            helper.stop = self.stop.clone();
+            helper.skip = Some((SKIP_SIZE[pattern], SKIP_PHASE[pattern]));
+            self.helpers.push(helper);
+        }
+        for helper in &mut self.helpers {
+            helper.params = self.params;
+            helper.multi_pv = self.multi_pv;
+            helper.skill = self.skill;
+            helper.tablebases = self.tablebases.clone();
+            helper.endgames = self.endgames.clone();
+        }
+    }
+
+    /// Iterative deepening from `result`, the outcome of the root checks, on one thread.
+    fn deepen(
+        &mut self,
+        board: &mut Board,
+        limits: &Limits,
+        mut result: SearchResult,
+    ) -> SearchResult {
+        self.nodes = 0;
+        self.aborted = false;
+        self.budget = limits.budget();
+        self.deadline = match self.pondering.load(Ordering::Relaxed) {
+            true => None,
+            false => self.budget.map(|budget| Instant::now() + budget),
+        };
+        self.node_limit = limits.nodes;
+        self.iteration = 0;
+        self.heuristics.new_search();
+        let max_depth = limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+        let wanted = self.multi_pv.max(self.skill.candidates());
+        let line_count = wanted.clamp(1, board.legal_moves().len());
+        'deepening: for depth in 1..=max_depth {
+            if let Some((size, phase)) = self.skip {
+                if (depth + phase) / size % 2 == 1 {
+                    continue;
+                }
+            }
+            // Each line is searched without the first moves of the better ones
+            let mut lines: Vec<Line> = Vec::with_capacity(line_count);
+            for index in 0..line_count {
+                let excluded: Vec<Move> = lines.iter().map(|line| line.moves[0]).collect();
+                let previous = result.lines.get(index);
+                let Some((mv, score)) = self.aspiration(board, depth, previous, &excluded) else {
+                    break 'deepening;
+                };
+                let moves = self.principal_variation(board, mv, depth);
+                lines.push(Line { moves, score });
+            }
+            lines.sort_by_key(|line| -line.score);
+            result = SearchResult {
+                best_move: Some(lines[0].moves[0]),
+                score: lines[0].score,
+                depth,
+                nodes: self.nodes,
+                lines,
+                tablebase: None,
+                endgame: None,
+            };
+            // Forced mates found: deeper iterations can't change the outcome
+            let mated = |line: &Line| line.score.abs() >= MATE - depth as i32;
+            if result.lines.iter().all(mated) {
+                break;
+            }
+        }
+        result.nodes = self.nodes;
+        result
+    }
+
+    /// Searches the root in a window around the previous score, widening it each time the
+    /// score falls outside. The `excluded` root moves are left out.
+    fn aspiration(
+        &mut self,
+        board: &mut Board,
+        depth: u32,
+        previous: Option<&Line>,
+        excluded: &[Move],
+    ) -> Option<(Move, i32)> {
+        let mut window = self.params.aspiration_window;
+        let previous_score = previous.map_or(0, |line| line.score);
+        let narrow = window > 0
+            && depth >= ASPIRATION_MIN_DEPTH
+            && previous.is_some()
+            && previous_score.abs() < TB_BOUND;
+        let (mut alpha, mut beta) = match narrow {
+            true => (previous_score - window, previous_score + window),
+            false => (-INFINITY, INFINITY),
+        };
+        let mut best_move = previous
+            .map(|line| line.moves[0])
+            .filter(|mv| !excluded.contains(mv));
+        loop {
+            let (found, score) =
+                self.search_root(board, depth, best_move, alpha, beta, excluded)?;
+            best_move = found.or(best_move);
+            window = window.saturating_mul(2);
+            if score <= alpha && alpha > -INFINITY {
+                alpha = (score - window).max(-INFINITY);
+            } else if score >= beta && beta < INFINITY {
+                beta = (score + window).min(INFINITY);
+            } else {
+                return best_move.map(|mv| (mv, score));
+            }
+        }
+    }
+
+    /// One iteration over the root moves but the `excluded` ones, trying the previous best
+    /// move first. `None` when a limit interrupted it, its result can't be trusted then. The
+    /// move is `None` when no move reached `alpha`.
+    fn search_root(
+        &mut self,
+        board: &mut Board,
+        depth: u32,
+        previous_best: Option<Move>,
+        mut alpha: i32,
+        beta: i32,
+        excluded: &[Move],
+    ) -> Option<(Option<Move>, i32)> {
+        let previous = board.history.last().map(|undo| undo.mv);
+        let mut picker = MovePicker::new(previous_best, previous, &self.heuristics, 0);
+        self.iteration = depth;
+        let mut best_move = None;
+        let mut played = 0;
+        while let Some(mv) = picker.next(board, &self.heuristics) {
+            if excluded.contains(&mv) {
+                continue;
+            }
+            played += 1;
+            self.record(0, Some(mv));
+            board.make_move(mv);
+            let mut score;
+            if played > 1 && self.params.pvs {
+                score = -self.negamax(board, depth - 1, 1, -alpha - 1, -alpha, true);
+                if score > alpha && score < beta {
+                    score = -self.negamax(board, depth - 1, 1, -beta, -alpha, true);
+                }
+            } else {
+                score = -self.negamax(board, depth - 1, 1, -beta, -alpha, true);
+            }
+            board.unmake_move();
+            if self.aborted {
+                return None;
+            }
+            if score >= beta {
+                if excluded.is_empty() {
+                    self.tt
+                        .store(board.hash, Some(mv), beta, depth, Bound::Lower, 0);
+                }
+                return Some((Some(mv), beta));
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+            }
+        }
+        // Not the root's value when moves were left out
+        if excluded.is_empty() {
+            let bound = match best_move {
+                Some(_) => Bound::Exact,
+                None => Bound::Upper,
+            };
+            self.tt.store(board.hash, best_move, alpha, depth, bound, 0);
+        }
+        Some((best_move, alpha))
+    }
+
+    /// The line starting with `first`, followed through the best moves of the transposition
+    /// table for at most `depth` plies.
+    fn principal_variation(&self, board: &mut Board, first: Move, depth: u32) -> Vec<Move> {
+        let mut moves = vec![first];
+        board.make_move(first);
+        while moves.len() < depth as usize && !board.is_repetition() {
+            let Some(mv) = self.tt.probe(board.hash).and_then(|entry| entry.best_move) else {
+                break;
+            };
+            if !board.legal_moves().contains(&mv) {
+                break;
+            }
+            board.make_move(mv);
+            moves.push(mv);
+        }
+        for _ in &moves {
+            board.unmake_move();
+        }
+        moves
+    }
+
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        mut depth: u32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        null_allowed: bool,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            self.check_limits();
+        }
+        if self.aborted {
+            return 0;
+        }
+        if board.king_square(board.turn).is_none() {
+            // Only happens in fog-of-war, where kings can be taken
+            return -MATE + ply;
+        }
+        if board.halfmove_clock >= 100 || board.is_repetition() {
+            return 0;
+        }
+        if ply >= MAX_PLY {
+            return board.evaluate(&mut self.pawns);
+        }
+        let in_check = board.in_check(board.turn);
+        if in_check && self.params.check_extension {
+            depth += 1;
+        }
+        if depth == 0 {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+
+        let entry = self.tt.probe(board.hash);
+        if let Some(entry) = entry {
+            let score = entry.score_at(ply);
+            if entry.depth as u32 >= depth && entry.cuts(score, alpha, beta) {
+                return score;
+            }
+        }
+        // Right after a capture or pawn move the tables know the result, if they hold it
+        if board.halfmove_clock == 0 {
+            let endgames = self.endgames.clone();
+            if let Some(dtm) = endgames.and_then(|endgames| endgames.probe(board)) {
+                let score = Search::dtm_score(dtm, ply);
+                let depth = (depth + TB_DEPTH_BONUS).min(MAX_DEPTH);
+                self.tt
+                    .store(board.hash, None, score, depth, Bound::Exact, ply);
+                return score;
+            }
+            let tablebases = self.tablebases.clone();
+            if let Some(wdl) = tablebases.and_then(|tablebases| tablebases.probe_wdl(board)) {
+                let score = Search::tablebase_score(wdl, ply);
+                let bound = match wdl {
+                    Wdl::Win => Bound::Lower,
+                    Wdl::Loss => Bound::Upper,
+                    _ => Bound::Exact,
+                };
+                let cuts = match bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cuts {
+                    let depth = (depth + TB_DEPTH_BONUS).min(MAX_DEPTH);
+                    self.tt.store(board.hash, None, score, depth, bound, ply);
+                    return score;
+                }
+            }
+        }
+
+        let pv_node = beta - alpha > 1;
+        let static_eval = match in_check {
+            true => None,
+            false => Some(board.evaluate(&mut self.pawns)),
+        };
+        if let (Some(eval), false) = (static_eval, pv_node) {
+            if let Some(score) = self.prune_early(board, depth, ply, eval, beta, null_allowed) {
+                return score;
+            }
+        }
+        // Quiet moves can't bring a hopeless position back to alpha this close to the leaves
+        let futile = self.params.futility
+            && !pv_node
+            && depth <= self.params.futility_max_depth
+            && alpha.abs() < TB_BOUND
+            && static_eval
+                .is_some_and(|eval| eval + self.params.futility_margin * depth as i32 <= alpha);
+
+        let hash_move = entry.and_then(|entry| entry.best_move);
+        let previous = self.previous_move(ply);
+        let mut picker = MovePicker::new(hash_move, previous, &self.heuristics, ply);
+        let mut best_move = None;
+        let mut played = 0;
+        let mut quiets_tried = vec![];
+        while let Some(mv) = picker.next(board, &self.heuristics) {
+            played += 1;
+            let quiet = !board.is_noisy(mv);
+            self.record(ply, Some(mv));
+            board.make_move(mv);
+            let gives_check = board.in_check(board.turn);
+            let tactical = !quiet || in_check || gives_check;
+            if futile && !tactical && played > 1 {
+                board.unmake_move();
+                continue;
+            }
+
+            let reduction = match tactical {
+                true => 0,
+                false => self.reduction(depth, played),
+            };
+            // Later moves are first searched with a null window, proving they are no better
+            let zero_window = self.params.pvs && played > 1;
+            let window_beta = if zero_window { alpha + 1 } else { beta };
+            let mut score = -self.negamax(
+                board,
+                depth - 1 - reduction,
+                ply + 1,
+                -window_beta,
+                -alpha,
+                true,
+            );
+            if reduction > 0 && score > alpha {
+                score = -self.negamax(board, depth - 1, ply + 1, -window_beta, -alpha, true);
+            }
+            if zero_window && score > alpha && score < beta {
+                score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha, true);
+            }
+            board.unmake_move();
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                if quiet {
+                    self.heuristics
+                        .cutoff(board, mv, previous, &quiets_tried, ply, depth);
+                }
+                self.tt
+                    .store(board.hash, Some(mv), beta, depth, Bound::Lower, ply);
+                return beta;
+            }
+            if quiet {
+                quiets_tried.push(mv);
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(mv);
+            }
+        }
+        if played == 0 {
+            return Search::terminal_score(board, ply);
+        }
+        let bound = match best_move {
+            Some(_) => Bound::Exact,
+            None => Bound::Upper,
+        };
+        self.tt
+            .store(board.hash, best_move, alpha, depth, bound, ply);
+        alpha
+    }
+
+    /// Reverse futility and null-move pruning, tried before any move in a node that isn't on
+    /// the principal variation and isn't in check. Returns the score to cut the node with.
+    fn prune_early(
+        &mut self,
+        board: &mut Board,
+        depth: u32,
+        ply: i32,
+        eval: i32,
+        beta: i32,
+        null_allowed: bool,
+    ) -> Option<i32> {
+        let params = &self.params;
+        if beta.abs() >= TB_BOUND {
+            return None;
+        }
+        // So far above beta that the opponent can't catch up in the plies left
+        if params.reverse_futility
+            && depth <= params.reverse_futility_max_depth
+            && eval - params.reverse_futility_margin * depth as i32 >= beta
+        {
+            return Some(beta);
+        }
+
+        // Passing the turn is a bad idea in zugzwang, which mostly happens when only the king
+        // and pawns are left
+        let has_pieces = board.pieces().any(|(_, piece)| {
+            piece.color == board.turn && !matches!(piece.kind, Kind::Pawn | Kind::King)
+        });
+        if !params.null_move
+            || !null_allowed
+            || !has_pieces
+            || depth < params.null_move_min_depth
+            || eval < beta
+        {
+            return None;
+        }
+        let reduction = params.null_move_reduction + depth / 6;
+        let verify_depth = params.null_move_verify_depth;
+        let null_depth = depth.saturating_sub(1 + reduction);
+        self.record(ply, None);
+        let null = board.make_null_move();
+        let score = -self.negamax(board, null_depth, ply + 1, -beta, -beta + 1, false);
+        board.unmake_null_move(null);
+        if self.aborted || score < beta {
+            return None;
+        }
+        // Deep cutoffs are confirmed by a reduced search of our own moves, in case of zugzwang
+        if verify_depth > 0 && depth >= verify_depth {
+            let verified = self.negamax(board, null_depth, ply, beta - 1, beta, false);
+            if self.aborted || verified < beta {
+                return None;
+            }
+        }
+        Some(beta)
+    }
+
+    /// Plies taken off the search of a late quiet move.
+    fn reduction(&self, depth: u32, played: u32) -> u32 {
+        let params = &self.params;
+        if !params.lmr || depth < params.lmr_min_depth || played <= params.lmr_min_moves {
+            return 0;
+        }
+        let reduction = ((depth as f32).ln() * (played as f32).ln() / 2.0) as u32;
+        reduction.clamp(1, depth - 2)
+    }
+
+    /// Plays out captures until the position is quiet so that the evaluation isn't taken in
+    /// the middle of an exchange. The side to move may stand pat instead of capturing.
+    fn quiescence(&mut self, board: &mut Board, ply: i32, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            self.check_limits();
+        }
+        if self.aborted {
+            return 0;
+        }
+        if board.king_square(board.turn).is_none() {
+            return -MATE + ply;
+        }
+
+        // In check every evasion is searched, standing pat isn't an option. Otherwise only
+        // captures and queen promotions are, the picker leaving out captures losing material.
+        let in_check = board.in_check(board.turn);
+        let stand_pat = board.evaluate(&mut self.pawns);
+        let mut picker = match in_check {
+            true => MovePicker::new(None, self.previous_move(ply), &self.heuristics, ply),
+            false => {
+                if stand_pat >= beta {
+                    return beta;
+                }
+                alpha = alpha.max(stand_pat);
+                MovePicker::noisy(None)
+            }
+        };
+
+        let mut played = 0;
+        while let Some(mv) = picker.next(board, &self.heuristics) {
+            played += 1;
+            if !in_check {
+                let victim = board.captured_by(mv).map_or(0, |piece| piece.kind.value());
+                let promotion = mv
+                    .promotion
+                    .map_or(0, |kind| kind.value() - Kind::Pawn.value());
+                if stand_pat + victim + promotion + DELTA_MARGIN < alpha {
+                    continue;
+                }
+            }
+            self.record(ply, Some(mv));
+            board.make_move(mv);
+            let score = -self.quiescence(board, ply + 1, -beta, -alpha);
+            board.unmake_move();
+            if self.aborted {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+        if in_check && played == 0 {
+            return Search::terminal_score(board, ply);
+        }
+        alpha
+    }
+
+    /// Remembers the move played at `ply` for the counter-moves of the node below.
+    fn record(&mut self, ply: i32, mv: Option<Move>) {
+        let ply = ply as usize;
+        if self.path.len() <= ply {
+            self.path.resize(ply + 1, None);
+        }
+        self.path[ply] = mv;
+    }
+
+    /// Move that led to the node at `ply`, below the root.
+    fn previous_move(&self, ply: i32) -> Option<Move> {
+        self.path[ply as usize - 1]
+    }
+
+    fn check_limits(&mut self) {
+        // The first iteration always completes so that there is a move to play
+        if self.iteration <= 1 {
+            return;
+        }
+        if self.stop.load(Ordering::Relaxed) {
+            self.aborted = true;
+        }
+        if self.aborted || self.pondering.load(Ordering::Relaxed) {
+            return;
+        }
+        // Pondered until now, the clock starts on the ponderhit
+        if self.deadline.is_none() {
+            self.deadline = self.budget.map(|budget| Instant::now() + budget);
+        }
+        let out_of_time = self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline);
+        let out_of_nodes = self.node_limit.is_some_and(|limit| self.nodes >= limit);
+        if out_of_time || out_of_nodes {
+            self.aborted = true;
+        }
+    }
+
+    /// Score of a tablebase result `ply` plies from the root, wins sooner are better.
+    fn tablebase_score(wdl: Wdl, ply: i32) -> i32 {
+        match wdl {
+            Wdl::Win => TB_WIN - ply,
+            Wdl::Loss => -TB_WIN + ply,
+            // Results drawn by the fifty-move rule keep their sign
+            wdl => wdl as i32,
+        }
+    }
+
+    /// Mate score of a result from the endgame tables `ply` plies from the root.
+    fn dtm_score(dtm: Dtm, ply: i32) -> i32 {
+        match dtm {
+            Dtm::Win(plies) => MATE - ply - plies as i32,
+            Dtm::Draw => 0,
+            Dtm::Loss(plies) => -MATE + ply + plies as i32,
+        }
+    }
+
+    /// Score when the side to move has no legal move: mated or stalemated.
+    fn terminal_score(board: &Board, ply: i32) -> i32 {
+        match board.in_check(board.turn) {
+            true => -MATE + ply,
+            false => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    /// A ponder miss stops the search whether it comes before or right after the thread starts.
+    #[test]
+    fn stop_while_pondering() {
+        for stop_first in [true, false] {
+            let mut search = Search::new(1);
+            search.threads = 2;
+            search.stop.store(false, Ordering::Relaxed);
+            search.pondering.store(true, Ordering::Relaxed);
+            let stop = search.stop.clone();
+            if stop_first {
+                stop.store(true, Ordering::Relaxed);
+            }
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || {
+                let mut board = Board::new(false, 8, 8);
+                board
+                    .load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                    .unwrap();
+                let limits = Limits {
+                    movetime: Some(Duration::from_secs(1)),
+                    ..Limits::default()
+                };
+                let _ = sender.send(search.search(&mut board, &limits));
+            });
+            stop.store(true, Ordering::Relaxed);
+            let result = receiver.recv_timeout(Duration::from_secs(30));
+            assert!(result.is_ok_and(|result| result.best_move.is_some()));
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::bitmap::Bitmap;
+use crate::board::{Board, BISHOP_DELTAS, KNIGHT_DELTAS, QUEEN_DELTAS, ROOK_DELTAS};
+use crate::piece::{Color, Kind};
+
+/// Worth of a king in an exchange, it may only capture last.
+const KING_VALUE: i32 = 20_000;
+
+impl Board {
+    /// Static exchange evaluation: material the side moving from `from` to `to` wins, in
+    /// centipawns, once both sides have made every profitable recapture on `to`.
+    /// Pins are ignored, sliders lined up behind each other join in as the front ones go.
+    pub fn see(&self, from: u8, to: u8) -> i32 {
+        let Some(piece) = self.piece_at(from) else {
+            return 0;
+        };
+        let mut removed = Bitmap::new();
+        removed.set(from);
+        let victim = match self.piece_at(to) {
+            Some(victim) => Board::see_value(victim.kind),
+            None if piece.kind == Kind::Pawn && self.en_passant == Some(to) => {
+                let (_, rank) = self.index_to_file_rank(from);
+                let (file, _) = self.index_to_file_rank(to);
+                removed.set(self.file_rank_to_index(file, rank));
+                Kind::Pawn.value()
+            }
+            None => 0,
+        };
+
+        // gains[d] is what the side making the d-th capture has won if the exchange stops there
+        let mut gains = vec![victim];
+        let mut on_square = Board::see_value(piece.kind);
+        let mut side = piece.color.opponent();
+        while let Some((square, kind)) = self.least_valuable_attacker(to, side, &removed) {
+            let gain = on_square - gains[gains.len() - 1];
+            gains.push(gain);
+            removed.set(square);
+            on_square = Board::see_value(kind);
+            side = side.opponent();
+        }
+        // Each side stops capturing as soon as going on would lose more
+        while gains.len() > 1 {
+            let last = gains.pop().unwrap();
+            let previous = gains.last_mut().unwrap();
+            *previous = -(-*previous).max(last);
+        }
+        gains[0]
+    }
+
+    /// Cheapest piece of `color` attacking `index`, looking through the `removed` squares.
+    fn least_valuable_attacker(
+        &self,
+        index: u8,
+        color: Color,
+        removed: &Bitmap,
+    ) -> Option<(u8, Kind)> {
+        let mut best: Option<(u8, Kind)> = None;
+        let mut consider = |square: u8, kind: Kind| {
+            if best
+                .is_none_or(|(_, best_kind)| Board::see_value(kind) < Board::see_value(best_kind))
+            {
+                best = Some((square, kind));
+            }
+        };
+        let mut look = |delta: (i16, i16), kinds: &[Kind], slide: bool| {
+            let mut square = index;
+            while let Some(next) = self.offset(square, delta) {
+                square = next;
+                if !removed.get(square) {
+                    if let Some(piece) = self.piece_at(square) {
+                        if piece.color == color && kinds.contains(&piece.kind) {
+                            consider(square, piece.kind);
+                        }
+                        break;
+                    }
+                }
+                if !slide {
+                    break;
+                }
+            }
+        };
+        for (df, dr) in Board::pawn_attacks(color) {
+            look((-df, -dr), &[Kind::Pawn], false);
+        }
+        for delta in KNIGHT_DELTAS {
+            look(delta, &[Kind::Knight], false);
+        }
+        for delta in QUEEN_DELTAS {
+            look(delta, &[Kind::King], false);
+        }
+        for delta in BISHOP_DELTAS {
+            look(delta, &[Kind::Bishop, Kind::Queen], true);
+        }
+        for delta in ROOK_DELTAS {
+            look(delta, &[Kind::Rook, Kind::Queen], true);
+        }
+        best
+    }
+
+    fn see_value(kind: Kind) -> i32 {
+        match kind {
+            Kind::King => KING_VALUE,
+            kind => kind.value(),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::moves::Move;
+use crate::search::{Limits, Line};
+
+/// Full strength, no handicap.
+pub const MAX_LEVEL: u8 = 20;
+/// Elo range `elo` is clamped to, mapped linearly onto the levels. A rough guess, the levels
+/// haven't been measured against rated engines.
+pub const MIN_ELO: u32 = 800;
+pub const MAX_ELO: u32 = 2500;
+/// Lines searched to choose the played move from.
+const CANDIDATES: usize = 4;
+/// Most a worse line is pushed up by chance, in centipawns.
+const MAX_SPREAD: i32 = 100;
+
+/// Handicap of the engine, set like the `Skill Level`, `UCI_LimitStrength` and `UCI_Elo`
+/// options of UCI engines. A weakened engine searches less deep and fewer nodes, then plays
+/// one of its best few moves at random, the weaker the more likely a worse one.
+#[derive(Copy, Clone, Debug)]
+pub struct Skill {
+    /// From 0 to `MAX_LEVEL`, used unless the strength is limited by Elo.
+    pub level: u8,
+    pub limit_strength: bool,
+    pub elo: u32,
+}
+
+impl Default for Skill {
+    fn default() -> Self {
+        Skill {
+            level: MAX_LEVEL,
+            limit_strength: false,
+            elo: 1500,
+        }
+    }
+}
+
+impl Skill {
+    /// Level in effect, between two levels when set by Elo.
+    pub fn effective_level(&self) -> f64 {
+        match self.limit_strength {
+            true => {
+                let elo = self.elo.clamp(MIN_ELO, MAX_ELO);
+                (elo - MIN_ELO) as f64 * MAX_LEVEL as f64 / (MAX_ELO - MIN_ELO) as f64
+            }
+            false => self.level.min(MAX_LEVEL) as f64,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.effective_level() < MAX_LEVEL as f64
+    }
+
+    /// Principal variations the weakened engine needs to pick from.
+    pub fn candidates(&self) -> usize {
+        match self.enabled() {
+            true => CANDIDATES,
+            false => 1,
+        }
+    }
+
+    /// `limits` tightened to the level: depth 1 and a thousand nodes at level 0, a ply more
+    /// and quadratically more nodes for each level.
+    pub fn limits(&self, limits: &Limits) -> Limits {
+        if !self.enabled() {
+            return *limits;
+        }
+        let level = self.effective_level();
+        let depth = 1 + level.round() as u32;
+        let nodes = ((level + 1.0).powi(2) * 1000.0) as u64;
+        Limits {
+            depth: Some(limits.depth.map_or(depth, |limit| limit.min(depth))),
+            nodes: Some(limits.nodes.map_or(nodes, |limit| limit.min(nodes))),
+            ..*limits
+        }
+    }
+
+    /// Move played from `lines`, sorted best first. Each line's score is pushed up by how
+    /// much worse than the best line it is, scaled by the weakness, plus a random share of
+    /// the spread of the scores; the highest pushed score wins.
+    pub fn pick(&self, lines: &[Line], random: u64) -> Option<Move> {
+        let (best, worst) = (lines.first()?.score, lines.last()?.score);
+        let weakness = 120.0 - 2.0 * self.effective_level();
+        let spread = (best - worst).min(MAX_SPREAD) as f64;
+        let mut state = random | 1;
+        lines
+            .iter()
+            .max_by_key(|line| {
+                // Xorshift, a new draw for each line
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let chance = (state % 1000) as f64 / 1000.0 * weakness;
+                let push = (weakness * (best - line.score) as f64 + spread * chance) / 128.0;
+                line.score + push as i32
+            })
+            .map(|line| line.moves[0])
+    }
+}
@@ -0,0 +1,43 @@
+use chess::piece::Color::{Black, White};
+use chess::piece::{Color, Kind};
+use opengl_graphics::{Texture, TextureSettings};
+use sprite::*;
+use std::collections::HashMap;
+use std::rc::Rc;
+use uuid::Uuid;
+
+fn get_set() -> HashMap<(Kind, Color), String> {
+    let mut set = HashMap::new();
+    set.insert((Kind::Pawn, White), String::from("wP.png"));
+    set.insert((Kind::Bishop, White), String::from("wB.png"));
+    set.insert((Kind::Knight, White), String::from("wN.png"));
+    set.insert((Kind::King, White), String::from("wK.png"));
+    set.insert((Kind::Queen, White), String::from("wQ.png"));
+    set.insert((Kind::Rook, White), String::from("wR.png"));
+    set.insert((Kind::Pawn, Black), String::from("bP.png"));
+    set.insert((Kind::Bishop, Black), String::from("bB.png"));
+    set.insert((Kind::Knight, Black), String::from("bN.png"));
+    set.insert((Kind::King, Black), String::from("bK.png"));
+    set.insert((Kind::Queen, Black), String::from("bQ.png"));
+    set.insert((Kind::Rook, Black), String::from("bR.png"));
+    set
+}
+
+/// Adds the sprite of a piece to the scene, hidden until it is first placed.
+pub fn new_piece_sprite(kind: Kind, color: Color, scene: &mut Scene<Texture>) -> Uuid {
+    let set: HashMap<(Kind, Color), String> = get_set();
+    let assets = find_folder::Search::ParentsThenKids(1, 1)
+        .for_folder("assets")
+        .unwrap();
+    let s = set.get(&(kind, color)).unwrap();
+    let tex = Rc::new(
+        Texture::from_path(
+            assets.join(format!("set/merida/{s}")),
+            &TextureSettings::new(),
+        )
+        .unwrap(),
+    );
+    let mut sprite = Sprite::from_texture(tex);
+    sprite.set_scale(0.0, 0.0);
+    scene.add_child(sprite)
+}
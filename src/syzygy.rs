@@ -0,0 +1,1518 @@
+use crate::board::{Board, Castling, Variant};
+use crate::moves::Move;
+use crate::piece::{Color, Kind};
+use std::collections::HashMap;
+use std::ops::Neg;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+/// Most pieces a table can hold, kings included.
+const MAX_PIECES: usize = 7;
+/// Letters of the pieces in table names, strongest first.
+const NAME_ORDER: [(Kind, char); 6] = [
+    (Kind::King, 'K'),
+    (Kind::Queen, 'Q'),
+    (Kind::Rook, 'R'),
+    (Kind::Bishop, 'B'),
+    (Kind::Knight, 'N'),
+    (Kind::Pawn, 'P'),
+];
+
+// Flags of the compressed data, the first one tells which side a DTZ table is for
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+/// Result of a position with perfect play, cursed wins and blessed losses being drawn by
+/// the fifty-move rule.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Wdl {
+    Loss = -2,
+    BlessedLoss = -1,
+    Draw = 0,
+    CursedWin = 1,
+    Win = 2,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            i32::MIN..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    /// DTZ of the move before a capture or pawn move reaching this result.
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Wdl::Win => 1,
+            Wdl::CursedWin => 101,
+            Wdl::BlessedLoss => -101,
+            Wdl::Loss => -1,
+            Wdl::Draw => 0,
+        }
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-(self as i32))
+    }
+}
+
+/// How a table probe went besides its value.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum State {
+    Ok,
+    /// The DTZ table only holds the other side to move.
+    ChangeStm,
+    /// The best move is a capture or pawn move, the DTZ table can't be trusted.
+    ZeroingBestMove,
+}
+
+/// Best root move according to the tables.
+#[derive(Copy, Clone, Debug)]
+pub struct RootProbe {
+    pub best_move: Move,
+    pub wdl: Wdl,
+    /// Plies to the next capture or pawn move with best play, positive when winning.
+    pub dtz: i32,
+}
+
+/// Syzygy tablebases found in a set of directories, loaded as they are first probed.
+pub struct Tablebases {
+    /// Tables by the name of the material of each side, white first, both ways round.
+    tables: HashMap<String, Arc<Table>>,
+    /// Most pieces of any table found.
+    pub max_pieces: usize,
+}
+
+impl Tablebases {
+    /// Looks for the `.rtbw` files in `paths`, separated like the `PATH` variable.
+    pub fn open(paths: &str) -> Result<Tablebases, String> {
+        let mut tables = HashMap::new();
+        let mut max_pieces = 0;
+        for directory in std::env::split_paths(paths) {
+            let entries = std::fs::read_dir(&directory)
+                .map_err(|error| format!("{}: {error}", directory.display()))?;
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_none_or(|extension| extension != "rtbw") {
+                    continue;
+                }
+                let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+                let Some(table) = Table::new(name, directory.clone()) else {
+                    continue;
+                };
+                max_pieces = max_pieces.max(table.piece_count);
+                let table = Arc::new(table);
+                let (white, black) = name.split_once('v').unwrap();
+                tables.insert(format!("{black}v{white}"), table.clone());
+                tables.insert(name.to_string(), table);
+            }
+        }
+        Ok(Tablebases { tables, max_pieces })
+    }
+
+    /// The tables may know the position: standard chess, no castling, few enough pieces.
+    pub fn covers(&self, board: &Board) -> bool {
+        (board.files, board.ranks) == (8, 8)
+            && board.variant == Variant::Standard
+            && board.castling == Castling::default()
+            && match board.pieces().count() {
+                2 => true,
+                count if count > self.max_pieces => false,
+                _ => self.tables.contains_key(&Table::name(board)),
+            }
+    }
+
+    /// Result for the side to move, ignoring the moves played so far.
+    pub fn probe_wdl(&self, board: &mut Board) -> Option<Wdl> {
+        if !self.covers(board) {
+            return None;
+        }
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    /// Plies to the next capture or pawn move that keeps the result, positive when the side
+    /// to move wins, with 100 added for results spoiled by the fifty-move rule.
+    pub fn probe_dtz(&self, board: &mut Board) -> Option<i32> {
+        if !self.covers(board) {
+            return None;
+        }
+        self.dtz(board)
+    }
+
+    /// Move keeping the best result and making progress towards it, accounting for the
+    /// fifty-move counter of the position.
+    pub fn probe_root(&self, board: &mut Board) -> Option<RootProbe> {
+        if !self.covers(board) {
+            return None;
+        }
+        let clock = board.halfmove_clock as i32;
+        let mut best: Option<(i32, RootProbe)> = None;
+        for mv in board.legal_moves() {
+            board.make_move(mv);
+            let dtz = if board.halfmove_clock == 0 {
+                self.search(board, false)
+                    .map(|(wdl, _)| (-wdl).dtz_before_zeroing())
+            } else if board.is_repetition() || board.halfmove_clock >= 100 {
+                Some(0)
+            } else {
+                self.dtz(board).map(|dtz| -dtz - dtz.signum())
+            };
+            let mated = board.in_check(board.turn) && board.legal_moves().is_empty();
+            board.unmake_move();
+            let mut dtz = dtz?;
+            if mated {
+                dtz = 1;
+            }
+            // Wins within the fifty moves first and quickest, losses as slow as possible
+            let wdl = match dtz {
+                dtz if dtz > 0 && dtz + clock <= 100 => Wdl::Win,
+                dtz if dtz > 0 => Wdl::CursedWin,
+                dtz if dtz < 0 && -dtz + clock <= 100 => Wdl::Loss,
+                dtz if dtz < 0 => Wdl::BlessedLoss,
+                _ => Wdl::Draw,
+            };
+            let rank = match wdl {
+                Wdl::Draw => 0,
+                _ => 1000 * wdl as i32 - dtz,
+            };
+            let probe = RootProbe {
+                best_move: mv,
+                wdl,
+                dtz,
+            };
+            if best.is_none_or(|(best_rank, _)| rank > best_rank) {
+                best = Some((rank, probe));
+            }
+        }
+        best.map(|(_, probe)| probe)
+    }
+
+    /// Probes the WDL table, looking at the captures (and pawn moves with `zeroing`) as the
+    /// table may store any value where one of them is best.
+    fn search(&self, board: &mut Board, zeroing: bool) -> Option<(Wdl, State)> {
+        let moves = board.legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for &mv in &moves {
+            let capture = board.captured_by(mv).is_some();
+            let pawn = board
+                .piece_at(mv.from)
+                .is_some_and(|piece| piece.kind == Kind::Pawn);
+            if !(capture || zeroing && pawn) {
+                continue;
+            }
+            searched += 1;
+            board.make_move(mv);
+            let result = self.search(board, false);
+            board.unmake_move();
+            let value = -result?.0;
+            if value > best {
+                best = value;
+                if value == Wdl::Win {
+                    return Some((value, State::ZeroingBestMove));
+                }
+            }
+        }
+        // With every move searched the table isn't needed, it could be wrong for en passant
+        let no_more_moves = searched > 0 && searched == moves.len();
+        let value = match no_more_moves {
+            true => best,
+            false => Wdl::from_value(self.probe_table(board, false, Wdl::Draw)?.0),
+        };
+        if best >= value {
+            let state = match best > Wdl::Draw || no_more_moves {
+                true => State::ZeroingBestMove,
+                false => State::Ok,
+            };
+            return Some((best, state));
+        }
+        Some((value, State::Ok))
+    }
+
+    fn dtz(&self, board: &mut Board) -> Option<i32> {
+        let (wdl, state) = self.search(board, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if state == State::ZeroingBestMove {
+            return Some(wdl.dtz_before_zeroing());
+        }
+        let (dtz, state) = self.probe_table(board, true, wdl)?;
+        if state != State::ChangeStm {
+            let cursed = matches!(wdl, Wdl::BlessedLoss | Wdl::CursedWin);
+            return Some((dtz + 100 * cursed as i32) * (wdl as i32).signum());
+        }
+        // The table holds the other side to move: the best move is found one ply deeper
+        let mut min_dtz = i32::MAX;
+        for mv in board.legal_moves() {
+            let zeroing = board.captured_by(mv).is_some()
+                || board
+                    .piece_at(mv.from)
+                    .is_some_and(|piece| piece.kind == Kind::Pawn);
+            board.make_move(mv);
+            let dtz = match zeroing {
+                true => self
+                    .search(board, false)
+                    .map(|(wdl, _)| -wdl.dtz_before_zeroing()),
+                false => self.dtz(board).map(|dtz| -dtz),
+            };
+            let mates = board.in_check(board.turn) && board.legal_moves().is_empty();
+            board.unmake_move();
+            let mut dtz = dtz?;
+            if dtz == 1 && mates {
+                min_dtz = 1;
+            }
+            if !zeroing {
+                dtz += dtz.signum();
+            }
+            if dtz < min_dtz && dtz.signum() == (wdl as i32).signum() {
+                min_dtz = dtz;
+            }
+        }
+        // Without legal moves the side to move is mated
+        Some(match min_dtz {
+            i32::MAX => -1,
+            dtz => dtz,
+        })
+    }
+
+    /// Value stored for the position: a WDL value, or a DTZ one for the result `wdl`.
+    fn probe_table(&self, board: &Board, dtz: bool, wdl: Wdl) -> Option<(i32, State)> {
+        if board.pieces().count() == 2 {
+            return Some((0, State::Ok));
+        }
+        let name = Table::name(board);
+        let table = self.tables.get(&name)?;
+        let file = table.file(dtz)?;
+        table.probe(board, file, &name, wdl)
+    }
+}
+
+/// Index tables shared by all the probes.
+struct Indexing {
+    /// Pawn squares a2-h7 by how many squares remain for the other pawns when leading.
+    map_pawns: [usize; 64],
+    /// Squares below the a1-h8 diagonal, 0 to 27.
+    map_b1h1h7: [usize; 64],
+    /// Squares of the a1-d1-d4 triangle, 0 to 9, the diagonal last.
+    map_a1d1d4: [usize; 64],
+    /// The 462 placements of two kings, the first in the a1-d1-d4 triangle.
+    map_kk: [[usize; 64]; 10],
+    /// Ways to choose k of n squares, by k and n.
+    binomial: [[u64; 64]; MAX_PIECES],
+    lead_pawn_idx: [[u64; 64]; MAX_PIECES],
+    lead_pawns_size: [[u64; 4]; MAX_PIECES],
+}
+
+impl Indexing {
+    fn get() -> &'static Indexing {
+        static INDEXING: OnceLock<Box<Indexing>> = OnceLock::new();
+        INDEXING.get_or_init(|| {
+            let mut indexing = Box::new(Indexing {
+                map_pawns: [0; 64],
+                map_b1h1h7: [0; 64],
+                map_a1d1d4: [usize::MAX; 64],
+                map_kk: [[0; 64]; 10],
+                binomial: [[0; 64]; MAX_PIECES],
+                lead_pawn_idx: [[0; 64]; MAX_PIECES],
+                lead_pawns_size: [[0; 4]; MAX_PIECES],
+            });
+            let mut code = 0;
+            for square in 0..64 {
+                if off_diagonal(square) < 0 {
+                    indexing.map_b1h1h7[square] = code;
+                    code += 1;
+                }
+            }
+
+            let mut diagonal = vec![];
+            code = 0;
+            for square in 0..=27 {
+                if off_diagonal(square) < 0 && square % 8 <= 3 {
+                    indexing.map_a1d1d4[square] = code;
+                    code += 1;
+                } else if off_diagonal(square) == 0 && square % 8 <= 3 {
+                    diagonal.push(square);
+                }
+            }
+            for square in diagonal {
+                indexing.map_a1d1d4[square] = code;
+                code += 1;
+            }
+
+            // With the first king on the diagonal the second stays on or below it
+            let mut both_on_diagonal = vec![];
+            code = 0;
+            for idx in 0..10 {
+                for first in 0..=27 {
+                    if indexing.map_a1d1d4[first] != idx {
+                        continue;
+                    }
+                    for second in 0..64 {
+                        if distance(first, second) <= 1
+                            || (off_diagonal(first) == 0 && off_diagonal(second) > 0)
+                        {
+                            continue;
+                        }
+                        if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                            both_on_diagonal.push((idx, second));
+                        } else {
+                            indexing.map_kk[idx][second] = code;
+                            code += 1;
+                        }
+                    }
+                }
+            }
+            for (idx, second) in both_on_diagonal {
+                indexing.map_kk[idx][second] = code;
+                code += 1;
+            }
+
+            indexing.binomial[0][0] = 1;
+            for n in 1..64 {
+                for k in 0..MAX_PIECES.min(n + 1) {
+                    let with = if k > 0 {
+                        indexing.binomial[k - 1][n - 1]
+                    } else {
+                        0
+                    };
+                    let without = if k < n {
+                        indexing.binomial[k][n - 1]
+                    } else {
+                        0
+                    };
+                    indexing.binomial[k][n] = with + without;
+                }
+            }
+
+            // The leading pawn is the one nearest to the edge, then the lowest
+            let mut available = 47;
+            for lead_count in 1..MAX_PIECES - 1 {
+                for file in 0..4 {
+                    let mut idx = 0;
+                    for rank in 1..7 {
+                        let square = file + 8 * rank;
+                        if lead_count == 1 {
+                            indexing.map_pawns[square] = available;
+                            indexing.map_pawns[square ^ 7] = available - 1;
+                            available = available.saturating_sub(2);
+                        }
+                        indexing.lead_pawn_idx[lead_count][square] = idx;
+                        idx += indexing.binomial[lead_count - 1][indexing.map_pawns[square]];
+                    }
+                    indexing.lead_pawns_size[lead_count][file] = idx;
+                }
+            }
+            indexing
+        })
+    }
+}
+
+/// Rank minus file: negative below the a1-h8 diagonal.
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+fn distance(a: usize, b: usize) -> usize {
+    (a % 8).abs_diff(b % 8).max((a / 8).abs_diff(b / 8))
+}
+
+/// Decoding information of one part of a table: a side to move and, with pawns, a file.
+#[derive(Clone, Default)]
+struct Pairs {
+    flags: u8,
+    /// The only value stored with `SINGLE_VALUE`.
+    min_sym_len: u8,
+    block_size: usize,
+    span: usize,
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    block_length_size: usize,
+    sparse_index: usize,
+    sparse_index_size: usize,
+    num_blocks: usize,
+    data: usize,
+    /// Lowest symbol of each length, left-aligned on 64 bits.
+    base64: Vec<u64>,
+    /// Values minus one each symbol expands to.
+    sym_len: Vec<u8>,
+    /// Pieces in encoding order, as white 1-6 and black 9-14 from pawn to king.
+    pieces: [u8; MAX_PIECES],
+    group_idx: [u64; MAX_PIECES + 1],
+    group_len: [usize; MAX_PIECES + 1],
+    /// Start of the DTZ value maps for wins, losses, cursed wins and blessed losses.
+    map_idx: [usize; 4],
+}
+
+/// Contents of a table file with the decoding information of its parts.
+struct TableFile {
+    bytes: Vec<u8>,
+    /// By side to move, then file of the leading pawn.
+    pairs: [[Pairs; 4]; 2],
+    sides: usize,
+    dtz: bool,
+    map: usize,
+}
+
+impl TableFile {
+    /// Errs unless the `count` bytes from `at` are in the file, checked before parsing them.
+    fn available(&self, at: usize, count: usize) -> Result<(), String> {
+        match at
+            .checked_add(count)
+            .and_then(|end| self.bytes.get(at..end))
+        {
+            Some(_) => Ok(()),
+            None => Err("Table is truncated.".to_string()),
+        }
+    }
+
+    fn u16(&self, at: usize) -> u16 {
+        u16::from_le_bytes([self.bytes[at], self.bytes[at + 1]])
+    }
+
+    fn u32(&self, at: usize) -> u32 {
+        u32::from_le_bytes(self.bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u32_be(&self, at: usize) -> u32 {
+        u32::from_be_bytes(self.bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn u64_be(&self, at: usize) -> u64 {
+        u64::from_be_bytes(self.bytes[at..at + 8].try_into().unwrap())
+    }
+
+    /// Left and right symbols a symbol expands to.
+    fn pair(&self, pairs: &Pairs, sym: usize) -> (usize, usize) {
+        let at = pairs.btree + 3 * sym;
+        let bytes = &self.bytes[at..at + 3];
+        let left = ((bytes[1] as usize & 0xf) << 8) | bytes[0] as usize;
+        let right = ((bytes[2] as usize) << 4) | (bytes[1] as usize >> 4);
+        (left, right)
+    }
+
+    /// Value stored at `idx`, found through the sparse index then the Huffman coded block.
+    fn decompress(&self, pairs: &Pairs, idx: u64) -> i32 {
+        if pairs.flags & SINGLE_VALUE != 0 {
+            return pairs.min_sym_len as i32;
+        }
+        let k = (idx / pairs.span as u64) as usize;
+        let entry = pairs.sparse_index + 6 * k;
+        let mut block = self.u32(entry) as usize;
+        let mut offset = self.u16(entry + 4) as i64;
+        offset += (idx % pairs.span as u64) as i64 - (pairs.span / 2) as i64;
+        let length = |block: usize| self.u16(pairs.block_length + 2 * block) as i64;
+        while offset < 0 {
+            block -= 1;
+            offset += length(block) + 1;
+        }
+        while offset > length(block) {
+            offset -= length(block) + 1;
+            block += 1;
+        }
+
+        let mut at = pairs.data + block * pairs.block_size;
+        let mut buffer = self.u64_be(at);
+        at += 8;
+        let mut buffer_size = 64;
+        let min = pairs.min_sym_len as usize;
+        let mut sym: u16;
+        loop {
+            let mut len = 0;
+            while buffer < pairs.base64[len] {
+                len += 1;
+            }
+            sym = ((buffer - pairs.base64[len]) >> (64 - len - min)) as u16;
+            sym = sym.wrapping_add(self.u16(pairs.lowest_sym + 2 * len));
+            if offset < pairs.sym_len[sym as usize] as i64 + 1 {
+                break;
+            }
+            offset -= pairs.sym_len[sym as usize] as i64 + 1;
+            len += min;
+            buffer <<= len;
+            buffer_size -= len;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (self.u32_be(at) as u64) << (64 - buffer_size);
+                at += 4;
+            }
+        }
+        // The symbol stands for a run of values, halved until the wanted one is reached
+        let mut sym = sym as usize;
+        while pairs.sym_len[sym] != 0 {
+            let (left, right) = self.pair(pairs, sym);
+            if offset < pairs.sym_len[left] as i64 + 1 {
+                sym = left;
+            } else {
+                offset -= pairs.sym_len[left] as i64 + 1;
+                sym = right;
+            }
+        }
+        self.pair(pairs, sym).0 as i32
+    }
+}
+
+/// A table and its material, its files read at the first probe.
+struct Table {
+    directory: PathBuf,
+    /// File name without extension, like `KRPvKR`.
+    name: String,
+    piece_count: usize,
+    has_pawns: bool,
+    /// Some side has a single piece of a kind other than the king.
+    has_unique_pieces: bool,
+    /// Pawns of the leading side, the one with fewer, then of the other.
+    pawn_count: [usize; 2],
+    /// Both sides have the same material.
+    symmetric: bool,
+    wdl: OnceLock<Option<TableFile>>,
+    dtz: OnceLock<Option<TableFile>>,
+}
+
+impl Table {
+    fn new(name: &str, directory: PathBuf) -> Option<Table> {
+        let (white, black) = name.split_once('v')?;
+        let count = |side: &str, letter: char| side.chars().filter(|&c| c == letter).count();
+        let valid =
+            |side: &str| count(side, 'K') == 1 && side.chars().all(|c| "KQRBNP".contains(c));
+        if !valid(white) || !valid(black) || white.len() + black.len() > MAX_PIECES {
+            return None;
+        }
+        let has_unique_pieces = "QRBNP"
+            .chars()
+            .any(|letter| count(white, letter) == 1 || count(black, letter) == 1);
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = match white_leads {
+            true => [white_pawns, black_pawns],
+            false => [black_pawns, white_pawns],
+        };
+        Some(Table {
+            directory,
+            name: name.to_string(),
+            piece_count: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count,
+            symmetric: white == black,
+            wdl: OnceLock::new(),
+            dtz: OnceLock::new(),
+        })
+    }
+
+    /// Name of the table of the position's material, white first, like `KRPvKR`.
+    fn name(board: &Board) -> String {
+        format!(
+            "{}v{}",
+            Table::side_name(board, Color::White),
+            Table::side_name(board, Color::Black)
+        )
+    }
+
+    /// Pieces of a side as in table names, like `KRP`.
+    fn side_name(board: &Board, color: Color) -> String {
+        let mut name = String::new();
+        for (kind, letter) in NAME_ORDER {
+            let count = board
+                .pieces()
+                .filter(|(_, piece)| piece.kind == kind && piece.color == color)
+                .count();
+            name.extend(std::iter::repeat_n(letter, count));
+        }
+        name
+    }
+
+    fn file(&self, dtz: bool) -> Option<&TableFile> {
+        let (cell, extension) = match dtz {
+            true => (&self.dtz, "rtbz"),
+            false => (&self.wdl, "rtbw"),
+        };
+        cell.get_or_init(|| {
+            let path = self.directory.join(format!("{}.{extension}", self.name));
+            let bytes = std::fs::read(&path).ok()?;
+            match self.parse(bytes, dtz) {
+                Ok(file) => Some(file),
+                Err(error) => {
+                    eprintln!("{}: {error}", path.display());
+                    None
+                }
+            }
+        })
+        .as_ref()
+    }
+
+    fn parse(&self, bytes: Vec<u8>, dtz: bool) -> Result<TableFile, String> {
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.len() < 6 || bytes[..4] != magic {
+            return Err("Not a Syzygy table.".to_string());
+        }
+        if (bytes[4] & 2 != 0) != self.has_pawns {
+            return Err("Pawns don't match the table name.".to_string());
+        }
+        let mut file = TableFile {
+            bytes,
+            pairs: Default::default(),
+            sides: if dtz || self.symmetric { 1 } else { 2 },
+            dtz,
+            map: 0,
+        };
+        let sides = file.sides;
+        let files = self.files();
+        let mut at = self.set_pieces(&mut file)?;
+        for f in 0..files {
+            for side in 0..sides {
+                at = Table::set_sizes(&mut file, side, f, at)?;
+            }
+        }
+        if dtz {
+            at = Table::set_dtz_map(&mut file, files, at)?;
+        }
+        for f in 0..files {
+            for side in 0..sides {
+                let pairs = &mut file.pairs[side][f];
+                pairs.sparse_index = at;
+                at += 6 * pairs.sparse_index_size;
+            }
+        }
+        for f in 0..files {
+            for side in 0..sides {
+                let pairs = &mut file.pairs[side][f];
+                pairs.block_length = at;
+                at += 2 * pairs.block_length_size;
+            }
+        }
+        for f in 0..files {
+            for side in 0..sides {
+                at = (at + 0x3f) & !0x3f;
+                let pairs = &mut file.pairs[side][f];
+                pairs.data = at;
+                at += pairs.num_blocks * pairs.block_size;
+            }
+        }
+        if at > file.bytes.len() {
+            return Err("Table is truncated.".to_string());
+        }
+        Ok(file)
+    }
+
+    /// Parts of the tables for each side to move, by file of the leading pawn.
+    fn files(&self) -> usize {
+        if self.has_pawns {
+            4
+        } else {
+            1
+        }
+    }
+
+    /// Reads the order of the pieces of each part, returns where their sizes start.
+    fn set_pieces(&self, file: &mut TableFile) -> Result<usize, String> {
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut at = 5;
+        for f in 0..self.files() {
+            file.available(at, 1 + both_pawns as usize + self.piece_count)?;
+            let first = file.bytes[at];
+            let second = if both_pawns { file.bytes[at + 1] } else { 0xff };
+            let order = [[first & 0xf, second & 0xf], [first >> 4, second >> 4]];
+            at += 1 + both_pawns as usize;
+            for k in 0..self.piece_count {
+                let byte = file.bytes[at];
+                for side in 0..file.sides {
+                    file.pairs[side][f].pieces[k] = if side == 1 { byte >> 4 } else { byte & 0xf };
+                }
+                at += 1;
+            }
+            for (side, order) in order.iter().enumerate().take(file.sides) {
+                self.set_groups(&mut file.pairs[side][f], order, f);
+            }
+        }
+        Ok(at + (at & 1))
+    }
+
+    /// Splits the pieces into groups encoded together and sizes each group's index.
+    fn set_groups(&self, pairs: &mut Pairs, order: &[u8; 2], f: usize) {
+        let indexing = Indexing::get();
+        let mut n = 0;
+        let mut first_len: i32 = match (self.has_pawns, self.has_unique_pieces) {
+            (true, _) => 0,
+            (false, true) => 3,
+            (false, false) => 2,
+        };
+        pairs.group_len[0] = 1;
+        for i in 1..self.piece_count {
+            first_len -= 1;
+            if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+                pairs.group_len[n] += 1;
+            } else {
+                n += 1;
+                pairs.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        pairs.group_len[n] = 0;
+
+        let both_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free_squares =
+            64 - pairs.group_len[0] - if both_pawns { pairs.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                pairs.group_idx[0] = idx;
+                idx *= match (self.has_pawns, self.has_unique_pieces) {
+                    (true, _) => indexing.lead_pawns_size[pairs.group_len[0]][f],
+                    (false, true) => 31332,
+                    (false, false) => 462,
+                };
+            } else if k == order[1] as usize {
+                pairs.group_idx[1] = idx;
+                idx *= indexing.binomial[pairs.group_len[1]][48 - pairs.group_len[0]];
+            } else {
+                pairs.group_idx[next] = idx;
+                idx *= indexing.binomial[pairs.group_len[next]][free_squares];
+                free_squares -= pairs.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        pairs.group_idx[n] = idx;
+    }
+
+    /// Reads the sizes and Huffman code of a part, returns where the next part starts.
+    fn set_sizes(
+        file: &mut TableFile,
+        side: usize,
+        f: usize,
+        mut at: usize,
+    ) -> Result<usize, String> {
+        file.available(at, 2)?;
+        let flags = file.bytes[at];
+        let mut pairs = std::mem::take(&mut file.pairs[side][f]);
+        pairs.flags = flags;
+        at += 1;
+        if flags & SINGLE_VALUE != 0 {
+            pairs.min_sym_len = file.bytes[at];
+            file.pairs[side][f] = pairs;
+            return Ok(at + 1);
+        }
+        file.available(at, 9)?;
+        let groups = pairs.group_len.iter().position(|&len| len == 0).unwrap();
+        let size = pairs.group_idx[groups];
+        let (block_bits, span_bits) = (file.bytes[at], file.bytes[at + 1]);
+        let max_sym_len = file.bytes[at + 7] as usize;
+        pairs.min_sym_len = file.bytes[at + 8];
+        let min = pairs.min_sym_len as usize;
+        if block_bits >= 32 || span_bits >= 32 || max_sym_len < min || max_sym_len >= 64 {
+            return Err("Table has bad block or symbol sizes.".to_string());
+        }
+        pairs.block_size = 1 << block_bits;
+        pairs.span = 1 << span_bits;
+        pairs.sparse_index_size = size.div_ceil(pairs.span as u64) as usize;
+        let padding = file.bytes[at + 2] as usize;
+        pairs.num_blocks = file.u32(at + 3) as usize;
+        pairs.block_length_size = pairs.num_blocks + padding;
+        at += 9;
+        pairs.lowest_sym = at;
+
+        // Canonical Huffman code: longer symbols have lower values
+        let lengths = max_sym_len - min + 1;
+        file.available(at, 2 * lengths + 2)?;
+        let lowest = |i: usize| file.u16(pairs.lowest_sym + 2 * i) as u64;
+        pairs.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            pairs.base64[i] = (pairs.base64[i + 1] + lowest(i)).wrapping_sub(lowest(i + 1)) / 2;
+        }
+        for (i, base) in pairs.base64.iter_mut().enumerate() {
+            *base = base.checked_shl((64 - i - min) as u32).unwrap_or(0);
+        }
+        at += 2 * lengths;
+
+        let symbols = file.u16(at) as usize;
+        at += 2;
+        pairs.btree = at;
+        file.available(at, 3 * symbols)?;
+        for sym in 0..symbols {
+            let (left, right) = file.pair(&pairs, sym);
+            if right != 0xfff && (left >= symbols || right >= symbols) {
+                return Err("Table has a symbol made of unknown ones.".to_string());
+            }
+        }
+        pairs.sym_len = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                pairs.sym_len[sym] = Table::set_sym_len(file, &mut pairs, sym, &mut visited);
+            }
+        }
+        file.pairs[side][f] = pairs;
+        Ok(at + 3 * symbols + (symbols & 1))
+    }
+
+    /// Number of values minus one a symbol expands to through its pairs.
+    fn set_sym_len(file: &TableFile, pairs: &mut Pairs, sym: usize, visited: &mut [bool]) -> u8 {
+        visited[sym] = true;
+        let (left, right) = file.pair(pairs, sym);
+        if right == 0xfff {
+            return 0;
+        }
+        if !visited[left] {
+            pairs.sym_len[left] = Table::set_sym_len(file, pairs, left, visited);
+        }
+        if !visited[right] {
+            pairs.sym_len[right] = Table::set_sym_len(file, pairs, right, visited);
+        }
+        pairs.sym_len[left]
+            .wrapping_add(pairs.sym_len[right])
+            .wrapping_add(1)
+    }
+
+    /// Finds the maps turning the stored DTZ values, sorted by frequency, back to distances.
+    fn set_dtz_map(file: &mut TableFile, files: usize, mut at: usize) -> Result<usize, String> {
+        file.map = at;
+        for f in 0..files {
+            let flags = file.pairs[0][f].flags;
+            if flags & MAPPED == 0 {
+                continue;
+            }
+            for i in 0..4 {
+                if flags & WIDE != 0 {
+                    at += at & 1;
+                    file.available(at, 2)?;
+                    file.pairs[0][f].map_idx[i] = (at - file.map) / 2 + 1;
+                    at += 2 * file.u16(at) as usize + 2;
+                } else {
+                    file.available(at, 1)?;
+                    file.pairs[0][f].map_idx[i] = at - file.map + 1;
+                    at += file.bytes[at] as usize + 1;
+                }
+            }
+        }
+        Ok(at + (at & 1))
+    }
+
+    /// Looks the position up, `name` being its material with white first.
+    fn probe(&self, board: &Board, file: &TableFile, name: &str, wdl: Wdl) -> Option<(i32, State)> {
+        let Some((side, tb_file, idx)) = self.locate(board, file, name) else {
+            return Some((0, State::ChangeStm));
+        };
+        let value = file.decompress(&file.pairs[side][tb_file], idx);
+        match file.dtz {
+            false => Some((value - 2, State::Ok)),
+            true => Some((Table::map_dtz(file, tb_file, value, wdl), State::Ok)),
+        }
+    }
+
+    /// Side to move and file of the part holding the position, and its index there. `None`
+    /// when it's a DTZ table of the other side to move.
+    fn locate(&self, board: &Board, file: &TableFile, name: &str) -> Option<(usize, usize, u64)> {
+        let indexing = Indexing::get();
+        // Tables are made with white as the stronger side and, when both sides have the
+        // same material, white to move: other positions are looked up with colors swapped
+        let black_to_move = board.turn == Color::Black;
+        let flip = (self.symmetric && black_to_move) || name != self.name;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = (flip != black_to_move) as usize;
+
+        let code = |kind: Kind, color: Color| -> u8 {
+            let kind = match kind {
+                Kind::Pawn => 1,
+                Kind::Knight => 2,
+                Kind::Bishop => 3,
+                Kind::Rook => 4,
+                Kind::Queen => 5,
+                Kind::King => 6,
+            };
+            kind + 8 * (color == Color::Black) as u8
+        };
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut lead_count = 0;
+        let mut tb_file = 0;
+        let mut lead_color = None;
+        if self.has_pawns {
+            let lead = file.pairs[0][0].pieces[0] ^ flip_color;
+            let color = if lead & 8 != 0 {
+                Color::Black
+            } else {
+                Color::White
+            };
+            lead_color = Some(color);
+            for (index, piece) in board.pieces() {
+                if piece.kind == Kind::Pawn && piece.color == color {
+                    squares[size] = index as usize ^ flip_squares;
+                    size += 1;
+                }
+            }
+            lead_count = size;
+            let mut leading = 0;
+            for i in 1..lead_count {
+                if indexing.map_pawns[squares[i]] > indexing.map_pawns[squares[leading]] {
+                    leading = i;
+                }
+            }
+            squares.swap(0, leading);
+            let column = squares[0] % 8;
+            tb_file = column.min(7 - column);
+        }
+
+        if file.dtz {
+            let flags = file.pairs[0][tb_file].flags;
+            if (flags & STM) as usize != stm && (self.has_pawns || !self.symmetric) {
+                return None;
+            }
+        }
+
+        for (index, piece) in board.pieces() {
+            if piece.kind == Kind::Pawn && Some(piece.color) == lead_color {
+                continue;
+            }
+            squares[size] = index as usize ^ flip_squares;
+            pieces[size] = code(piece.kind, piece.color) ^ flip_color;
+            size += 1;
+        }
+        let side = stm % file.sides;
+        let pairs = &file.pairs[side][tb_file];
+
+        // Same piece order as the table
+        for i in lead_count..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if pairs.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // The leading piece goes to the a-d files
+        if squares[0] % 8 > 3 {
+            for square in squares.iter_mut().take(size) {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx: u64;
+        if self.has_pawns {
+            idx = indexing.lead_pawn_idx[lead_count][squares[0]];
+            squares[1..lead_count].sort_by_key(|&square| indexing.map_pawns[square]);
+            for (i, &square) in squares.iter().enumerate().take(lead_count).skip(1) {
+                idx += indexing.binomial[i][indexing.map_pawns[square]];
+            }
+        } else {
+            // Then to ranks 1-4, and below the a1-h8 diagonal
+            if squares[0] / 8 > 3 {
+                for square in squares.iter_mut().take(size) {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..pairs.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in squares.iter_mut().take(size).skip(i) {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+            idx = if self.has_unique_pieces {
+                Table::unique_index(&squares)
+            } else {
+                indexing.map_kk[indexing.map_a1d1d4[squares[0]]][squares[1]] as u64
+            };
+        }
+
+        idx *= pairs.group_idx[0];
+        let mut start = pairs.group_len[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_count[1] > 0;
+        let mut next = 1;
+        while pairs.group_len[next] != 0 {
+            let len = pairs.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let adjust = squares[..start].iter().filter(|&&s| square > s).count();
+                n += indexing.binomial[i + 1][square - adjust - 8 * remaining_pawns as usize];
+            }
+            remaining_pawns = false;
+            idx += n * pairs.group_idx[next];
+            start += len;
+            next += 1;
+        }
+        Some((side, tb_file, idx))
+    }
+
+    /// Index of the first three pieces when they all differ, kings included.
+    fn unique_index(squares: &[usize; MAX_PIECES]) -> u64 {
+        let indexing = Indexing::get();
+        let rank = |square: usize| (square / 8) as u64;
+        let adjust1 = (squares[1] > squares[0]) as u64;
+        let adjust2 = (squares[2] > squares[0]) as u64 + (squares[2] > squares[1]) as u64;
+        let (second, third) = (squares[1] as u64, squares[2] as u64);
+        if off_diagonal(squares[0]) != 0 {
+            (indexing.map_a1d1d4[squares[0]] as u64 * 63 + (second - adjust1)) * 62 + third
+                - adjust2
+        } else if off_diagonal(squares[1]) != 0 {
+            (6 * 63 + rank(squares[0]) * 28 + indexing.map_b1h1h7[squares[1]] as u64) * 62 + third
+                - adjust2
+        } else if off_diagonal(squares[2]) != 0 {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + rank(squares[0]) * 7 * 28
+                + (rank(squares[1]) - adjust1) * 28
+                + indexing.map_b1h1h7[squares[2]] as u64
+        } else {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + 4 * 7 * 28
+                + rank(squares[0]) * 7 * 6
+                + (rank(squares[1]) - adjust1) * 6
+                + (rank(squares[2]) - adjust2)
+        }
+    }
+
+    /// Distance in plies from a stored DTZ value for the result `wdl`.
+    fn map_dtz(file: &TableFile, tb_file: usize, value: i32, wdl: Wdl) -> i32 {
+        let pairs = &file.pairs[0][tb_file];
+        let flags = pairs.flags;
+        let mut value = value as usize;
+        if flags & MAPPED != 0 {
+            let map = match wdl {
+                Wdl::Win => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss | Wdl::Draw => 3,
+            };
+            let idx = pairs.map_idx[map];
+            value = match flags & WIDE != 0 {
+                true => file.u16(file.map + 2 * (idx + value)) as usize,
+                false => file.bytes[file.map + idx + value] as usize,
+            };
+        }
+        // Stored in moves unless told otherwise
+        let plies = match wdl {
+            Wdl::Win => flags & WIN_PLIES != 0,
+            Wdl::Loss => flags & LOSS_PLIES != 0,
+            _ => false,
+        };
+        let value = value as i32;
+        match plies {
+            true => value + 1,
+            false => 2 * value + 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endgames::{Dtm, Endgames, Material};
+
+    /// Tables written by `write_tables` from the retrograde analysis of `endgames`.
+    const DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/syzygy");
+    /// Names of the tables of `DIRECTORY` with the pieces of their positions.
+    const TABLES: [(&str, [char; 3]); 3] = [
+        ("KQvK", ['K', 'Q', 'k']),
+        ("KRvK", ['K', 'R', 'k']),
+        ("KPvK", ['P', 'K', 'k']),
+    ];
+    /// Bytes of a block and values between two sparse index entries, as powers of two.
+    const BLOCK_BITS: u8 = 10;
+    const SPAN_BITS: u8 = 10;
+
+    fn tablebases() -> Tablebases {
+        let tablebases = Tablebases::open(DIRECTORY).unwrap();
+        assert_eq!(tablebases.max_pieces, 3);
+        tablebases
+    }
+
+    fn position(fen: &str) -> Board {
+        let mut board = Board::new(false, 8, 8);
+        board.load_fen(fen).expect(fen);
+        board
+    }
+
+    #[test]
+    fn wdl() {
+        let tablebases = tablebases();
+        let cases = [
+            ("8/8/8/4k3/8/8/8/KQ6 w - - 0 1", Wdl::Win),
+            ("8/8/8/4k3/8/8/8/KQ6 b - - 0 1", Wdl::Loss),
+            ("8/8/8/4k3/8/8/8/KR6 b - - 0 1", Wdl::Loss),
+            // The undefended piece is taken
+            ("8/8/8/4Q3/3k4/8/8/K7 b - - 0 1", Wdl::Draw),
+            ("8/8/8/4R3/3k4/8/8/K7 b - - 0 1", Wdl::Draw),
+            ("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1", Wdl::Draw),
+            ("k7/1Q6/1K6/8/8/8/8/8 b - - 0 1", Wdl::Loss),
+            // Colors swapped
+            ("K7/8/8/8/8/8/8/kq6 b - - 0 1", Wdl::Win),
+            ("K7/8/8/8/8/8/8/kr6 w - - 0 1", Wdl::Loss),
+            // The king in front of its pawn on the sixth rank wins, the rook pawn draws
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+            ("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1", Wdl::Loss),
+            ("k7/8/8/8/8/8/P7/K7 w - - 0 1", Wdl::Draw),
+            ("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1", Wdl::Loss),
+            ("8/8/8/8/3p4/3k4/8/3K4 w - - 0 1", Wdl::Loss),
+        ];
+        for (fen, wdl) in cases {
+            assert_eq!(tablebases.probe_wdl(&mut position(fen)), Some(wdl), "{fen}");
+        }
+    }
+
+    #[test]
+    fn dtz() {
+        let tablebases = tablebases();
+        let cases = [
+            ("k7/8/1K6/8/8/8/7Q/8 w - - 0 1", 1),
+            ("k7/8/1K6/8/8/8/8/7R w - - 0 1", 1),
+            ("k7/8/1K6/8/8/8/8/7R b - - 0 1", -2),
+            ("8/8/8/4k3/8/8/8/KQ6 w - - 0 1", 17),
+            ("8/8/8/4Q3/3k4/8/8/K7 b - - 0 1", 0),
+            // The longest wins: mates in 10 and 16 moves
+            ("7K/6Q1/8/8/8/3k4/8/8 w - - 0 1", 19),
+            ("7K/8/8/8/8/8/2k5/1R6 w - - 0 1", 31),
+            // The pawn runs, or moves once the king has stepped aside or come to its help
+            ("7k/8/8/P7/8/8/8/K7 w - - 0 1", 1),
+            ("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", 3),
+            ("4k3/4P3/8/4K3/8/8/8/8 w - - 0 1", 5),
+            ("k7/8/8/8/8/8/P7/K7 w - - 0 1", 0),
+        ];
+        for (fen, dtz) in cases {
+            assert_eq!(tablebases.probe_dtz(&mut position(fen)), Some(dtz), "{fen}");
+        }
+    }
+
+    #[test]
+    fn root() {
+        let tablebases = tablebases();
+        let mut board = position("k7/8/1K6/8/8/8/7Q/8 w - - 0 1");
+        let probe = tablebases.probe_root(&mut board).unwrap();
+        assert_eq!((probe.wdl, probe.dtz), (Wdl::Win, 1));
+        board.make_move(probe.best_move);
+        assert!(board.in_check(board.turn) && board.legal_moves().is_empty());
+
+        // Winning moves make progress, the rook isn't left hanging
+        let mut board = position("8/8/8/4k3/8/8/8/KR6 w - - 0 1");
+        let probe = tablebases.probe_root(&mut board).unwrap();
+        assert_eq!(probe.wdl, Wdl::Win);
+        board.make_move(probe.best_move);
+        assert_eq!(tablebases.probe_dtz(&mut board), Some(1 - probe.dtz));
+
+        // Black to move isn't in the pawn's DTZ table, it's found through white's moves
+        let mut board = position("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1");
+        let probe = tablebases.probe_root(&mut board).unwrap();
+        assert_eq!((probe.wdl, probe.dtz), (Wdl::Win, 3));
+        board.make_move(probe.best_move);
+        assert_eq!(tablebases.probe_dtz(&mut board), Some(-2));
+    }
+
+    #[test]
+    fn truncated() {
+        let table = Table::new("KPvK", DIRECTORY.into()).unwrap();
+        for dtz in [false, true] {
+            let extension = if dtz { "rtbz" } else { "rtbw" };
+            let bytes = std::fs::read(format!("{DIRECTORY}/KPvK.{extension}")).unwrap();
+            assert!(table.parse(bytes.clone(), dtz).is_ok());
+            for end in [5, 8, 40, 100, bytes.len() / 2] {
+                assert!(table.parse(bytes[..end].to_vec(), dtz).is_err(), "{end}");
+            }
+        }
+    }
+
+    /// Piece placement field of a FEN, a1 first.
+    fn placement(squares: &[Option<char>; 64]) -> String {
+        let ranks: Vec<String> = squares
+            .chunks(8)
+            .rev()
+            .map(|rank| {
+                let mut text = String::new();
+                let mut empty = 0;
+                for square in rank {
+                    match square {
+                        Some(letter) => {
+                            if empty > 0 {
+                                text.push_str(&empty.to_string());
+                                empty = 0;
+                            }
+                            text.push(*letter);
+                        }
+                        None => empty += 1,
+                    }
+                }
+                if empty > 0 {
+                    text.push_str(&empty.to_string());
+                }
+                text
+            })
+            .collect();
+        ranks.join("/")
+    }
+
+    /// Every position of the tables against the retrograde analysis of `endgames`.
+    #[test]
+    #[ignore]
+    fn against_endgames() {
+        let tablebases = tablebases();
+        let mut endgames = Endgames::new(8, 8);
+        for name in ["KQK", "KRK", "KPK"] {
+            endgames.generate(&Material::parse(name).unwrap()).unwrap();
+        }
+        for piece in ['Q', 'R', 'P', 'q', 'r', 'p'] {
+            for (king, other, enemy) in (0..64 * 64 * 64).map(|n| (n % 64, n / 64 % 64, n / 4096)) {
+                for turn in ['w', 'b'] {
+                    let mut squares = [None; 64];
+                    squares[king] = Some('K');
+                    squares[other] = Some(piece);
+                    squares[enemy] = Some('k');
+                    if squares.iter().flatten().count() < 3 {
+                        continue;
+                    }
+                    let fen = format!("{} {turn} - - 0 1", placement(&squares));
+                    let mut board = Board::new(false, 8, 8);
+                    if board.load_fen(&fen).is_err() || board.legal_moves().is_empty() {
+                        continue;
+                    }
+                    let (wdl, dtz) = match endgames.probe(&board).unwrap() {
+                        Dtm::Win(plies) => (Wdl::Win, plies as i32),
+                        Dtm::Draw => (Wdl::Draw, 0),
+                        Dtm::Loss(plies) => (Wdl::Loss, -(plies as i32)),
+                    };
+                    assert_eq!(tablebases.probe_wdl(&mut board), Some(wdl), "{fen}");
+                    // Without pawns the next capture is the mate
+                    if !"Pp".contains(piece) {
+                        assert_eq!(tablebases.probe_dtz(&mut board), Some(dtz), "{fen}");
+                    }
+                }
+            }
+        }
+    }
+
+    /// Positions of the pieces, both sides to move, that could happen in a game.
+    fn positions(pieces: [char; 3]) -> Vec<String> {
+        let mut positions = vec![];
+        let mut board = Board::new(false, 8, 8);
+        for n in 0..64 * 64 * 64 {
+            let mut squares = [None; 64];
+            for (i, &piece) in pieces.iter().enumerate() {
+                squares[n >> (6 * i) & 63] = Some(piece);
+            }
+            if squares.iter().flatten().count() < pieces.len() {
+                continue;
+            }
+            for turn in ['w', 'b'] {
+                let fen = format!("{} {turn} - - 0 1", placement(&squares));
+                if board.load_fen(&fen).is_ok() {
+                    positions.push(fen);
+                }
+            }
+        }
+        positions
+    }
+
+    /// Plies to the next capture or pawn move of white's wins with white to move. Found level
+    /// by level: a move reaches a level when black's replies all lead to lower ones.
+    fn distances(endgames: &Endgames, positions: &[String]) -> HashMap<u64, i32> {
+        let mut board = Board::new(false, 8, 8);
+        let mut pending: Vec<&String> = positions
+            .iter()
+            .filter(|fen| {
+                board.load_fen(fen).unwrap();
+                board.turn == Color::White && matches!(endgames.probe(&board), Some(Dtm::Win(_)))
+            })
+            .collect();
+        let mut known = HashMap::new();
+        let mut level = 1;
+        while !pending.is_empty() {
+            let mut reached = vec![];
+            pending.retain(|fen| {
+                board.load_fen(fen).unwrap();
+                let found = board.legal_moves().into_iter().any(|mv| {
+                    let zeroing = board.captured_by(mv).is_some()
+                        || board
+                            .piece_at(mv.from)
+                            .is_some_and(|piece| piece.kind == Kind::Pawn);
+                    board.make_move(mv);
+                    let wins = matches!(endgames.probe(&board), Some(Dtm::Loss(_)));
+                    let replies = board.legal_moves();
+                    let found = wins
+                        && (zeroing
+                            || replies.is_empty()
+                            || replies.into_iter().all(|reply| {
+                                board.make_move(reply);
+                                let distance = known.get(&board.hash).copied();
+                                board.unmake_move();
+                                distance.is_some_and(|distance| distance + 2 <= level)
+                            }));
+                    board.unmake_move();
+                    found
+                });
+                if found {
+                    reached.push(board.hash);
+                }
+                !found
+            });
+            assert!(!reached.is_empty(), "No win reached in {level} plies.");
+            known.extend(reached.into_iter().map(|hash| (hash, level)));
+            level += 2;
+        }
+        known
+    }
+
+    /// Table file with a fixed-length code and no pairs, each symbol standing for a value:
+    /// `value` gives those of the positions the file holds.
+    fn write(
+        table: &Table,
+        dtz: bool,
+        flags: u8,
+        positions: &[String],
+        value: impl Fn(&Board) -> u8,
+    ) -> Vec<u8> {
+        let sides = if dtz || table.symmetric { 1 } else { 2 };
+        let mut bytes = if dtz { DTZ_MAGIC } else { WDL_MAGIC }.to_vec();
+        bytes.push((sides == 2) as u8 | (table.has_pawns as u8) << 1);
+        // Groups in their natural order, then the pieces the same for both sides to move
+        let (_, pieces) = TABLES.iter().find(|(name, _)| *name == table.name).unwrap();
+        let mut order = vec![0];
+        for piece in pieces {
+            let code = "PNBRQK".find(piece.to_ascii_uppercase()).unwrap() as u8 + 1;
+            let code = code + 8 * piece.is_ascii_lowercase() as u8;
+            order.push(code | code << 4);
+        }
+        for _ in 0..table.files() {
+            bytes.extend(&order);
+        }
+        bytes.resize(bytes.len() + bytes.len() % 2, 0);
+
+        // The header tells the index of every position
+        let mut file = TableFile {
+            bytes: bytes.clone(),
+            pairs: Default::default(),
+            sides,
+            dtz,
+            map: 0,
+        };
+        table.set_pieces(&mut file).unwrap();
+        let mut parts: Vec<Vec<Option<u8>>> = vec![];
+        for f in 0..table.files() {
+            for side in 0..sides {
+                let pairs = &file.pairs[side][f];
+                let groups = pairs.group_len.iter().position(|&len| len == 0).unwrap();
+                parts.push(vec![None; pairs.group_idx[groups] as usize]);
+            }
+        }
+        let mut board = Board::new(false, 8, 8);
+        for fen in positions {
+            board.load_fen(fen).unwrap();
+            let Some((side, f, idx)) = table.locate(&board, &file, &table.name) else {
+                continue;
+            };
+            let value = value(&board);
+            let stored = &mut parts[f * sides + side][idx as usize];
+            assert!(stored.is_none_or(|stored| stored == value), "{fen}");
+            *stored = Some(value);
+        }
+        let parts: Vec<Vec<u8>> = parts
+            .into_iter()
+            .map(|part| part.into_iter().map(Option::unwrap_or_default).collect())
+            .collect();
+
+        let max = parts.iter().flatten().copied().max().unwrap_or(0);
+        let bits = (u8::BITS - max.leading_zeros()).max(1) as usize;
+        let per_block = (8 << BLOCK_BITS) / bits;
+        for part in &parts {
+            bytes.push(flags);
+            bytes.extend([BLOCK_BITS, SPAN_BITS, 0]);
+            bytes.extend((part.len().div_ceil(per_block) as u32).to_le_bytes());
+            // Symbols of a single length, from the lowest one
+            bytes.extend([bits as u8, bits as u8, 0, 0]);
+            bytes.extend(((1 << bits) as u16).to_le_bytes());
+            for sym in 0..1 << bits {
+                bytes.extend([sym as u8, 0xf0, 0xff]);
+            }
+        }
+        for part in &parts {
+            let span = 1 << SPAN_BITS;
+            let blocks = part.len().div_ceil(per_block);
+            for entry in 0..part.len().div_ceil(span) {
+                let middle = entry * span + span / 2;
+                let block = (middle / per_block).min(blocks - 1);
+                bytes.extend((block as u32).to_le_bytes());
+                bytes.extend(((middle - block * per_block) as u16).to_le_bytes());
+            }
+        }
+        for part in &parts {
+            for block in part.chunks(per_block) {
+                bytes.extend(((block.len() - 1) as u16).to_le_bytes());
+            }
+        }
+        for part in &parts {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            for block in part.chunks(per_block) {
+                let mut data = vec![0u8; 1 << BLOCK_BITS];
+                for (i, &value) in block.iter().enumerate() {
+                    for bit in 0..bits {
+                        if value >> (bits - 1 - bit) & 1 != 0 {
+                            let at = i * bits + bit;
+                            data[at / 8] |= 0x80 >> (at % 8);
+                        }
+                    }
+                }
+                bytes.extend(data);
+            }
+        }
+        // Decoding reads ahead of the last values
+        bytes.extend([0; 16]);
+        bytes
+    }
+
+    /// Writes the tables of `DIRECTORY`: `cargo test --release write_tables -- --ignored`.
+    /// DTZ tables hold white to move, in plies.
+    #[test]
+    #[ignore]
+    fn write_tables() {
+        let mut endgames = Endgames::new(8, 8);
+        for (name, pieces) in TABLES {
+            endgames.generate(&Material::parse(name).unwrap()).unwrap();
+            let table = Table::new(name, DIRECTORY.into()).unwrap();
+            let positions = positions(pieces);
+            let wdl = write(&table, false, 0, &positions, |board| {
+                match endgames.probe(board).unwrap() {
+                    Dtm::Win(_) => 4,
+                    Dtm::Draw => 2,
+                    Dtm::Loss(_) => 0,
+                }
+            });
+            let distances = distances(&endgames, &positions);
+            let dtz = write(&table, true, WIN_PLIES | LOSS_PLIES, &positions, |board| {
+                distances
+                    .get(&board.hash)
+                    .map_or(0, |&plies| plies as u8 - 1)
+            });
+            for (bytes, extension) in [(wdl, "rtbw"), (dtz, "rtbz")] {
+                table.parse(bytes.clone(), extension == "rtbz").unwrap();
+                std::fs::write(format!("{DIRECTORY}/{name}.{extension}"), bytes).unwrap();
+            }
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::moves::Move;
+use crate::piece::Kind;
+use crate::search::TB_BOUND;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// Entries sharing a slot, the least valuable one gets replaced.
+const BUCKET_SIZE: usize = 4;
+/// Promotion kinds by their code in a packed entry, minus one.
+const KINDS: [Kind; 6] = [
+    Kind::Pawn,
+    Kind::Bishop,
+    Kind::Knight,
+    Kind::Rook,
+    Kind::Queen,
+    Kind::King,
+];
+
+// Bit offsets of the fields of a packed entry
+const DEPTH_SHIFT: u32 = 16;
+const BOUND_SHIFT: u32 = 24;
+const AGE_SHIFT: u32 = 26;
+const OCCUPIED: u64 = 1 << 34;
+const HAS_MOVE: u64 = 1 << 35;
+const FROM_SHIFT: u32 = 36;
+const TO_SHIFT: u32 = 44;
+const PROMOTION_SHIFT: u32 = 52;
+
+/// How the stored score relates to the real one. Mate and tablebase scores are stored relative
+/// to the node instead of the root.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Bound {
+    Exact,
+    /// Failed high, the score is at least this much.
+    Lower,
+    /// Failed low, the score is at most this much.
+    Upper,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Entry {
+    pub key: u64,
+    pub best_move: Option<Move>,
+    pub score: i32,
+    pub depth: u8,
+    pub bound: Bound,
+    age: u8,
+}
+
+impl Entry {
+    /// Score as seen from a node `ply` plies away from the root.
+    pub fn score_at(&self, ply: i32) -> i32 {
+        match self.score {
+            score if score >= TB_BOUND => score - ply,
+            score if score <= -TB_BOUND => score + ply,
+            score => score,
+        }
+    }
+
+    /// The stored bound settles the node for this window.
+    pub fn cuts(&self, score: i32, alpha: i32, beta: i32) -> bool {
+        match self.bound {
+            Bound::Exact => true,
+            Bound::Lower => score >= beta,
+            Bound::Upper => score <= alpha,
+        }
+    }
+
+    /// Everything but the key in one word.
+    fn pack(&self) -> u64 {
+        let mut data = self.score as i16 as u16 as u64
+            | (self.depth as u64) << DEPTH_SHIFT
+            | (self.bound as u64) << BOUND_SHIFT
+            | (self.age as u64) << AGE_SHIFT
+            | OCCUPIED;
+        if let Some(mv) = self.best_move {
+            let promotion = mv.promotion.map_or(0, |kind| kind as u64 + 1);
+            data |= HAS_MOVE
+                | (mv.from as u64) << FROM_SHIFT
+                | (mv.to as u64) << TO_SHIFT
+                | promotion << PROMOTION_SHIFT;
+        }
+        data
+    }
+
+    fn unpack(key: u64, data: u64) -> Entry {
+        let best_move = (data & HAS_MOVE != 0).then(|| Move {
+            from: (data >> FROM_SHIFT) as u8,
+            to: (data >> TO_SHIFT) as u8,
+            promotion: match (data >> PROMOTION_SHIFT) & 7 {
+                0 => None,
+                code => Some(KINDS[code as usize - 1]),
+            },
+        });
+        Entry {
+            key,
+            best_move,
+            score: data as u16 as i16 as i32,
+            depth: (data >> DEPTH_SHIFT) as u8,
+            bound: match (data >> BOUND_SHIFT) & 3 {
+                0 => Bound::Exact,
+                1 => Bound::Lower,
+                _ => Bound::Upper,
+            },
+            age: (data >> AGE_SHIFT) as u8,
+        }
+    }
+}
+
+/// An entry packed in two words, the key stored xored with the data: when two threads write
+/// the slot at once, the mixed up words fail the key check instead of being read as an entry.
+#[derive(Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+impl Slot {
+    fn load(&self) -> Option<Entry> {
+        let data = self.data.load(Ordering::Relaxed);
+        let key = self.key.load(Ordering::Relaxed) ^ data;
+        (data & OCCUPIED != 0).then(|| Entry::unpack(key, data))
+    }
+
+    fn save(&self, entry: &Entry) {
+        let data = entry.pack();
+        self.data.store(data, Ordering::Relaxed);
+        self.key.store(entry.key ^ data, Ordering::Relaxed);
+    }
+}
+
+/// Fixed-size table of searched positions, indexed by their Zobrist hash. Shared by the
+/// search threads without locking.
+pub struct TranspositionTable {
+    buckets: Vec<[Slot; BUCKET_SIZE]>,
+    /// Bumped for each new search so entries from older ones are replaced first.
+    age: AtomicU8,
+}
+
+impl TranspositionTable {
+    /// Table taking about `megabytes` of memory.
+    pub fn new(megabytes: usize) -> Self {
+        let bucket_bytes = std::mem::size_of::<[Slot; BUCKET_SIZE]>();
+        let count = (megabytes * 1024 * 1024 / bucket_bytes).max(1);
+        TranspositionTable {
+            buckets: (0..count).map(|_| Default::default()).collect(),
+            age: AtomicU8::new(0),
+        }
+    }
+
+    /// Forgets every position, for a new game.
+    pub fn clear(&self) {
+        for slot in self.buckets.iter().flatten() {
+            slot.data.store(0, Ordering::Relaxed);
+            slot.key.store(0, Ordering::Relaxed);
+        }
+        self.age.store(0, Ordering::Relaxed);
+    }
+
+    pub fn new_search(&self) {
+        self.age.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        self.buckets[self.bucket(key)]
+            .iter()
+            .filter_map(Slot::load)
+            .find(|entry| entry.key == key)
+    }
+
+    /// Stores a search result found `ply` plies away from the root. An entry for the same
+    /// position is overwritten, otherwise the shallowest and oldest one makes room.
+    pub fn store(
+        &self,
+        key: u64,
+        best_move: Option<Move>,
+        score: i32,
+        depth: u32,
+        bound: Bound,
+        ply: i32,
+    ) {
+        let age = self.age.load(Ordering::Relaxed);
+        let slots = &self.buckets[self.bucket(key)];
+        let entries = slots.each_ref().map(Slot::load);
+        let same = entries
+            .iter()
+            .position(|entry| entry.is_some_and(|e| e.key == key));
+        let slot = same.unwrap_or_else(|| {
+            (0..BUCKET_SIZE)
+                .min_by_key(|&slot| match entries[slot] {
+                    None => i32::MIN,
+                    Some(entry) if entry.age != age => entry.depth as i32 - 256,
+                    Some(entry) => entry.depth as i32,
+                })
+                .unwrap()
+        });
+        // Keep the move of a previous search of this position if this one found none
+        let best_move = best_move.or(same.and_then(|slot| entries[slot]?.best_move));
+        let score = match score {
+            score if score >= TB_BOUND => score + ply,
+            score if score <= -TB_BOUND => score - ply,
+            score => score,
+        };
+        slots[slot].save(&Entry {
+            key,
+            best_move,
+            score,
+            depth: depth.min(u8::MAX as u32) as u8,
+            bound,
+            age,
+        });
+    }
+
+    fn bucket(&self, key: u64) -> usize {
+        ((key as u128 * self.buckets.len() as u128) >> 64) as usize
+    }
+}
@@ -0,0 +1,536 @@
+use crate::board::{Board, Variant, Wing};
+use crate::piece::{Color, Kind};
+use std::fmt;
+
+/// Reason why a FEN string can't be loaded, either malformed or a position that can't be
+/// reached in a game.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ValidationError {
+    RankCount {
+        count: usize,
+        ranks: u8,
+    },
+    RankWidth {
+        rank: String,
+        files: u8,
+    },
+    UnknownPiece {
+        letter: char,
+    },
+    UnknownTurn {
+        field: String,
+    },
+    UnknownCastling {
+        letter: char,
+    },
+    UnknownEnPassant {
+        field: String,
+    },
+    BadHalfmoveClock {
+        field: String,
+    },
+    BadFullmoveNumber {
+        field: String,
+    },
+    KingCount {
+        color: Color,
+        count: usize,
+    },
+    PawnOnBackRank {
+        square: String,
+    },
+    TooManyPawns {
+        color: Color,
+        count: usize,
+    },
+    TooManyPieces {
+        color: Color,
+        count: usize,
+    },
+    TooManyPromotions {
+        color: Color,
+        promoted: usize,
+        missing_pawns: usize,
+    },
+    SideNotToMoveInCheck {
+        color: Color,
+    },
+    TooManyCheckers {
+        count: usize,
+    },
+    ImpossibleDoubleCheck,
+    CastlingWithoutKing {
+        color: Color,
+        wing: Wing,
+    },
+    CastlingWithoutRook {
+        color: Color,
+        wing: Wing,
+    },
+    EnPassantWrongRank {
+        square: String,
+    },
+    EnPassantWithoutPawn {
+        square: String,
+    },
+    EnPassantBlocked {
+        square: String,
+    },
+    EnPassantWithHalfmoves {
+        clock: u32,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::RankCount { count, ranks } => {
+                write!(f, "placement has {count} ranks, the board has {ranks}")
+            }
+            ValidationError::RankWidth { rank, files } => {
+                write!(f, "rank {rank} doesn't fill exactly {files} files")
+            }
+            ValidationError::UnknownPiece { letter } => {
+                write!(f, "{letter} isn't the letter of a piece")
+            }
+            ValidationError::UnknownTurn { field } => {
+                write!(f, "side to move is {field} instead of w or b")
+            }
+            ValidationError::UnknownCastling { letter } => {
+                write!(f, "{letter} isn't a castling right, only K, Q, k, q or - are")
+            }
+            ValidationError::UnknownEnPassant { field } => {
+                write!(f, "en passant target {field} isn't a square of the board")
+            }
+            ValidationError::BadHalfmoveClock { field } => {
+                write!(f, "halfmove clock {field} isn't a number")
+            }
+            ValidationError::BadFullmoveNumber { field } => {
+                write!(f, "fullmove number {field} isn't a number")
+            }
+            ValidationError::KingCount { color, count } => {
+                write!(f, "{color:?} has {count} kings instead of one")
+            }
+            ValidationError::PawnOnBackRank { square } => {
+                write!(f, "pawn on {square} stands on the first or last rank")
+            }
+            ValidationError::TooManyPawns { color, count } => {
+                write!(f, "{color:?} has {count} pawns, more than there are files")
+            }
+            ValidationError::TooManyPieces { color, count } => {
+                write!(f, "{color:?} has {count} pieces, more than two full ranks")
+            }
+            ValidationError::TooManyPromotions {
+                color,
+                promoted,
+                missing_pawns,
+            } => write!(
+                f,
+                "{color:?} needs {promoted} promoted pieces but only {missing_pawns} pawns are missing"
+            ),
+            ValidationError::SideNotToMoveInCheck { color } => {
+                write!(f, "{color:?} is in check but it isn't its turn to move")
+            }
+            ValidationError::TooManyCheckers { count } => {
+                write!(f, "king is checked by {count} pieces, at most two can check")
+            }
+            ValidationError::ImpossibleDoubleCheck => {
+                write!(f, "double check without a slider, no move can discover it")
+            }
+            ValidationError::CastlingWithoutKing { color, wing } => write!(
+                f,
+                "{color:?} may castle on the {wing:?} side but its king has left its square"
+            ),
+            ValidationError::CastlingWithoutRook { color, wing } => write!(
+                f,
+                "{color:?} may castle on the {wing:?} side but its rook isn't in the corner"
+            ),
+            ValidationError::EnPassantWrongRank { square } => {
+                write!(f, "en passant square {square} isn't behind a double pawn move")
+            }
+            ValidationError::EnPassantWithoutPawn { square } => {
+                write!(f, "en passant square {square} has no pawn that just moved past it")
+            }
+            ValidationError::EnPassantBlocked { square } => {
+                write!(f, "en passant square {square} or the square the pawn left isn't empty")
+            }
+            ValidationError::EnPassantWithHalfmoves { clock } => write!(
+                f,
+                "halfmove clock is {clock} although a pawn just moved for en passant"
+            ),
+        }
+    }
+}
+
+/// Pieces of each kind a side starts with on an 8 files board.
+const START_COUNTS: [(Kind, usize); 4] = [
+    (Kind::Queen, 1),
+    (Kind::Rook, 2),
+    (Kind::Bishop, 2),
+    (Kind::Knight, 2),
+];
+
+impl Board {
+    /// Checks that the position could happen in a game, listing every problem found.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+        for color in [Color::White, Color::Black] {
+            self.validate_material(color, &mut errors);
+            self.validate_castling(color, &mut errors);
+        }
+        // Kings may be left en prise in fog-of-war
+        if self.variant != Variant::FogOfWar {
+            self.validate_checks(&mut errors);
+        }
+        self.validate_en_passant(&mut errors);
+        match errors.is_empty() {
+            true => Ok(()),
+            false => Err(errors),
+        }
+    }
+
+    fn validate_material(&self, color: Color, errors: &mut Vec<ValidationError>) {
+        let count = |kind| {
+            self.pieces()
+                .filter(|(_, piece)| piece.color == color && piece.kind == kind)
+                .count()
+        };
+
+        let kings = count(Kind::King);
+        if kings != 1 {
+            errors.push(ValidationError::KingCount {
+                color,
+                count: kings,
+            });
+        }
+        for (index, piece) in self.pieces() {
+            let (_, rank) = self.index_to_file_rank(index);
+            let back_rank = rank == 0 || rank == self.ranks - 1;
+            if piece.color == color && piece.kind == Kind::Pawn && back_rank {
+                errors.push(ValidationError::PawnOnBackRank {
+                    square: self.square_name(index),
+                });
+            }
+        }
+
+        let pawns = count(Kind::Pawn);
+        if pawns > self.files as usize {
+            errors.push(ValidationError::TooManyPawns {
+                color,
+                count: pawns,
+            });
+        }
+        let pieces = self
+            .pieces()
+            .filter(|(_, piece)| piece.color == color)
+            .count();
+        if pieces > 2 * self.files as usize {
+            errors.push(ValidationError::TooManyPieces {
+                color,
+                count: pieces,
+            });
+        }
+
+        // Pieces beyond the starting set must come from promoted pawns
+        if self.files == 8 {
+            let promoted: usize = START_COUNTS
+                .iter()
+                .map(|&(kind, start)| count(kind).saturating_sub(start))
+                .sum();
+            let missing_pawns = (self.files as usize).saturating_sub(pawns);
+            if promoted > missing_pawns {
+                errors.push(ValidationError::TooManyPromotions {
+                    color,
+                    promoted,
+                    missing_pawns,
+                });
+            }
+        }
+    }
+
+    fn validate_castling(&self, color: Color, errors: &mut Vec<ValidationError>) {
+        for wing in [Wing::King, Wing::Queen] {
+            if !self.castling.get(color, wing) {
+                continue;
+            }
+            let (king, rook) = self.castling_squares(color, wing);
+            let is = |index, kind| {
+                self.piece_at(index)
+                    .is_some_and(|piece| piece.color == color && piece.kind == kind)
+            };
+            if !is(king, Kind::King) {
+                errors.push(ValidationError::CastlingWithoutKing { color, wing });
+            }
+            if !is(rook, Kind::Rook) {
+                errors.push(ValidationError::CastlingWithoutRook { color, wing });
+            }
+        }
+    }
+
+    fn validate_checks(&self, errors: &mut Vec<ValidationError>) {
+        let waiting = self.turn.opponent();
+        if self.in_check(waiting) {
+            errors.push(ValidationError::SideNotToMoveInCheck { color: waiting });
+        }
+
+        let Some(king) = self.king_square(self.turn) else {
+            return;
+        };
+        let checkers = self.attackers(king, waiting);
+        let count = checkers.count() as usize;
+        if count > 2 {
+            errors.push(ValidationError::TooManyCheckers { count });
+        } else if count == 2 {
+            // One of the two checks was discovered, and only sliders can be discovered
+            let slider = checkers.iter().any(|index| {
+                self.piece_at(index).is_some_and(|piece| {
+                    matches!(piece.kind, Kind::Bishop | Kind::Rook | Kind::Queen)
+                })
+            });
+            if !slider {
+                errors.push(ValidationError::ImpossibleDoubleCheck);
+            }
+        }
+    }
+
+    fn validate_en_passant(&self, errors: &mut Vec<ValidationError>) {
+        let Some(square) = self.en_passant else {
+            return;
+        };
+        let name = self.square_name(square);
+        // The pawn that just moved belongs to the side not to move
+        let mover = self.turn.opponent();
+        let forward = Board::pawn_forward(mover);
+        let (_, rank) = self.index_to_file_rank(square);
+        let expected_rank = (self.pawn_start_rank(mover) as i16 + forward.1) as u8;
+        if rank != expected_rank {
+            errors.push(ValidationError::EnPassantWrongRank { square: name });
+            return;
+        }
+
+        let pawn = self.offset(square, forward);
+        let has_pawn = pawn
+            .and_then(|index| self.piece_at(index))
+            .is_some_and(|piece| piece.color == mover && piece.kind == Kind::Pawn);
+        if !has_pawn {
+            errors.push(ValidationError::EnPassantWithoutPawn {
+                square: name.clone(),
+            });
+        }
+        let left = self.offset(square, (-forward.0, -forward.1));
+        let blocked = [Some(square), left]
+            .into_iter()
+            .flatten()
+            .any(|index| self.piece_at(index).is_some());
+        if blocked {
+            errors.push(ValidationError::EnPassantBlocked { square: name });
+        }
+        if self.halfmove_clock != 0 {
+            errors.push(ValidationError::EnPassantWithHalfmoves {
+                clock: self.halfmove_clock,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(fen: &str) -> Vec<ValidationError> {
+        let mut board = Board::new(false, 8, 8);
+        board.load_fen(fen).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn valid() {
+        assert_eq!(
+            errors("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            []
+        );
+        assert_eq!(
+            errors("rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"),
+            []
+        );
+    }
+
+    #[test]
+    fn king_count() {
+        let errors = errors("k7/8/8/8/8/8/8/K6K w - - 0 1");
+        assert!(errors.contains(&ValidationError::KingCount {
+            color: Color::White,
+            count: 2
+        }));
+    }
+
+    #[test]
+    fn pawn_on_back_rank() {
+        assert_eq!(
+            errors("k6P/8/8/8/8/8/8/K7 w - - 0 1"),
+            [ValidationError::PawnOnBackRank {
+                square: "h8".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn too_many_pawns() {
+        assert_eq!(
+            errors("k7/8/8/8/8/P7/PPPPPPPP/K7 w - - 0 1"),
+            [ValidationError::TooManyPawns {
+                color: Color::White,
+                count: 9
+            }]
+        );
+    }
+
+    #[test]
+    fn too_many_pieces() {
+        let errors = errors("k7/8/8/8/8/QQ6/PPPPPPPP/RNBQKBNR w - - 0 1");
+        assert!(errors.contains(&ValidationError::TooManyPieces {
+            color: Color::White,
+            count: 18
+        }));
+    }
+
+    #[test]
+    fn too_many_promotions() {
+        assert_eq!(
+            errors("k7/8/8/8/8/8/PPPPPPP1/QQQ1K3 w - - 0 1"),
+            [ValidationError::TooManyPromotions {
+                color: Color::White,
+                promoted: 2,
+                missing_pawns: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn side_not_to_move_in_check() {
+        assert_eq!(
+            errors("k6R/8/8/8/8/8/8/K7 w - - 0 1"),
+            [ValidationError::SideNotToMoveInCheck {
+                color: Color::Black
+            }]
+        );
+    }
+
+    #[test]
+    fn too_many_checkers() {
+        assert_eq!(
+            errors("k7/8/1N6/8/8/8/8/R3K2B b - - 0 1"),
+            [ValidationError::TooManyCheckers { count: 3 }]
+        );
+    }
+
+    #[test]
+    fn impossible_double_check() {
+        assert_eq!(
+            errors("k7/1P6/1N6/8/8/8/8/K7 b - - 0 1"),
+            [ValidationError::ImpossibleDoubleCheck]
+        );
+    }
+
+    #[test]
+    fn castling_without_king() {
+        assert_eq!(
+            errors("r3k2r/8/8/8/8/8/8/R4K1R w Kkq - 0 1"),
+            [ValidationError::CastlingWithoutKing {
+                color: Color::White,
+                wing: Wing::King
+            }]
+        );
+    }
+
+    #[test]
+    fn castling_without_rook() {
+        assert_eq!(
+            errors("r3k2r/8/8/8/8/8/8/R3K3 w KQkq - 0 1"),
+            [ValidationError::CastlingWithoutRook {
+                color: Color::White,
+                wing: Wing::King
+            }]
+        );
+    }
+
+    #[test]
+    fn en_passant_wrong_rank() {
+        assert_eq!(
+            errors("4k3/8/8/8/4P3/8/8/4K3 w - e3 0 1"),
+            [ValidationError::EnPassantWrongRank {
+                square: "e3".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn en_passant_without_pawn() {
+        assert_eq!(
+            errors("4k3/8/8/8/8/8/8/4K3 w - e6 0 1"),
+            [ValidationError::EnPassantWithoutPawn {
+                square: "e6".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn en_passant_blocked() {
+        assert_eq!(
+            errors("4k3/4p3/8/4p3/8/8/8/4K3 w - e6 0 1"),
+            [ValidationError::EnPassantBlocked {
+                square: "e6".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    fn en_passant_with_halfmoves() {
+        assert_eq!(
+            errors("4k3/8/8/4p3/8/8/8/4K3 w - e6 3 1"),
+            [ValidationError::EnPassantWithHalfmoves { clock: 3 }]
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(
+            errors("4k3/8/8/8/8/8/4K3 w - - 0 1"),
+            [ValidationError::RankCount { count: 7, ranks: 8 }]
+        );
+        assert_eq!(
+            errors("4k3/8/8/8/8/8/8/4K4 w - - 0 1"),
+            [ValidationError::RankWidth {
+                rank: "4K4".to_string(),
+                files: 8
+            }]
+        );
+        assert_eq!(
+            errors("4k3/8/8/8/8/8/8/3AK3 w - - 0 1"),
+            [
+                ValidationError::UnknownPiece { letter: 'A' },
+                ValidationError::RankWidth {
+                    rank: "3AK3".to_string(),
+                    files: 8
+                }
+            ]
+        );
+        assert_eq!(
+            errors("4k3/8/8/8/8/8/8/4K3 x Kx e9 one 0.5"),
+            [
+                ValidationError::UnknownTurn {
+                    field: "x".to_string()
+                },
+                ValidationError::UnknownCastling { letter: 'x' },
+                ValidationError::UnknownEnPassant {
+                    field: "e9".to_string()
+                },
+                ValidationError::BadHalfmoveClock {
+                    field: "one".to_string()
+                },
+                ValidationError::BadFullmoveNumber {
+                    field: "0.5".to_string()
+                }
+            ]
+        );
+    }
+}
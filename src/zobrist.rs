@@ -0,0 +1,119 @@
+use crate::board::{Board, Wing, MAX_SIDE};
+use crate::piece::{Color, Kind, Piece};
+use std::sync::OnceLock;
+
+const SQUARES: usize = MAX_SIDE as usize * MAX_SIDE as usize;
+
+/// Random keys xored together to hash a position, one per feature it can have.
+pub struct Keys {
+    pieces: [[[u64; SQUARES]; 6]; 2],
+    castling: [[u64; 2]; 2],
+    en_passant: [u64; MAX_SIDE as usize],
+    black_to_move: u64,
+}
+
+impl Keys {
+    /// Keys shared by every board, always the same from one run to the next.
+    pub fn get() -> &'static Keys {
+        static KEYS: OnceLock<Box<Keys>> = OnceLock::new();
+        KEYS.get_or_init(|| {
+            // SplitMix64 with a fixed seed
+            let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+            let mut next = || {
+                state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                let mut z = state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                z ^ (z >> 31)
+            };
+            let mut keys = Box::new(Keys {
+                pieces: [[[0; SQUARES]; 6]; 2],
+                castling: [[0; 2]; 2],
+                en_passant: [0; MAX_SIDE as usize],
+                black_to_move: 0,
+            });
+            for key in keys.pieces.iter_mut().flatten().flatten() {
+                *key = next();
+            }
+            for key in keys.castling.iter_mut().flatten() {
+                *key = next();
+            }
+            for key in keys.en_passant.iter_mut() {
+                *key = next();
+            }
+            keys.black_to_move = next();
+            keys
+        })
+    }
+
+    /// Key of a piece on a square, pawns alone also make up the pawn hash.
+    pub fn piece(&self, piece: Piece, index: u8) -> u64 {
+        self.pieces[piece.color.index()][piece.kind as usize][index as usize]
+    }
+
+    pub fn castling(&self, color: Color, wing: Wing) -> u64 {
+        self.castling[color.index()][wing as usize]
+    }
+
+    pub fn en_passant(&self, file: u8) -> u64 {
+        self.en_passant[file as usize]
+    }
+
+    pub fn black_to_move(&self) -> u64 {
+        self.black_to_move
+    }
+}
+
+impl Board {
+    /// Hash of the position computed from scratch, `Board::hash` keeps it up to date.
+    pub fn compute_hash(&self) -> u64 {
+        let keys = Keys::get();
+        let mut hash = self
+            .pieces()
+            .fold(0, |hash, (index, piece)| hash ^ keys.piece(piece, index));
+        hash ^= self.castling_hash() ^ self.en_passant_hash();
+        if self.turn == Color::Black {
+            hash ^= keys.black_to_move();
+        }
+        hash
+    }
+
+    /// Hash of the pawns alone, `Board::pawn_hash` keeps it up to date.
+    pub fn compute_pawn_hash(&self) -> u64 {
+        let keys = Keys::get();
+        self.pieces()
+            .filter(|(_, piece)| piece.kind == Kind::Pawn)
+            .fold(0, |hash, (index, piece)| hash ^ keys.piece(piece, index))
+    }
+
+    pub(crate) fn castling_hash(&self) -> u64 {
+        let keys = Keys::get();
+        let mut hash = 0;
+        for color in [Color::White, Color::Black] {
+            for wing in [Wing::King, Wing::Queen] {
+                if self.castling.get(color, wing) {
+                    hash ^= keys.castling(color, wing);
+                }
+            }
+        }
+        hash
+    }
+
+    pub(crate) fn en_passant_hash(&self) -> u64 {
+        self.en_passant.map_or(0, |square| {
+            let (file, _) = self.index_to_file_rank(square);
+            Keys::get().en_passant(file)
+        })
+    }
+
+    /// The position already happened since the last capture or pawn move.
+    pub fn is_repetition(&self) -> bool {
+        self.history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|undo| undo.hash == self.hash)
+    }
+}